@@ -1,4 +1,4 @@
-use std::{mem, sync::Arc};
+use std::{mem, ops::Deref, sync::Arc};
 
 use color_eyre::eyre::Result;
 use itertools::Itertools;
@@ -16,6 +16,7 @@ pub struct App {
     target_delta_time: Duration,
 }
 
+#[allow(clippy::large_enum_variant)]
 enum AppInner {
     Created {
         window_attributes: w::window::WindowAttributes,
@@ -28,11 +29,16 @@ enum AppInner {
 }
 
 #[derive(Debug)]
-pub struct Context {
+pub struct Gpu {
     pub instance: g::Instance,
     pub adapter: g::Adapter,
     pub device: g::Device,
     pub queue: g::Queue,
+}
+
+#[derive(Debug)]
+pub struct Context {
+    pub gpu: Gpu,
     pub window: Arc<w::window::Window>,
     pub surface: g::Surface<'static>,
     pub surface_config: g::SurfaceConfiguration,
@@ -53,6 +59,10 @@ pub trait SubAppBuilder: 'static {
 
 pub trait SubApp: 'static {
     fn update(&mut self, context: &Context, time: Time) -> Result<()>;
+
+    fn window_event(&mut self, _context: &Context, _event: &w::event::WindowEvent) -> Result<()> {
+        Ok(())
+    }
 }
 
 impl App {
@@ -173,6 +183,19 @@ impl w::application::ApplicationHandler for App {
             return;
         };
 
+        // the sub-apps see the new size when they get the event
+        if let w::event::WindowEvent::Resized(size) = event {
+            context.resize(size);
+        }
+
+        for sub_app in sub_apps.iter_mut() {
+            if let Err(error) = sub_app.window_event(context, &event) {
+                error!("failed to handle window event in sub-app: {error:?}");
+                event_loop.exit();
+                return;
+            }
+        }
+
         use w::event::WindowEvent as E;
         match event {
             E::CloseRequested => event_loop.exit(),
@@ -203,20 +226,37 @@ impl w::application::ApplicationHandler for App {
     }
 }
 
-impl Context {
-    async fn new(window: w::window::Window) -> Result<Self> {
-        let window = Arc::new(window);
-
+impl Gpu {
+    /// Creates a GPU context without any window, for offscreen rendering. The fallback adapter is
+    /// usually a software rasterizer, which gives reproducible results across machines.
+    pub async fn headless(force_fallback_adapter: bool) -> Result<Self> {
         let instance = g::Instance::new(&g::InstanceDescriptor {
             backends: g::Backends::all(),
             ..Default::default()
         });
+        Self::with_instance(instance, None, force_fallback_adapter).await
+    }
 
-        let surface = instance.create_surface(window.clone())?;
+    /// Looks for an adapter like the one a window would get, without creating a device on it, to
+    /// tell whether there is a GPU at all.
+    pub async fn probe_adapter() -> Result<()> {
+        let instance = g::Instance::new(&g::InstanceDescriptor {
+            backends: g::Backends::all(),
+            ..Default::default()
+        });
+        instance.request_adapter(&Default::default()).await?;
+        Ok(())
+    }
 
+    async fn with_instance(
+        instance: g::Instance,
+        compatible_surface: Option<&g::Surface<'_>>,
+        force_fallback_adapter: bool,
+    ) -> Result<Self> {
         let adapter = instance
             .request_adapter(&g::RequestAdapterOptions {
-                compatible_surface: Some(&surface),
+                compatible_surface,
+                force_fallback_adapter,
                 ..Default::default()
             })
             .await?;
@@ -228,8 +268,46 @@ impl Context {
             })
             .await?;
 
-        let capabilities = surface.get_capabilities(&adapter);
+        Ok(Self {
+            instance,
+            adapter,
+            device,
+            queue,
+        })
+    }
+}
+
+impl Deref for Context {
+    type Target = Gpu;
+    fn deref(&self) -> &Self::Target {
+        &self.gpu
+    }
+}
+
+impl Context {
+    async fn new(window: w::window::Window) -> Result<Self> {
+        let window = Arc::new(window);
+
+        let instance = g::Instance::new(&g::InstanceDescriptor {
+            backends: g::Backends::all(),
+            ..Default::default()
+        });
+
+        let surface = instance.create_surface(window.clone())?;
+
+        let gpu = Gpu::with_instance(instance, Some(&surface), false).await?;
+
+        let capabilities = surface.get_capabilities(&gpu.adapter);
         let window_size = window.inner_size();
+        // frames have premultiplied alpha, which only shows with a transparent window or canvas
+        let alpha_mode = if capabilities
+            .alpha_modes
+            .contains(&g::CompositeAlphaMode::PreMultiplied)
+        {
+            g::CompositeAlphaMode::PreMultiplied
+        } else {
+            capabilities.alpha_modes[0]
+        };
         let surface_config = g::SurfaceConfiguration {
             usage: g::TextureUsages::RENDER_ATTACHMENT,
             format: capabilities.formats[0],
@@ -237,19 +315,27 @@ impl Context {
             height: window_size.height,
             present_mode: capabilities.present_modes[0],
             desired_maximum_frame_latency: 2,
-            alpha_mode: capabilities.alpha_modes[0],
+            alpha_mode,
             view_formats: vec![],
         };
-        surface.configure(&device, &surface_config);
+        surface.configure(&gpu.device, &surface_config);
 
         Ok(Self {
-            instance,
-            adapter,
-            device,
-            queue,
+            gpu,
             window,
             surface,
             surface_config,
         })
     }
+
+    /// Reconfigures the surface for a new window size.
+    fn resize(&mut self, size: w::dpi::PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        self.surface_config.width = size.width;
+        self.surface_config.height = size.height;
+        self.surface
+            .configure(&self.gpu.device, &self.surface_config);
+    }
 }
@@ -0,0 +1,347 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+};
+
+use clap::{Args, Parser, Subcommand};
+use color_eyre::eyre::{Result, WrapErr, ensure};
+use log::{info, warn};
+
+use crate::{
+    app::Gpu,
+    dance::{
+        Dance, Point, Transformation,
+        analysis::AttractorStatistics,
+        clock::{AnimationClock, ClockHandle},
+        cpu::CpuSimulator,
+        density::DensityRender,
+        offscreen::OffscreenTarget,
+        scene::Scene,
+        search::{Candidate, SeedSearch},
+        seeding::PointDistribution,
+        sequence::Sequence,
+        transformations::TransformationGenerator,
+    },
+    random::Rng,
+};
+
+/// Renders particles dancing on the attractors of random transformation sets, or analyzes the
+/// attractors without a window.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    #[command(flatten)]
+    pub window: WindowArgs,
+    /// Opens the window when left out.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Prints the fractal dimensions and other statistics of a generated attractor.
+    Analyze(AnalyzeArgs),
+    /// Scores many seeds by how interesting their attractors look and writes the best ones as
+    /// scene files with thumbnails.
+    Search(SearchArgs),
+}
+
+/// Options of the window, when no command is given.
+#[derive(Debug, Clone, Args)]
+pub struct WindowArgs {
+    /// A scene file to open the window on, like the ones `search` writes.
+    #[arg(long, conflicts_with = "sequence")]
+    pub scene: Option<PathBuf>,
+    /// A sequence file to play in the window, timed in seconds.
+    #[arg(long)]
+    pub sequence: Option<PathBuf>,
+    /// The generator time the animation starts at, that of the scene by default. In seconds for a
+    /// sequence.
+    #[arg(long, allow_negative_numbers = true)]
+    pub time: Option<f32>,
+    /// The generator time the animation advances by per second, negative to play it backwards.
+    /// 0.1 by default, and 1 for a sequence.
+    #[arg(long, allow_negative_numbers = true)]
+    pub speed: Option<f32>,
+    /// Starts the animation paused.
+    #[arg(long)]
+    pub paused: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct AnalyzeArgs {
+    #[command(flatten)]
+    pub transformations: TransformationArgs,
+    /// The points simulated.
+    #[arg(long, default_value_t = 100_000)]
+    pub points: usize,
+    /// The steps simulated for the points to settle on the attractor.
+    #[arg(long, default_value_t = 50)]
+    pub steps: u32,
+    /// Counts the points over this many further steps into a density histogram of `--size`
+    /// pixels and analyzes that instead of the points of the last step. Needs a GPU.
+    #[arg(long)]
+    pub density_steps: Option<u32>,
+    /// The width and height of the density histogram, in pixels.
+    #[arg(long, default_value_t = 1024)]
+    pub size: u32,
+    /// Simulates on the CPU even if there is a GPU.
+    #[arg(long)]
+    pub cpu: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct SearchArgs {
+    /// The first seed tried.
+    #[arg(long, default_value_t = 0)]
+    pub first_seed: u32,
+    /// The number of seeds tried.
+    #[arg(long, default_value_t = 100)]
+    pub n_seeds: u32,
+    /// The number of seeds kept.
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+    /// The number of transformations.
+    #[arg(long, default_value_t = 5)]
+    pub n_transformations: usize,
+    /// The time the generator is evaluated at.
+    #[arg(long, default_value_t = 0.0)]
+    pub time: f32,
+    /// The points simulated for each seed.
+    #[arg(long, default_value_t = 20_000)]
+    pub points: usize,
+    /// The steps simulated for the points to settle on the attractor.
+    #[arg(long, default_value_t = 30)]
+    pub steps: u32,
+    /// The width and height of the thumbnails, in pixels.
+    #[arg(long, default_value_t = 128)]
+    pub thumbnail_size: u32,
+    /// The directory the scenes and thumbnails are written to.
+    #[arg(long, default_value = "search")]
+    pub out: PathBuf,
+    /// Simulates on the CPU even if there is a GPU.
+    #[arg(long)]
+    pub cpu: bool,
+}
+
+/// Picks a transformation set like the window does.
+#[derive(Debug, Clone, Copy, Args)]
+pub struct TransformationArgs {
+    /// The seed of the `TransformationGenerator`.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u32,
+    /// The number of transformations.
+    #[arg(long, default_value_t = 5)]
+    pub n_transformations: usize,
+    /// The time the generator is evaluated at.
+    #[arg(long, default_value_t = 0.0)]
+    pub time: f32,
+    /// Loops the generator over this many keyframes.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub period: Option<u32>,
+}
+
+impl TransformationArgs {
+    pub fn generate(self) -> Vec<Transformation> {
+        let generator = TransformationGenerator::with_seed(
+            TransformationGenerator::default_colors(self.n_transformations),
+            self.seed,
+        );
+        match self.period {
+            Some(period) => generator.looping(period),
+            None => generator,
+        }
+        .generate(self.time)
+    }
+}
+
+impl Cli {
+    pub fn run(self) -> Result<()> {
+        match self.command {
+            None => {
+                let scene = self.window.scene.as_deref().map(Scene::read).transpose()?;
+                let sequence = self
+                    .window
+                    .sequence
+                    .as_deref()
+                    .map(Sequence::read)
+                    .transpose()?;
+                let clock = self.window.clock(scene.as_ref())?;
+                crate::run(scene, sequence, ClockHandle::new(clock))
+            }
+            Some(Command::Analyze(args)) => {
+                env_logger::init();
+                let statistics = args.analyze()?;
+                print_statistics(&statistics);
+                Ok(())
+            }
+            Some(Command::Search(args)) => {
+                env_logger::init();
+                let best = args.search()?;
+                for (rank, candidate) in best.iter().enumerate() {
+                    print_candidate(rank + 1, candidate);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl WindowArgs {
+    /// The clock to start the window with, on `scene` if there is one.
+    pub fn clock(&self, scene: Option<&Scene>) -> Result<AnimationClock> {
+        let speed = self.speed.unwrap_or(if self.sequence.is_some() {
+            Sequence::SPEED
+        } else {
+            AnimationClock::DEFAULT_SPEED
+        });
+        ensure!(speed.is_finite(), "the speed must be finite");
+        let time = self
+            .time
+            .or(scene.map(|scene| scene.time))
+            .unwrap_or_default();
+        let mut clock = AnimationClock::new(time);
+        clock.set_speed(speed);
+        clock.set_paused(self.paused);
+        Ok(clock)
+    }
+}
+
+impl AnalyzeArgs {
+    /// Simulates the points on a headless GPU, or on the CPU without one, and analyzes them.
+    pub fn analyze(&self) -> Result<AttractorStatistics> {
+        ensure!(
+            self.transformations.n_transformations > 0,
+            "a transformation set needs at least one transformation"
+        );
+        let transformations = self.transformations.generate();
+        let points = PointDistribution::Square
+            .sample(self.points, &mut Rng::with_seed(self.transformations.seed));
+
+        let Some(gpu) = headless_gpu(self.cpu) else {
+            ensure!(
+                self.density_steps.is_none(),
+                "density histograms are only counted on the GPU"
+            );
+            return Ok(AttractorStatistics::from_points(
+                &self.simulate_on_cpu(points, &transformations),
+                &transformations,
+            ));
+        };
+
+        let mut dance = Dance::new(&points, &transformations, OffscreenTarget::FORMAT, &gpu);
+        match self.density_steps {
+            Some(density_steps) => {
+                let field = dance.render_density_field(
+                    DensityRender {
+                        warmup_steps: self.steps,
+                        steps: density_steps,
+                        ..DensityRender::new(self.size, self.size)
+                    },
+                    &gpu,
+                )?;
+                Ok(AttractorStatistics::from_density(&field, &transformations))
+            }
+            None => {
+                for _ in 0..self.steps {
+                    dance.step(&gpu);
+                }
+                Ok(AttractorStatistics::from_points(
+                    &dance.read_points(&gpu)?,
+                    &transformations,
+                ))
+            }
+        }
+    }
+
+    fn simulate_on_cpu(
+        &self,
+        points: Vec<Point>,
+        transformations: &[Transformation],
+    ) -> Vec<Point> {
+        let mut simulator = CpuSimulator::new(points, transformations);
+        for _ in 0..self.steps {
+            simulator.step();
+        }
+        simulator.points().to_vec()
+    }
+}
+
+impl SearchArgs {
+    /// Runs the search, on a headless GPU or on the CPU without one, and writes a scene file and
+    /// a PPM thumbnail named after the seed for each of the best seeds to `out`.
+    pub fn search(&self) -> Result<Vec<Candidate>> {
+        let search = SeedSearch {
+            seeds: self.first_seed..self.first_seed.saturating_add(self.n_seeds),
+            top: self.top,
+            n_transformations: self.n_transformations,
+            time: self.time,
+            n_points: self.points,
+            steps: self.steps,
+            thumbnail_size: self.thumbnail_size,
+        };
+        let gpu = headless_gpu(self.cpu);
+        let best = search.run(gpu.as_ref(), |candidate| {
+            info!(
+                "seed {}: score {:.3}",
+                candidate.scene.seed, candidate.score.total
+            );
+        })?;
+
+        fs::create_dir_all(&self.out)
+            .wrap_err_with(|| format!("failed to create {}", self.out.display()))?;
+        for candidate in &best {
+            let name = format!("seed-{}", candidate.scene.seed);
+            candidate
+                .scene
+                .write(&self.out.join(&name).with_extension("json"))?;
+            let thumbnail_path = self.out.join(name).with_extension("ppm");
+            let thumbnail = File::create(&thumbnail_path)
+                .wrap_err_with(|| format!("failed to create {}", thumbnail_path.display()))?;
+            candidate.thumbnail.write_ppm(BufWriter::new(thumbnail))?;
+        }
+        Ok(best)
+    }
+}
+
+/// A headless GPU unless `cpu` asks for the CPU, falling back to it without an adapter.
+fn headless_gpu(cpu: bool) -> Option<Gpu> {
+    if cpu {
+        return None;
+    }
+    match futures::executor::block_on(Gpu::headless(false)) {
+        Ok(gpu) => Some(gpu),
+        Err(error) => {
+            warn!("simulating on the CPU, no GPU adapter: {error}");
+            None
+        }
+    }
+}
+
+fn print_candidate(rank: usize, candidate: &Candidate) {
+    let score = candidate.score;
+    println!(
+        "{rank}. seed {}: {:.3} (coverage {:.2}, dimension {:.2}, edges {:.2}, colors {:.2})",
+        candidate.scene.seed,
+        score.total,
+        score.coverage,
+        score.dimension,
+        score.edge_density,
+        score.color_variance
+    );
+}
+
+fn print_statistics(statistics: &AttractorStatistics) {
+    println!(
+        "box-counting dimension: {:.4}",
+        statistics.box_counting_dimension
+    );
+    println!(
+        "correlation dimension: {:.4}",
+        statistics.correlation_dimension
+    );
+    println!("lyapunov exponent: {:.4}", statistics.lyapunov_exponent);
+    println!("coverage: {:.4}", statistics.coverage);
+    println!("entropy: {:.4}", statistics.entropy);
+}
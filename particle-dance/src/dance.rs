@@ -1,22 +1,58 @@
-use std::{f32, iter};
+use std::{f32, mem, path::PathBuf};
 
+use background::{Background, BackgroundParameters, BackgroundPass};
+use bloom::{BloomParameters, BloomPass};
 use bytemuck::{Pod, Zeroable};
+use clock::ClockHandle;
 use color_eyre::eyre::Result;
+use contractivity::{AttractorEstimate, ContractivityBound, ContractivityReport};
 use glam::{Affine2, Mat3, Vec2, Vec4};
+use histogram::{Histogram, HistogramParameters};
 use itertools::Itertools;
-use render::Renderer;
+use log::{error, info, warn};
+use post::{PostParameters, PostProcessor};
+use render::{Camera, RenderOptions, RenderParameters, Renderer};
+use seeding::PointDistribution;
+use sequence::Sequence;
+use serde::{Deserialize, Serialize};
 use sim::Simulator;
+use snippets::Snippets;
+use still::{Downsampler, StillParameters};
+use trails::TrailsPass;
 use transformations::TransformationGenerator;
 use wgpu as g;
+use winit as w;
 
 use crate::{
-    app::{Context, SubApp, SubAppBuilder, Time},
-    data::{Buffer, WgpuMat3x3},
+    app::{Context, Gpu, SubApp, SubAppBuilder, Time},
+    data::{Buffer, GrowableBuffer, Readback, WgpuMat3x3},
+    hash, impl_wgsl_struct,
     random::Rng,
+    shader::{Composer, ShaderDir},
+    time::Duration,
 };
 
+pub mod analysis;
+pub mod background;
+pub mod bloom;
+pub mod clock;
+pub mod contractivity;
+pub mod cpu;
+pub mod density;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod fallback;
+pub mod histogram;
+pub mod offscreen;
+pub mod post;
 pub mod render;
+pub mod scene;
+pub mod search;
+pub mod seeding;
+pub mod sequence;
 pub mod sim;
+pub mod snippets;
+pub mod still;
+pub mod trails;
 pub mod transformations;
 
 #[derive(Debug, Clone, Copy, Zeroable, Pod)]
@@ -25,7 +61,9 @@ pub struct Point {
     pub pos: Vec2,
 }
 
-#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+impl_wgsl_struct!(Point { pos: Vec2 });
+
+#[derive(Debug, Clone, Copy, PartialEq, Zeroable, Pod, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Transformation {
     pub center: Vec2,
@@ -34,6 +72,13 @@ pub struct Transformation {
     pub color: Vec4,
 }
 
+impl_wgsl_struct!(Transformation {
+    center: Vec2,
+    scale: f32,
+    angle: f32,
+    color: Vec4,
+});
+
 #[derive(Debug, Clone, Copy, Zeroable, Pod)]
 #[repr(C)]
 struct ComputedTransformation {
@@ -41,6 +86,11 @@ struct ComputedTransformation {
     matrix: WgpuMat3x3,
 }
 
+impl_wgsl_struct!(ComputedTransformation {
+    transformation: Transformation,
+    matrix: WgpuMat3x3,
+});
+
 impl ComputedTransformation {
     fn new(transformation: Transformation) -> Self {
         Self {
@@ -59,103 +109,820 @@ impl ComputedTransformation {
     }
 }
 
+/// A vertex stage for passes over every pixel, see `fullscreen.wgsl`.
+const FULLSCREEN_WGSL: &str = include_str!("dance/fullscreen.wgsl");
+
+/// The modules imported by the shaders of a `Dance`, with the default snippets.
+pub fn shader_composer() -> Composer {
+    let streams = format!(
+        "const SELECTION_STREAM: u32 = {}u;\nconst RESPAWN_STREAM: u32 = {}u;\n",
+        Simulator::SELECTION_STREAM,
+        Simulator::RESPAWN_STREAM
+    );
+    Composer::new()
+        .with_module("streams", &streams)
+        .with_module("fullscreen", FULLSCREEN_WGSL)
+        .with_module("background", BackgroundPass::SOURCE)
+        .with_module("color", Snippets::DEFAULT_COLOR)
+        .with_module("variation", Snippets::DEFAULT_VARIATION)
+        .with_struct::<Point>()
+        .with_struct::<ComputedTransformation>()
+        .with_struct::<sim::Parameters>()
+        .with_struct::<RenderParameters>()
+        .with_struct::<PostParameters>()
+        .with_struct::<BloomParameters>()
+        .with_struct::<StillParameters>()
+        .with_struct::<HistogramParameters>()
+        .with_struct::<BackgroundParameters>()
+}
+
+/// The composed shaders of a `Dance`.
 #[derive(Debug)]
-pub struct DanceSubApp {
-    point_buffer: Buffer<Point>,
-    transformation_generator: TransformationGenerator,
+struct ShaderSources {
+    sim: String,
+    render: String,
+    post: String,
+    trails: String,
+    bloom: String,
+    still: String,
+    histogram: String,
+    background: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum TransformationSet {
+    Generated(TransformationGenerator),
+    Fixed(Vec<Transformation>),
+    /// Also sets the camera and render options of a `DanceSubApp` as it plays.
+    Sequence(Sequence),
+}
+
+impl TransformationSet {
+    pub fn at(&self, t: f32) -> Vec<Transformation> {
+        match self {
+            Self::Generated(generator) => generator.generate(t),
+            Self::Fixed(transformations) => transformations.clone(),
+            Self::Sequence(sequence) => sequence.at(t).transformations,
+        }
+    }
+
+    pub fn colors(&self) -> Vec<Vec4> {
+        match self {
+            Self::Generated(generator) => generator.colors(),
+            Self::Sequence(sequence) => sequence.colors(),
+            Self::Fixed(transformations) => transformations
+                .iter()
+                .map(|transformation| transformation.color)
+                .collect_vec(),
+        }
+    }
+}
+
+/// The GPU side of a dance: the point and transformation buffers along with the passes that
+/// simulate and render them, independent of where the frames end up.
+#[derive(Debug)]
+pub struct Dance {
+    point_buffer: GrowableBuffer<Point>,
     transformation_buffer: Buffer<ComputedTransformation>,
     simulator: Simulator,
     renderer: Renderer,
+    downsampler: Downsampler,
+    histogram: Histogram,
+    snippets: Snippets,
+    shader_dir: Option<ShaderDir>,
 }
 
-impl DanceSubApp {
-    pub fn new(n_points: usize, transformation_colors: Vec<Vec4>, context: &Context) -> Self {
-        let mut rng = Rng::new();
+impl Dance {
+    /// The shader files read from a shader directory, relative to `ShaderDir::SOURCE_DIR`.
+    pub const SHADER_PATHS: [&str; 12] = [
+        "hash.wgsl",
+        "dance/fullscreen.wgsl",
+        "dance/sim.wgsl",
+        "dance/render.wgsl",
+        "dance/post.wgsl",
+        "dance/trails.wgsl",
+        "dance/bloom.wgsl",
+        "dance/still.wgsl",
+        "dance/histogram.wgsl",
+        "dance/background.wgsl",
+        "dance/color.wgsl",
+        "dance/variation.wgsl",
+    ];
 
-        let points = iter::repeat_with(|| Point {
-            pos: rng.random::<Vec2>() * 2.0 - 1.0,
-        })
-        .take(n_points)
-        .collect_vec();
-        let point_buffer = Buffer::from_data(
-            &points,
-            Some("point buffer"),
-            g::BufferUsages::STORAGE | g::BufferUsages::VERTEX,
-            context,
+    pub fn new(
+        points: &[Point],
+        transformations: &[Transformation],
+        dst_format: g::TextureFormat,
+        gpu: &Gpu,
+    ) -> Self {
+        assert!(
+            !transformations.is_empty(),
+            "a transformation set needs at least one transformation"
         );
 
-        let transformation_generator = TransformationGenerator::new(transformation_colors);
+        let point_buffer = Self::create_point_buffer(points, gpu);
+        let transformation_buffer = Self::create_transformation_buffer(
+            &transformations
+                .iter()
+                .copied()
+                .map(ComputedTransformation::new)
+                .collect_vec(),
+            gpu,
+        );
+
+        let sources = Self::compose_shaders(None, &Snippets::default())
+            .expect("failed to compose the built-in shaders");
+        let simulator = Simulator::new(
+            point_buffer.range(..),
+            &transformation_buffer,
+            &sources.sim,
+            gpu,
+        );
+        let renderer = Renderer::new(
+            &transformation_buffer,
+            dst_format,
+            RenderOptions::default(),
+            &sources,
+            gpu,
+        );
+        let downsampler = Downsampler::new(&sources.still, gpu);
+        let histogram = Histogram::new(&sources.histogram, gpu);
+
+        Self {
+            point_buffer,
+            transformation_buffer,
+            simulator,
+            renderer,
+            downsampler,
+            histogram,
+            snippets: Snippets::default(),
+            shader_dir: None,
+        }
+    }
+
+    pub fn n_points(&self) -> usize {
+        self.point_buffer.len()
+    }
+
+    /// Overwrites the points, rebuilding the bind groups that refer to them if their count
+    /// changed. The point buffer is only reallocated when it grows past its capacity.
+    pub fn write_points(&mut self, points: &[Point], gpu: &Gpu) {
+        let len_changed = points.len() != self.point_buffer.len();
+        let reallocated = self.point_buffer.write(points, gpu);
+        if len_changed || reallocated {
+            self.simulator.set_points(self.point_buffer.range(..), gpu);
+        }
+    }
+
+    /// Overwrites the transformations, replacing the transformation buffer and rebuilding the
+    /// bind groups that refer to it if their count changed.
+    pub fn write_transformations(&mut self, transformations: &[Transformation], gpu: &Gpu) {
+        assert!(
+            !transformations.is_empty(),
+            "a transformation set needs at least one transformation"
+        );
 
-        let transformations = transformation_generator
-            .generate(0.0)
-            .into_iter()
+        let transformations = transformations
+            .iter()
+            .copied()
             .map(ComputedTransformation::new)
             .collect_vec();
-        let transformation_buffer = Buffer::from_data(
-            &transformations,
+
+        if transformations.len() == self.transformation_buffer.len() {
+            self.transformation_buffer.write(0, &transformations, gpu);
+        } else {
+            self.transformation_buffer = Self::create_transformation_buffer(&transformations, gpu);
+            self.simulator
+                .set_transformations(&self.transformation_buffer, gpu);
+            self.renderer
+                .set_transformations(&self.transformation_buffer, gpu);
+        }
+    }
+
+    pub fn step(&mut self, gpu: &Gpu) {
+        self.simulator.step(gpu);
+    }
+
+    pub fn render_options(&self) -> RenderOptions {
+        self.renderer.options()
+    }
+
+    pub fn set_render_options(&mut self, options: RenderOptions, gpu: &Gpu) {
+        self.renderer.set_options(options, gpu);
+    }
+
+    pub fn camera(&self) -> Camera {
+        self.renderer.camera()
+    }
+
+    /// Moves the camera, starting the trails over if it changed.
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.renderer.set_camera(camera);
+    }
+
+    /// Moves the camera keeping the trails, for gradual moves such as the crossfades of a
+    /// sequence.
+    pub fn move_camera(&mut self, camera: Camera) {
+        self.renderer.move_camera(camera);
+    }
+
+    /// Starts the trails over from the next frame, for example after a jump in the
+    /// transformations.
+    pub fn reset_trails(&mut self) {
+        self.renderer.reset_trails();
+    }
+
+    pub fn background(&self) -> &Background {
+        self.renderer.background()
+    }
+
+    /// Sets what the points are drawn over, `Background::TRANSPARENT` for images to composite
+    /// elsewhere.
+    pub fn set_background(&mut self, background: Background, gpu: &Gpu) {
+        self.renderer.set_background(background, gpu);
+    }
+
+    /// Renders the points into `dst`, which must have the format the dance was created with.
+    pub fn render(&mut self, dst: &g::Texture, gpu: &Gpu) -> Result<()> {
+        let last_frame = self.simulator.frame().wrapping_sub(1);
+        self.renderer
+            .render(self.point_buffer.range(..), last_frame, dst, gpu)
+    }
+
+    /// Number of points that escaped to infinity or far out of bounds and were respawned since the
+    /// last call. A steadily growing count means the transformations are not contractive.
+    pub async fn take_respawn_count_async(&mut self, gpu: &Gpu) -> Result<u32> {
+        let count = self.simulator.take_respawn_count(gpu).wait(gpu).await?;
+        Ok(count[0])
+    }
+
+    /// See `Dance::take_respawn_count_async`, blocking until the GPU is done.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn take_respawn_count(&mut self, gpu: &Gpu) -> Result<u32> {
+        let count = self.simulator.take_respawn_count(gpu).wait_blocking(gpu)?;
+        Ok(count[0])
+    }
+
+    /// See `Dance::take_respawn_count_async`, but the count arrives as the single element of a
+    /// readback to pick up on a later frame.
+    pub fn take_respawn_count_later(&mut self, gpu: &Gpu) -> Readback<u32> {
+        self.simulator.take_respawn_count(gpu)
+    }
+
+    /// Copies the points back to the CPU.
+    pub async fn read_points_async(&self, gpu: &Gpu) -> Result<Vec<Point>> {
+        self.point_buffer.range(..).read_to_vec_async(gpu).await
+    }
+
+    /// Copies the points back to the CPU, blocking until the GPU is done with them.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_points(&self, gpu: &Gpu) -> Result<Vec<Point>> {
+        self.point_buffer.range(..).read_to_vec(gpu)
+    }
+
+    /// Replaces the color and variation snippets, rebuilding the pipelines. The current snippets
+    /// and pipelines are kept if a shader fails to compile.
+    pub fn set_snippets(&mut self, snippets: Snippets, gpu: &Gpu) -> Result<()> {
+        let sources = Self::compose_shaders(self.shader_dir.as_ref(), &snippets)?;
+        self.set_shaders(sources, gpu)?;
+        self.snippets = snippets;
+        Ok(())
+    }
+
+    /// Loads the shaders from the files in `shader_dir`, at the paths in `SHADER_PATHS`, instead
+    /// of the copies embedded in the binary. `None` goes back to the embedded ones.
+    pub fn set_shader_dir(&mut self, mut shader_dir: Option<ShaderDir>, gpu: &Gpu) -> Result<()> {
+        if let Some(shader_dir) = &mut shader_dir {
+            shader_dir.poll_modified(&Self::SHADER_PATHS);
+        }
+        self.shader_dir = shader_dir;
+        self.reload_shaders(gpu)
+    }
+
+    /// Whether any of the files in the shader directory changed since the previous call.
+    pub fn poll_shaders_modified(&mut self) -> bool {
+        self.shader_dir
+            .as_mut()
+            .is_some_and(|shader_dir| shader_dir.poll_modified(&Self::SHADER_PATHS))
+    }
+
+    /// Rebuilds the pipelines from the shader directory, if there is one. The current pipelines
+    /// are kept if a shader fails to compile.
+    pub fn reload_shaders(&mut self, gpu: &Gpu) -> Result<()> {
+        let sources = Self::compose_shaders(self.shader_dir.as_ref(), &self.snippets)?;
+        self.set_shaders(sources, gpu)
+    }
+
+    /// Builds every pipeline before swapping any in, so that all of them are kept if one fails.
+    fn set_shaders(&mut self, sources: ShaderSources, gpu: &Gpu) -> Result<()> {
+        let sim = self.simulator.compile_shader(&sources.sim, gpu)?;
+        let renderer = self.renderer.compile_shaders(&sources, gpu)?;
+        let still = self.downsampler.compile_shader(&sources.still, gpu)?;
+        let histogram = self.histogram.compile_shader(&sources.histogram, gpu)?;
+
+        self.simulator.set_pipeline(sim);
+        self.renderer.set_pipelines(renderer);
+        self.downsampler.set_pipeline(still);
+        self.histogram.set_pipeline(histogram);
+        Ok(())
+    }
+
+    /// Composes and validates the shaders, read from `shader_dir` if there is one, with
+    /// `snippets` spliced in.
+    fn compose_shaders(
+        shader_dir: Option<&ShaderDir>,
+        snippets: &Snippets,
+    ) -> Result<ShaderSources> {
+        let read = |path: &str, embedded: &str| match shader_dir {
+            Some(shader_dir) => shader_dir.read(path),
+            None => Ok(embedded.to_owned()),
+        };
+        let color = match &snippets.color {
+            Some(color) => color.clone(),
+            None => read("dance/color.wgsl", Snippets::DEFAULT_COLOR)?,
+        };
+        let variation = match &snippets.variation {
+            Some(variation) => variation.clone(),
+            None => read("dance/variation.wgsl", Snippets::DEFAULT_VARIATION)?,
+        };
+
+        let background = read("dance/background.wgsl", BackgroundPass::SOURCE)?;
+        let composer = shader_composer()
+            .with_module("hash", &read("hash.wgsl", hash::WGSL)?)
+            .with_module(
+                "fullscreen",
+                &read("dance/fullscreen.wgsl", FULLSCREEN_WGSL)?,
+            )
+            .with_module("background", &background)
+            .with_module("color", &color)
+            .with_module("variation", &variation);
+        Ok(ShaderSources {
+            sim: composer
+                .compose_validated("sim.wgsl", &read("dance/sim.wgsl", Simulator::SOURCE)?)?,
+            render: composer
+                .compose_validated("render.wgsl", &read("dance/render.wgsl", Renderer::SOURCE)?)?,
+            post: composer.compose_validated(
+                "post.wgsl",
+                &read("dance/post.wgsl", PostProcessor::SOURCE)?,
+            )?,
+            trails: composer.compose_validated(
+                "trails.wgsl",
+                &read("dance/trails.wgsl", TrailsPass::SOURCE)?,
+            )?,
+            bloom: composer
+                .compose_validated("bloom.wgsl", &read("dance/bloom.wgsl", BloomPass::SOURCE)?)?,
+            still: composer.compose_validated(
+                "still.wgsl",
+                &read("dance/still.wgsl", Downsampler::SOURCE)?,
+            )?,
+            histogram: composer.compose_validated(
+                "histogram.wgsl",
+                &read("dance/histogram.wgsl", Histogram::SOURCE)?,
+            )?,
+            background: composer.compose_validated("background.wgsl", &background)?,
+        })
+    }
+
+    fn create_transformation_buffer(
+        transformations: &[ComputedTransformation],
+        gpu: &Gpu,
+    ) -> Buffer<ComputedTransformation> {
+        Buffer::from_data(
+            transformations,
             Some("transformation buffer"),
             g::BufferUsages::STORAGE | g::BufferUsages::COPY_DST,
-            context,
-        );
+            gpu,
+        )
+    }
 
-        let simulator = Simulator::new(&point_buffer, &transformation_buffer, context);
+    fn create_point_buffer(points: &[Point], gpu: &Gpu) -> GrowableBuffer<Point> {
+        GrowableBuffer::from_data(
+            points,
+            Some("point buffer"),
+            g::BufferUsages::STORAGE | g::BufferUsages::VERTEX,
+            gpu,
+        )
+    }
+}
 
-        let renderer = Renderer::new(
-            &transformation_buffer,
+#[derive(Debug)]
+pub struct DanceSubApp {
+    rng: Rng,
+    dance: Dance,
+    transformations: TransformationSet,
+    clock: ClockHandle,
+    contractivity_bound: Option<ContractivityBound>,
+    last_respawn_check: Duration,
+    /// The respawn count on its way back from the GPU.
+    respawn_count: Option<Readback<u32>>,
+    last_shader_poll: Duration,
+}
+
+impl DanceSubApp {
+    const MIN_POINTS: usize = 1024;
+    /// The resolution of the density mask points are reseeded from.
+    #[cfg(not(target_arch = "wasm32"))]
+    const MASK_SIZE: u32 = 256;
+    const RESPAWN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+    const SHADER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+    /// The fraction of the view an arrow key pans by.
+    const PAN_STEP: f32 = 0.1;
+    /// The zoom factor of one mouse wheel line.
+    const ZOOM_STEP: f32 = 1.1;
+    /// The speed factor of one key press.
+    const SPEED_STEP: f32 = 1.5;
+    /// The generator time Page Up and Page Down seek by, a keyframe.
+    const SEEK_STEP: f32 = 1.0;
+
+    /// With a `shader_dir`, the shaders are loaded from it instead and reloaded whenever they
+    /// change. Snippets that fail to compile are logged and replaced by the defaults.
+    pub fn new(
+        n_points: usize,
+        transformations: TransformationSet,
+        contractivity_bound: Option<ContractivityBound>,
+        render_options: RenderOptions,
+        snippets: Snippets,
+        shader_dir: Option<ShaderDir>,
+        context: &Context,
+    ) -> Self {
+        let mut rng = Rng::new();
+
+        let points = PointDistribution::Square.sample(n_points, &mut rng);
+
+        let mut dance = Dance::new(
+            &points,
+            &Self::bounded_transformations(transformations.at(0.0), contractivity_bound),
             context.surface_config.format,
             context,
         );
 
+        dance.set_render_options(render_options, context);
+        if let Some(shader_dir) = shader_dir {
+            match dance.set_shader_dir(Some(shader_dir), context) {
+                Ok(()) => info!("loaded shaders from disk"),
+                Err(error) => error!("failed to load shaders from disk: {error:#}"),
+            }
+        }
+        if snippets != Snippets::default()
+            && let Err(error) = dance.set_snippets(snippets, context)
+        {
+            error!("failed to compile the snippets, using the defaults: {error:#}");
+        }
+
         Self {
-            point_buffer,
-            transformation_generator,
-            transformation_buffer,
-            simulator,
-            renderer,
+            rng,
+            dance,
+            transformations,
+            clock: ClockHandle::default(),
+            contractivity_bound,
+            last_respawn_check: Duration::ZERO,
+            respawn_count: None,
+            last_shader_poll: Duration::ZERO,
         }
     }
+
+    pub fn n_points(&self) -> usize {
+        self.dance.n_points()
+    }
+
+    /// Scatters the existing points anew, keeping their count.
+    pub fn reseed_points(&mut self, distribution: &PointDistribution, context: &Context) {
+        self.resize_points(self.n_points(), distribution, context);
+    }
+
+    /// Scatters the points anew over a mask of where they are now.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn reseed_points_from_density(&mut self, context: &Context) -> Result<()> {
+        let points = self.dance.read_points(context)?;
+        let mask = seeding::ImageMask::from_points(&points, Self::MASK_SIZE, Self::MASK_SIZE);
+        self.reseed_points(&PointDistribution::Mask(mask), context);
+        Ok(())
+    }
+
+    /// Replaces the points with `n_points` fresh ones.
+    pub fn resize_points(
+        &mut self,
+        n_points: usize,
+        distribution: &PointDistribution,
+        context: &Context,
+    ) {
+        let points = distribution.sample(n_points, &mut self.rng);
+        self.dance.write_points(&points, context);
+        self.dance.reset_trails();
+    }
+
+    /// See `Dance::take_respawn_count`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn take_respawn_count(&mut self, context: &Context) -> Result<u32> {
+        self.dance.take_respawn_count(context)
+    }
+
+    /// Replaces the transformations with a new random generator, keeping the colors and the loop
+    /// period. A playing sequence owns the transformations, so it is left alone.
+    pub fn randomize_transformations(&mut self) {
+        let period = match &self.transformations {
+            TransformationSet::Generated(generator) => generator.period(),
+            TransformationSet::Fixed(_) => None,
+            TransformationSet::Sequence(_) => {
+                info!("not randomizing the transformations of a sequence");
+                return;
+            }
+        };
+        let generator = TransformationGenerator::new(self.transformations.colors());
+        self.transformations = TransformationSet::Generated(match period {
+            Some(period) => generator.looping(period),
+            None => generator,
+        });
+    }
+
+    /// Replaces the transformations with a fixed, user-authored set, warning if it is expansive.
+    pub fn set_transformations(&mut self, transformations: Vec<Transformation>) {
+        assert!(
+            !transformations.is_empty(),
+            "a transformation set needs at least one transformation"
+        );
+
+        let report = ContractivityReport::analyze(&transformations);
+        match report.estimate_attractor() {
+            AttractorEstimate::Bounded => {}
+            AttractorEstimate::BoundedOnAverage => warn!(
+                "transformation set is expansive (max operator norm = {}), \
+                 points will regularly escape",
+                report.max_operator_norm()
+            ),
+            AttractorEstimate::Unbounded => warn!(
+                "transformation set is expansive on average (mean log operator norm = {}), \
+                 it has no attractor",
+                report.mean_log_norm
+            ),
+        }
+
+        self.transformations = TransformationSet::Fixed(transformations);
+    }
+
+    /// The clock the transformations are animated by, to control the dance with.
+    pub fn clock(&self) -> &ClockHandle {
+        &self.clock
+    }
+
+    /// Animates the transformations by `clock` from now on.
+    pub fn set_clock(&mut self, clock: ClockHandle) {
+        self.clock = clock;
+    }
+
+    /// See `Dance::set_camera`.
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.dance.set_camera(camera);
+    }
+
+    /// Moves the camera by `offset`, in units of the visible half-width.
+    fn pan(&mut self, offset: Vec2) {
+        let camera = self.dance.camera();
+        self.set_camera(Camera {
+            center: camera.center + offset / camera.zoom,
+            ..camera
+        });
+    }
+
+    fn zoom(&mut self, factor: f32) {
+        let camera = self.dance.camera();
+        self.set_camera(Camera {
+            zoom: camera.zoom * factor,
+            ..camera
+        });
+    }
+
+    fn seek_by(&mut self, offset: f32) {
+        let mut clock = self.clock.lock();
+        let time = clock.time() + offset;
+        clock.seek(time);
+    }
+
+    fn scale_speed(&mut self, factor: f32) {
+        let mut clock = self.clock.lock();
+        let speed = clock.speed() * factor;
+        clock.set_speed(speed);
+        info!("animation speed {speed}");
+    }
+
+    /// See `Dance::set_render_options`.
+    pub fn set_render_options(&mut self, options: RenderOptions, context: &Context) {
+        self.dance.set_render_options(options, context);
+    }
+
+    /// See `Dance::set_snippets`.
+    pub fn set_snippets(&mut self, snippets: Snippets, context: &Context) -> Result<()> {
+        self.dance.set_snippets(snippets, context)
+    }
+
+    /// See `Dance::set_background`.
+    pub fn set_background(&mut self, background: Background, context: &Context) {
+        self.dance.set_background(background, context);
+    }
+
+    /// Reloads the shaders from the shader directory, logging errors.
+    fn reload_shaders(&mut self, context: &Context) {
+        match self.dance.reload_shaders(context) {
+            Ok(()) => info!("reloaded shaders from disk"),
+            Err(error) => error!("failed to reload shaders, keeping the previous ones: {error:#}"),
+        }
+    }
+
+    fn bounded_transformations(
+        mut transformations: Vec<Transformation>,
+        contractivity_bound: Option<ContractivityBound>,
+    ) -> Vec<Transformation> {
+        if let Some(bound) = contractivity_bound {
+            bound.enforce(&mut transformations);
+        }
+        transformations
+    }
 }
 
 pub struct DanceSubAppBuilder {
     pub n_points: usize,
     pub transformation_colors: Vec<Vec4>,
+    /// Seeds the transformation generator, a random seed is used without one.
+    pub transformation_seed: Option<u32>,
+    /// Loops the generator over this many keyframes, see `TransformationGenerator::looping`.
+    pub transformation_period: Option<u32>,
+    /// Animates the transformations, and can be kept to control them.
+    pub clock: ClockHandle,
+    /// Plays this instead of generating transformations, see `TransformationSet::Sequence`.
+    pub sequence: Option<Sequence>,
+    pub contractivity_bound: Option<ContractivityBound>,
+    pub render_options: RenderOptions,
+    pub snippets: Snippets,
+    pub background: Background,
+    /// Directory to load the shaders from and watch for changes, see `DanceSubApp::new`.
+    pub shader_dir: Option<PathBuf>,
+}
+
+impl DanceSubAppBuilder {
+    /// The transformations the dance plays, the sequence or else a generator.
+    pub fn transformations(&self) -> TransformationSet {
+        if let Some(sequence) = &self.sequence {
+            return TransformationSet::Sequence(sequence.clone());
+        }
+        let colors = self.transformation_colors.clone();
+        let generator = match self.transformation_seed {
+            Some(seed) => TransformationGenerator::with_seed(colors, seed),
+            None => TransformationGenerator::new(colors),
+        };
+        TransformationSet::Generated(match self.transformation_period {
+            Some(period) => generator.looping(period),
+            None => generator,
+        })
+    }
 }
 
 impl SubAppBuilder for DanceSubAppBuilder {
     fn build(self: Box<Self>, context: &Context) -> Result<Box<dyn SubApp>> {
-        Ok(Box::new(DanceSubApp::new(
+        let mut dance = DanceSubApp::new(
             self.n_points,
-            self.transformation_colors,
+            self.transformations(),
+            self.contractivity_bound,
+            self.render_options,
+            self.snippets,
+            self.shader_dir.map(ShaderDir::new),
             context,
-        )))
+        );
+        dance.set_clock(self.clock);
+        dance.set_background(self.background, context);
+        Ok(Box::new(dance))
     }
 }
 
 impl SubApp for DanceSubApp {
     fn update(&mut self, context: &Context, time: Time) -> Result<()> {
         let texture = context.surface.get_current_texture()?;
-        let texture_view = texture.texture.create_view(&g::TextureViewDescriptor {
-            label: Some("surface texture view"),
-            ..Default::default()
-        });
-        self.renderer
-            .render(&self.point_buffer, &texture_view, context)?;
+        self.dance.render(&texture.texture, context)?;
         context.window.pre_present_notify();
         texture.present();
 
-        let transformations = self
-            .transformation_generator
-            .generate(time.elapsed_f32 * 0.1)
-            .into_iter()
-            .map(ComputedTransformation::new)
-            .collect_vec();
-        context.queue.write_buffer(
-            &self.transformation_buffer,
-            0,
-            bytemuck::cast_slice(&transformations),
-        );
+        let (animation_time, simulate) = {
+            let mut clock = self.clock.lock();
+            let simulate = clock.tick(time.delta_f32);
+            (clock.time(), simulate)
+        };
+        let transformations = match &self.transformations {
+            TransformationSet::Sequence(sequence) => {
+                let state = sequence.at(animation_time);
+                self.dance.move_camera(state.camera);
+                if state.render_options != self.dance.render_options() {
+                    self.dance.set_render_options(state.render_options, context);
+                }
+                state.transformations
+            }
+            transformations => transformations.at(animation_time),
+        };
+        let transformations =
+            Self::bounded_transformations(transformations, self.contractivity_bound);
+        self.dance.write_transformations(&transformations, context);
 
-        self.simulator.step(context);
+        if simulate {
+            self.dance.step(context);
+        }
+
+        if time.elapsed - self.last_shader_poll >= Self::SHADER_POLL_INTERVAL {
+            self.last_shader_poll = time.elapsed;
+            if self.dance.poll_shaders_modified() {
+                self.reload_shaders(context);
+            }
+        }
+
+        // the count is picked up on a later frame rather than waited for
+        if let Some(readback) = &mut self.respawn_count
+            && let Some(count) = readback.try_take(context)?
+        {
+            self.respawn_count = None;
+            if count[0] != 0 {
+                warn!("{} points escaped and were respawned", count[0]);
+            }
+        }
+        if self.respawn_count.is_none()
+            && time.elapsed - self.last_respawn_check >= Self::RESPAWN_CHECK_INTERVAL
+        {
+            self.last_respawn_check = time.elapsed;
+            self.respawn_count = Some(self.dance.take_respawn_count_later(context));
+        }
+
+        Ok(())
+    }
+
+    fn window_event(&mut self, context: &Context, event: &w::event::WindowEvent) -> Result<()> {
+        use w::{event::WindowEvent as E, keyboard::Key, keyboard::NamedKey};
+
+        let event = match event {
+            E::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    w::event::MouseScrollDelta::LineDelta(_, y) => *y,
+                    w::event::MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                };
+                self.zoom(Self::ZOOM_STEP.powf(lines));
+                return Ok(());
+            }
+            E::KeyboardInput { event, .. } if event.state.is_pressed() => event,
+            _ => return Ok(()),
+        };
+        let key = match &event.logical_key {
+            Key::Named(key) => {
+                let direction = match key {
+                    NamedKey::ArrowLeft => Vec2::NEG_X,
+                    NamedKey::ArrowRight => Vec2::X,
+                    NamedKey::ArrowDown => Vec2::NEG_Y,
+                    NamedKey::ArrowUp => Vec2::Y,
+                    NamedKey::Space => {
+                        self.clock.lock().toggle_paused();
+                        return Ok(());
+                    }
+                    NamedKey::PageUp => {
+                        self.seek_by(Self::SEEK_STEP);
+                        return Ok(());
+                    }
+                    NamedKey::PageDown => {
+                        self.seek_by(-Self::SEEK_STEP);
+                        return Ok(());
+                    }
+                    _ => return Ok(()),
+                };
+                self.pan(direction * Self::PAN_STEP);
+                return Ok(());
+            }
+            Key::Character(key) => key,
+            _ => return Ok(()),
+        };
+
+        match key.as_str() {
+            "1" => self.reseed_points(&PointDistribution::Square, context),
+            "2" => self.reseed_points(&PointDistribution::Disc, context),
+            "3" => self.reseed_points(&PointDistribution::Gaussian { std_dev: 0.5 }, context),
+            // reading the points back would block, which the browser does not allow
+            #[cfg(not(target_arch = "wasm32"))]
+            "4" => self.reseed_points_from_density(context)?,
+            "+" | "=" => {
+                let limits = context.device.limits();
+                let max_points = (limits.max_storage_buffer_binding_size as u64)
+                    .min(limits.max_buffer_size) as usize
+                    / mem::size_of::<Point>();
+                let n_points = self.n_points().saturating_mul(2).min(max_points);
+                self.resize_points(n_points, &PointDistribution::Square, context);
+                info!("resized point buffer to {n_points} points");
+            }
+            "-" => {
+                let n_points = (self.n_points() / 2).max(Self::MIN_POINTS);
+                self.resize_points(n_points, &PointDistribution::Square, context);
+                info!("resized point buffer to {n_points} points");
+            }
+            "t" => self.randomize_transformations(),
+            "c" => self.set_camera(Camera::default()),
+            "." => self.clock.lock().step(1),
+            "," => self.clock.lock().step(-1),
+            "]" => self.scale_speed(Self::SPEED_STEP),
+            "[" => self.scale_speed(Self::SPEED_STEP.recip()),
+            "r" => self.clock.lock().reverse(),
+            _ => {}
+        }
 
         Ok(())
     }
@@ -0,0 +1,247 @@
+use glam::{Mat2, Mat3, UVec2, Vec2};
+use itertools::Itertools;
+
+use crate::random::Rng;
+
+use super::{ComputedTransformation, Point, Transformation, density::DensityField};
+
+/// Numbers that tell interesting attractors from degenerate ones: dust, lines, or blobs that fill
+/// the whole frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AttractorStatistics {
+    /// How the number of visited boxes grows as they shrink, from 0 for isolated points to 2 for
+    /// a filled area.
+    pub box_counting_dimension: f32,
+    /// Like `box_counting_dimension`, but weighting boxes by how often they are visited, so it is
+    /// lower for attractors that concentrate on a few spots.
+    pub correlation_dimension: f32,
+    /// The average rate at which nearby points separate per step, in nats. Negative for an
+    /// attractor that settles.
+    pub lyapunov_exponent: f32,
+    /// The fraction of cells visited at all, from 0 to 1.
+    pub coverage: f32,
+    /// The Shannon entropy of how the visits spread over the cells, as a fraction of the maximum
+    /// for the grid: 0 for a single cell and 1 for an even spread.
+    pub entropy: f32,
+}
+
+impl AttractorStatistics {
+    /// The steps simulated for `lyapunov_exponent`.
+    pub const LYAPUNOV_STEPS: u32 = 10_000;
+
+    /// Analyzes points read back from a dance, after they settled on the attractor, binned in a
+    /// square grid over their bounding box. Points that escaped to infinity are left out.
+    pub fn from_points(points: &[Point], transformations: &[Transformation]) -> Self {
+        Self::from_grid(Grid::from_points(points), transformations)
+    }
+
+    /// Analyzes the visits counted by a density render, with its pixels as the cells.
+    pub fn from_density(field: &DensityField, transformations: &[Transformation]) -> Self {
+        Self::from_grid(Grid::from_density(field), transformations)
+    }
+
+    fn from_grid(grid: Grid, transformations: &[Transformation]) -> Self {
+        let (box_counting_dimension, correlation_dimension) = grid.dimensions();
+        Self {
+            box_counting_dimension,
+            correlation_dimension,
+            lyapunov_exponent: lyapunov_exponent(
+                transformations,
+                Self::LYAPUNOV_STEPS,
+                &mut Rng::with_seed(0),
+            ),
+            coverage: grid.coverage(),
+            entropy: grid.entropy(),
+        }
+    }
+}
+
+/// Estimates the largest Lyapunov exponent of picking transformations uniformly at random, like
+/// the simulation does, by following how the maps stretch a vector over `steps` steps.
+/// Variation snippets are not taken into account.
+pub fn lyapunov_exponent(transformations: &[Transformation], steps: u32, rng: &mut Rng) -> f32 {
+    assert!(
+        !transformations.is_empty(),
+        "a transformation set needs at least one transformation"
+    );
+    let linear = transformations
+        .iter()
+        .map(|&transformation| {
+            let matrix = Mat3::from(ComputedTransformation::compute(transformation));
+            Mat2::from_cols(matrix.x_axis.truncate(), matrix.y_axis.truncate())
+        })
+        .collect_vec();
+
+    let mut v = Vec2::X;
+    let mut log_stretch = 0.0;
+    for _ in 0..steps {
+        v = linear[rng.random_range(0..linear.len() as u32) as usize] * v;
+        let length = v.length();
+        if length == 0.0 || !length.is_finite() {
+            // a singular map collapses every direction, a degenerate map blows up
+            return if length == 0.0 {
+                f32::NEG_INFINITY
+            } else {
+                f32::INFINITY
+            };
+        }
+        // renormalized every step so that the vector stays in range
+        log_stretch += f64::from(length.ln());
+        v /= length;
+    }
+    (log_stretch / f64::from(steps.max(1))) as f32
+}
+
+/// Visit counts in a grid of cells, coarsened by factors of two to count boxes of growing size.
+#[derive(Debug, Clone)]
+struct Grid {
+    width: u32,
+    height: u32,
+    counts: Vec<u64>,
+}
+
+impl Grid {
+    /// The coarsest grid fitted is this many boxes across, fewer say little about the shape.
+    const MIN_BOXES: u32 = 4;
+    /// Point sets smaller than this are taken as a single spot rather than stretched over the
+    /// grid, which would only show rounding errors.
+    const MIN_EXTENT: f32 = 1e-5;
+
+    /// A square grid over the bounding box of `points`, finer the more points there are, so that
+    /// most visited cells hold a few points.
+    fn from_points(points: &[Point]) -> Self {
+        let positions = points
+            .iter()
+            .map(|point| point.pos)
+            .filter(|pos| pos.is_finite())
+            .collect_vec();
+        let min = positions.iter().copied().fold(Vec2::INFINITY, Vec2::min);
+        let max = positions
+            .iter()
+            .copied()
+            .fold(Vec2::NEG_INFINITY, Vec2::max);
+        let extent = (max - min).max_element().max(Self::MIN_EXTENT);
+
+        let size = ((positions.len() as f32).sqrt() as u32 / 4)
+            .next_power_of_two()
+            .clamp(Self::MIN_BOXES, 1024);
+        let mut grid = Self {
+            width: size,
+            height: size,
+            counts: vec![0; size as usize * size as usize],
+        };
+        for pos in positions {
+            let cell = ((pos - min) / extent * size as f32)
+                .as_uvec2()
+                .min(UVec2::splat(size - 1));
+            grid.counts[(cell.y * size + cell.x) as usize] += 1;
+        }
+        grid
+    }
+
+    fn from_density(field: &DensityField) -> Self {
+        Self {
+            width: field.width(),
+            height: field.height(),
+            counts: field
+                .bins()
+                .iter()
+                .map(|bin| u64::from(bin.count))
+                .collect(),
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    fn coverage(&self) -> f32 {
+        let visited = self.counts.iter().filter(|&&count| count > 0).count();
+        visited as f32 / self.counts.len().max(1) as f32
+    }
+
+    fn entropy(&self) -> f32 {
+        let total = self.total() as f64;
+        if total == 0.0 || self.counts.len() < 2 {
+            return 0.0;
+        }
+        let entropy = self
+            .counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / total;
+                -p * p.ln()
+            })
+            .sum::<f64>();
+        (entropy / (self.counts.len() as f64).ln()) as f32
+    }
+
+    /// Sums boxes of 2x2 cells, the ones at an odd edge covering fewer.
+    fn coarsened(&self) -> Self {
+        let width = self.width.div_ceil(2);
+        let height = self.height.div_ceil(2);
+        let mut counts = vec![0; width as usize * height as usize];
+        for (y, row) in self.counts.chunks(self.width as usize).enumerate() {
+            for (x, &count) in row.iter().enumerate() {
+                counts[y / 2 * width as usize + x / 2] += count;
+            }
+        }
+        Self {
+            width,
+            height,
+            counts,
+        }
+    }
+
+    /// The box-counting and correlation dimensions, from the slopes of the number of visited
+    /// boxes and of the fraction of pairs of visits that share a box over the box size.
+    fn dimensions(&self) -> (f32, f32) {
+        let total = self.total() as f64;
+        if total < 2.0 {
+            return (0.0, 0.0);
+        }
+
+        let mut box_counts = vec![];
+        let mut correlation_sums = vec![];
+        let mut grid = self.clone();
+        let mut log_size = 0.0;
+        while grid.width.min(grid.height) >= Self::MIN_BOXES {
+            let visited = grid.counts.iter().filter(|&&count| count > 0).count();
+            // pairs of distinct visits, which unlike the squared probabilities does not grow
+            // with the noise of a few visits per box
+            let correlation_sum = grid
+                .counts
+                .iter()
+                .map(|&count| count as f64 * (count as f64 - 1.0))
+                .sum::<f64>()
+                / (total * (total - 1.0));
+            box_counts.push((log_size, (visited as f64).ln()));
+            if correlation_sum > 0.0 {
+                correlation_sums.push((log_size, correlation_sum.ln()));
+            }
+            grid = grid.coarsened();
+            log_size += 2.0_f64.ln();
+        }
+        (-slope(&box_counts) as f32, slope(&correlation_sums) as f32)
+    }
+}
+
+/// The least-squares slope of `y` over `x`, 0 with fewer than two points.
+fn slope(points: &[(f64, f64)]) -> f64 {
+    if points.len() < 2 {
+        return 0.0;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let covariance = points
+        .iter()
+        .map(|&(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>();
+    let variance = points
+        .iter()
+        .map(|&(x, _)| (x - mean_x).powi(2))
+        .sum::<f64>();
+    covariance / variance
+}
@@ -0,0 +1,363 @@
+use bytemuck::{Pod, Zeroable};
+use color_eyre::eyre::Result;
+use glam::{Vec2, Vec4};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use wgpu::{self as g, util::DeviceExt};
+
+use crate::{app::Gpu, data::UniformBuffer, image::Image, impl_wgsl_struct, shader};
+
+use super::render::Viewport;
+
+/// What the points are drawn over. Colors have straight alpha. Positions are in normalized
+/// device coordinates of the image, from -1 to 1 and with y up, so they do not move with the
+/// camera.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Background {
+    Solid(Vec4),
+    /// Blends from `start_color` at `start` to `end_color` at `end`, constant past either.
+    Linear {
+        start: Vec2,
+        end: Vec2,
+        start_color: Vec4,
+        end_color: Vec4,
+    },
+    /// Blends from `center_color` at `center` to `edge_color` at `radius` from it and beyond.
+    Radial {
+        center: Vec2,
+        radius: f32,
+        center_color: Vec4,
+        edge_color: Vec4,
+    },
+    /// Stretched over the whole image. Not saved to files, as the image has no path.
+    #[serde(skip)]
+    Image(Image),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::Solid(Vec4::W)
+    }
+}
+
+impl Background {
+    /// Leaves the image transparent where there are no points, for compositing it elsewhere.
+    pub const TRANSPARENT: Self = Self::Solid(Vec4::ZERO);
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(super) struct BackgroundParameters {
+    kind: u32,
+    radius: f32,
+    /// Map the texture coordinates of the target to those of the whole image.
+    uv_offset: Vec2,
+    uv_scale: Vec2,
+    /// The center of radial backgrounds.
+    start: Vec2,
+    end: Vec2,
+    _padding: [u32; 2],
+    start_color: Vec4,
+    end_color: Vec4,
+}
+
+impl_wgsl_struct!(BackgroundParameters {
+    kind: u32,
+    radius: f32,
+    uv_offset: Vec2,
+    uv_scale: Vec2,
+    start: Vec2,
+    end: Vec2,
+    _padding: [u32; 2],
+    start_color: Vec4,
+    end_color: Vec4,
+});
+
+impl BackgroundParameters {
+    /// The matching `BACKGROUND_*` constants in `background.wgsl` go by the variant order.
+    fn new(background: &Background) -> Self {
+        let parameters = Self {
+            uv_scale: Vec2::ONE,
+            ..Self::zeroed()
+        };
+        match *background {
+            Background::Solid(color) => Self {
+                kind: 0,
+                start_color: color,
+                ..parameters
+            },
+            Background::Linear {
+                start,
+                end,
+                start_color,
+                end_color,
+            } => Self {
+                kind: 1,
+                start,
+                end,
+                start_color,
+                end_color,
+                ..parameters
+            },
+            Background::Radial {
+                center,
+                radius,
+                center_color,
+                edge_color,
+            } => Self {
+                kind: 2,
+                radius,
+                start: center,
+                start_color: center_color,
+                end_color: edge_color,
+                ..parameters
+            },
+            Background::Image(_) => Self {
+                kind: 3,
+                ..parameters
+            },
+        }
+    }
+}
+
+/// The GPU side of a `Background`, bound by the post-processing and filled in on its own without
+/// an HDR target.
+#[derive(Debug)]
+pub(super) struct BackgroundPass {
+    background: Background,
+    parameters: BackgroundParameters,
+    parameter_buffer: UniformBuffer<BackgroundParameters>,
+    /// A single transparent pixel unless the background is an image.
+    image: g::TextureView,
+    sampler: g::Sampler,
+    bind_group_layout: g::BindGroupLayout,
+    pipeline_layout: g::PipelineLayout,
+    dst_format: g::TextureFormat,
+    pipeline: g::RenderPipeline,
+}
+
+impl BackgroundPass {
+    pub(super) const SOURCE: &str = include_str!("background.wgsl");
+
+    /// `source` is a composed `background.wgsl`, filled into targets of `dst_format`.
+    pub(super) fn new(dst_format: g::TextureFormat, source: &str, gpu: &Gpu) -> Self {
+        let background = Background::default();
+        let parameters = BackgroundParameters::new(&background);
+        let parameter_buffer =
+            UniformBuffer::new(&parameters, Some("background parameter buffer"), gpu);
+        let image = Self::create_image(&Image::new(1, 1, Vec4::ZERO), gpu);
+        let sampler = gpu.device.create_sampler(&g::SamplerDescriptor {
+            label: Some("background sampler"),
+            mag_filter: g::FilterMode::Linear,
+            min_filter: g::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&g::BindGroupLayoutDescriptor {
+                    label: Some("background bind group layout"),
+                    entries: &Self::layout_entries(),
+                });
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&g::PipelineLayoutDescriptor {
+                label: Some("background pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipeline = Self::create_pipeline(&pipeline_layout, dst_format, source, gpu);
+
+        Self {
+            background,
+            parameters,
+            parameter_buffer,
+            image,
+            sampler,
+            bind_group_layout,
+            pipeline_layout,
+            dst_format,
+            pipeline,
+        }
+    }
+
+    /// The bindings of `background.wgsl`, for the layouts of the shaders that import it.
+    pub(super) fn layout_entries() -> [g::BindGroupLayoutEntry; 3] {
+        [
+            g::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: g::ShaderStages::FRAGMENT,
+                ty: g::BindingType::Buffer {
+                    ty: g::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            g::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: g::ShaderStages::FRAGMENT,
+                ty: g::BindingType::Texture {
+                    sample_type: g::TextureSampleType::Float { filterable: true },
+                    view_dimension: g::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            g::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: g::ShaderStages::FRAGMENT,
+                ty: g::BindingType::Sampler(g::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ]
+    }
+
+    /// The resources for `layout_entries`.
+    pub(super) fn bind_group_entries(&self) -> [g::BindGroupEntry<'_>; 3] {
+        [
+            g::BindGroupEntry {
+                binding: 4,
+                resource: self.parameter_buffer.as_entire_binding(),
+            },
+            g::BindGroupEntry {
+                binding: 5,
+                resource: g::BindingResource::TextureView(&self.image),
+            },
+            g::BindGroupEntry {
+                binding: 6,
+                resource: g::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+
+    pub(super) fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub(super) fn set_background(&mut self, background: Background, gpu: &Gpu) {
+        self.image = match &background {
+            Background::Image(image) => Self::create_image(image, gpu),
+            _ => Self::create_image(&Image::new(1, 1, Vec4::ZERO), gpu),
+        };
+        self.parameters = BackgroundParameters::new(&background);
+        self.background = background;
+    }
+
+    /// Builds the pipeline from a composed `background.wgsl`, to swap in with `set_pipeline`.
+    pub(super) fn compile_shader(&self, source: &str, gpu: &Gpu) -> Result<g::RenderPipeline> {
+        shader::catch_validation_errors(gpu, || {
+            Self::create_pipeline(&self.pipeline_layout, self.dst_format, source, gpu)
+        })
+    }
+
+    pub(super) fn set_pipeline(&mut self, pipeline: g::RenderPipeline) {
+        self.pipeline = pipeline;
+    }
+
+    fn create_pipeline(
+        layout: &g::PipelineLayout,
+        dst_format: g::TextureFormat,
+        source: &str,
+        gpu: &Gpu,
+    ) -> g::RenderPipeline {
+        let shader = gpu.device.create_shader_module(g::ShaderModuleDescriptor {
+            label: Some("background.wgsl"),
+            source: g::ShaderSource::Wgsl(source.into()),
+        });
+        gpu.device
+            .create_render_pipeline(&g::RenderPipelineDescriptor {
+                label: Some("background pipeline"),
+                layout: Some(layout),
+                primitive: Default::default(),
+                vertex: g::VertexState {
+                    module: &shader,
+                    entry_point: Some("fullscreen"),
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                fragment: Some(g::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fill_background"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(g::ColorTargetState {
+                        format: dst_format,
+                        blend: Some(g::BlendState::REPLACE),
+                        write_mask: g::ColorWrites::ALL,
+                    })],
+                }),
+                depth_stencil: None,
+                multisample: Default::default(),
+                multiview: None,
+                cache: None,
+            })
+    }
+
+    fn create_image(image: &Image, gpu: &Gpu) -> g::TextureView {
+        let bytes = image
+            .pixels()
+            .iter()
+            .flat_map(|pixel| pixel.to_array())
+            .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect_vec();
+        gpu.device
+            .create_texture_with_data(
+                &gpu.queue,
+                &g::TextureDescriptor {
+                    label: Some("background image texture"),
+                    size: g::Extent3d {
+                        width: image.width(),
+                        height: image.height(),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: g::TextureDimension::D2,
+                    format: g::TextureFormat::Rgba8Unorm,
+                    usage: g::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+                Default::default(),
+                &bytes,
+            )
+            .create_view(&Default::default())
+    }
+
+    /// Writes the parameters for a target covering `viewport` of the image, before binding them.
+    pub(super) fn prepare(&mut self, viewport: Viewport, gpu: &Gpu) {
+        (self.parameters.uv_offset, self.parameters.uv_scale) = viewport.uv_transform();
+        self.parameter_buffer.write(&self.parameters, gpu);
+    }
+
+    /// Fills `dst`, covering `viewport` of the image, with the background.
+    pub(super) fn fill(
+        &mut self,
+        encoder: &mut g::CommandEncoder,
+        dst: &g::TextureView,
+        viewport: Viewport,
+        gpu: &Gpu,
+    ) {
+        self.prepare(viewport, gpu);
+        let bind_group = gpu.device.create_bind_group(&g::BindGroupDescriptor {
+            label: Some("background bind group"),
+            layout: &self.bind_group_layout,
+            entries: &self.bind_group_entries(),
+        });
+        let mut render_pass = encoder.begin_render_pass(&g::RenderPassDescriptor {
+            label: Some("background pass"),
+            color_attachments: &[Some(g::RenderPassColorAttachment {
+                view: dst,
+                resolve_target: None,
+                ops: g::Operations {
+                    load: g::LoadOp::Clear(g::Color::TRANSPARENT),
+                    store: g::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,43 @@
+// The background the points are drawn over, see `background.rs`. `post.wgsl` composites it
+// under the HDR target, without one it is filled in before the points with `fill_background`.
+
+#import fullscreen
+#import BackgroundParameters
+
+const BACKGROUND_SOLID: u32 = 0u;
+const BACKGROUND_LINEAR: u32 = 1u;
+const BACKGROUND_RADIAL: u32 = 2u;
+const BACKGROUND_IMAGE: u32 = 3u;
+
+@group(0) @binding(4) var<uniform> background: BackgroundParameters;
+@group(0) @binding(5) var background_image: texture_2d<f32>;
+@group(0) @binding(6) var background_sampler: sampler;
+
+// the premultiplied background at `uv` of the whole image, from its top left corner
+fn background_color(uv: vec2f) -> vec4f {
+    let ndc = vec2f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    var c: vec4f;
+    switch background.kind {
+        case BACKGROUND_LINEAR: {
+            let direction = background.end - background.start;
+            let t = dot(ndc - background.start, direction) / dot(direction, direction);
+            c = mix(background.start_color, background.end_color, clamp(t, 0.0, 1.0));
+        }
+        case BACKGROUND_RADIAL: {
+            let t = length(ndc - background.start) / background.radius;
+            c = mix(background.start_color, background.end_color, clamp(t, 0.0, 1.0));
+        }
+        case BACKGROUND_IMAGE: {
+            c = textureSampleLevel(background_image, background_sampler, uv, 0.0);
+        }
+        default: {
+            c = background.start_color;
+        }
+    }
+    return vec4f(c.rgb * c.a, c.a);
+}
+
+@fragment
+fn fill_background(vertex: FullscreenVertex) -> @location(0) vec4f {
+    return background_color(vertex.uv * background.uv_scale + background.uv_offset);
+}
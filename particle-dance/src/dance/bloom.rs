@@ -0,0 +1,338 @@
+use bytemuck::{Pod, Zeroable};
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+use wgpu as g;
+
+use crate::{app::Gpu, data::UniformBuffer, impl_wgsl_struct, shader};
+
+use super::post::PostProcessor;
+
+/// A glow around the bright parts of the image.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bloom {
+    /// How much of the glow is added to the image.
+    pub strength: f32,
+    /// Roughly how far the glow reaches, in pixels.
+    pub radius: f32,
+    /// The HDR brightness above which pixels glow.
+    pub threshold: f32,
+    /// The fraction of `threshold` below it over which the glow fades in.
+    pub knee: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            strength: 0.5,
+            radius: 64.0,
+            threshold: 1.0,
+            knee: 0.5,
+        }
+    }
+}
+
+impl Bloom {
+    /// The number of mips in the chain for a `width` by `height` image, each half the size of the
+    /// previous one, starting at half the image size.
+    pub(super) fn n_mips(self, width: u32, height: u32) -> u32 {
+        let max_mips = (width.min(height) / 2).max(1).ilog2() + 1;
+        (self.radius.max(1.0).log2().round() as u32).clamp(1, max_mips)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(super) struct BloomParameters {
+    threshold: f32,
+    knee: f32,
+    _padding: [u32; 2],
+}
+
+impl_wgsl_struct!(BloomParameters {
+    threshold: f32,
+    knee: f32,
+    _padding: [u32; 2],
+});
+
+/// The texture the bloom is blurred in, one mip per chain level.
+#[derive(Debug)]
+struct MipChain {
+    texture: g::Texture,
+    views: Vec<g::TextureView>,
+}
+
+/// Blurs the bright parts of the HDR target down and back up a mip chain, leaving the glow in the
+/// chain's first mip.
+#[derive(Debug)]
+pub(super) struct BloomPass {
+    parameter_buffer: UniformBuffer<BloomParameters>,
+    sampler: g::Sampler,
+    bind_group_layout: g::BindGroupLayout,
+    pipeline_layout: g::PipelineLayout,
+    pipelines: BloomPipelines,
+    chain: Option<MipChain>,
+}
+
+#[derive(Debug)]
+pub(super) struct BloomPipelines {
+    prefilter: g::RenderPipeline,
+    downsample: g::RenderPipeline,
+    upsample: g::RenderPipeline,
+}
+
+impl BloomPass {
+    pub(super) const SOURCE: &str = include_str!("bloom.wgsl");
+
+    /// `source` is a composed `bloom.wgsl`.
+    pub(super) fn new(source: &str, gpu: &Gpu) -> Self {
+        let parameter_buffer = UniformBuffer::new(
+            &BloomParameters::zeroed(),
+            Some("bloom parameter buffer"),
+            gpu,
+        );
+        let sampler = gpu.device.create_sampler(&g::SamplerDescriptor {
+            label: Some("bloom sampler"),
+            mag_filter: g::FilterMode::Linear,
+            min_filter: g::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&g::BindGroupLayoutDescriptor {
+                    label: Some("bloom bind group layout"),
+                    entries: &[
+                        g::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: g::ShaderStages::FRAGMENT,
+                            ty: g::BindingType::Texture {
+                                sample_type: g::TextureSampleType::Float { filterable: true },
+                                view_dimension: g::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        g::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: g::ShaderStages::FRAGMENT,
+                            ty: g::BindingType::Sampler(g::SamplerBindingType::Filtering),
+                            count: None,
+                        },
+                        g::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: g::ShaderStages::FRAGMENT,
+                            ty: g::BindingType::Buffer {
+                                ty: g::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&g::PipelineLayoutDescriptor {
+                label: Some("bloom pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipelines = Self::create_pipelines(&pipeline_layout, source, gpu);
+
+        Self {
+            parameter_buffer,
+            sampler,
+            bind_group_layout,
+            pipeline_layout,
+            pipelines,
+            chain: None,
+        }
+    }
+
+    pub(super) fn set_bloom(&self, bloom: Bloom, gpu: &Gpu) {
+        self.parameter_buffer.write(
+            &BloomParameters {
+                threshold: bloom.threshold,
+                knee: bloom.knee,
+                _padding: [0; 2],
+            },
+            gpu,
+        );
+    }
+
+    /// Builds the pipelines from a composed `bloom.wgsl`, to swap in with `set_pipelines`.
+    pub(super) fn compile_shader(&self, source: &str, gpu: &Gpu) -> Result<BloomPipelines> {
+        shader::catch_validation_errors(gpu, || {
+            Self::create_pipelines(&self.pipeline_layout, source, gpu)
+        })
+    }
+
+    pub(super) fn set_pipelines(&mut self, pipelines: BloomPipelines) {
+        self.pipelines = pipelines;
+    }
+
+    fn create_pipelines(layout: &g::PipelineLayout, source: &str, gpu: &Gpu) -> BloomPipelines {
+        let shader = gpu.device.create_shader_module(g::ShaderModuleDescriptor {
+            label: Some("bloom.wgsl"),
+            source: g::ShaderSource::Wgsl(source.into()),
+        });
+        let create_pipeline = |entry_point, blend| {
+            gpu.device
+                .create_render_pipeline(&g::RenderPipelineDescriptor {
+                    label: Some("bloom pipeline"),
+                    layout: Some(layout),
+                    primitive: Default::default(),
+                    vertex: g::VertexState {
+                        module: &shader,
+                        entry_point: Some("fullscreen"),
+                        compilation_options: Default::default(),
+                        buffers: &[],
+                    },
+                    fragment: Some(g::FragmentState {
+                        module: &shader,
+                        entry_point: Some(entry_point),
+                        compilation_options: Default::default(),
+                        targets: &[Some(g::ColorTargetState {
+                            format: PostProcessor::HDR_FORMAT,
+                            blend: Some(blend),
+                            write_mask: g::ColorWrites::ALL,
+                        })],
+                    }),
+                    depth_stencil: None,
+                    multisample: Default::default(),
+                    multiview: None,
+                    cache: None,
+                })
+        };
+        let add = g::BlendComponent {
+            src_factor: g::BlendFactor::One,
+            dst_factor: g::BlendFactor::One,
+            operation: g::BlendOperation::Add,
+        };
+        BloomPipelines {
+            prefilter: create_pipeline("prefilter", g::BlendState::REPLACE),
+            downsample: create_pipeline("downsample_mip", g::BlendState::REPLACE),
+            upsample: create_pipeline(
+                "upsample_mip",
+                g::BlendState {
+                    color: add,
+                    alpha: add,
+                },
+            ),
+        }
+    }
+
+    /// Recreates the mip chain if the image size or the number of mips changed.
+    fn chain(&mut self, size: g::Extent3d, n_mips: u32, gpu: &Gpu) -> &MipChain {
+        let chain_size = g::Extent3d {
+            width: (size.width / 2).max(1),
+            height: (size.height / 2).max(1),
+            depth_or_array_layers: 1,
+        };
+        if self.chain.as_ref().is_some_and(|chain| {
+            chain.texture.size() != chain_size || chain.texture.mip_level_count() != n_mips
+        }) {
+            self.chain = None;
+        }
+
+        self.chain.get_or_insert_with(|| {
+            let texture = gpu.device.create_texture(&g::TextureDescriptor {
+                label: Some("bloom mip chain texture"),
+                size: chain_size,
+                mip_level_count: n_mips,
+                sample_count: 1,
+                dimension: g::TextureDimension::D2,
+                format: PostProcessor::HDR_FORMAT,
+                usage: g::TextureUsages::RENDER_ATTACHMENT | g::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let views = (0..n_mips)
+                .map(|mip| {
+                    texture.create_view(&g::TextureViewDescriptor {
+                        label: Some("bloom mip view"),
+                        base_mip_level: mip,
+                        mip_level_count: Some(1),
+                        ..Default::default()
+                    })
+                })
+                .collect();
+            MipChain { texture, views }
+        })
+    }
+
+    /// Runs the chain on `hdr`, of size `size`, returning the view of the glow.
+    pub(super) fn apply(
+        &mut self,
+        encoder: &mut g::CommandEncoder,
+        hdr: &g::TextureView,
+        size: g::Extent3d,
+        bloom: Bloom,
+        gpu: &Gpu,
+    ) -> g::TextureView {
+        let n_mips = bloom.n_mips(size.width, size.height);
+        let views = self.chain(size, n_mips, gpu).views.clone();
+
+        let mut pass = |pipeline: &g::RenderPipeline, src: &g::TextureView, dst, load| {
+            let bind_group = gpu.device.create_bind_group(&g::BindGroupDescriptor {
+                label: Some("bloom bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    g::BindGroupEntry {
+                        binding: 0,
+                        resource: g::BindingResource::TextureView(src),
+                    },
+                    g::BindGroupEntry {
+                        binding: 1,
+                        resource: g::BindingResource::Sampler(&self.sampler),
+                    },
+                    g::BindGroupEntry {
+                        binding: 2,
+                        resource: self.parameter_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+            let mut render_pass = encoder.begin_render_pass(&g::RenderPassDescriptor {
+                label: Some("bloom pass"),
+                color_attachments: &[Some(g::RenderPassColorAttachment {
+                    view: dst,
+                    resolve_target: None,
+                    ops: g::Operations {
+                        load,
+                        store: g::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        };
+
+        let clear = g::LoadOp::Clear(g::Color::TRANSPARENT);
+        pass(&self.pipelines.prefilter, hdr, &views[0], clear);
+        for mip in 1..views.len() {
+            pass(
+                &self.pipelines.downsample,
+                &views[mip - 1],
+                &views[mip],
+                clear,
+            );
+        }
+        for mip in (1..views.len()).rev() {
+            pass(
+                &self.pipelines.upsample,
+                &views[mip],
+                &views[mip - 1],
+                g::LoadOp::Load,
+            );
+        }
+        views[0].clone()
+    }
+
+    /// Frees the mip chain until `apply` is called again.
+    pub(super) fn drop_chain(&mut self) {
+        self.chain = None;
+    }
+}
@@ -0,0 +1,64 @@
+// The passes of the bloom mip chain, following Jorge Jimenez's "Next Generation Post Processing
+// in Call of Duty: Advanced Warfare": a bright pass while downsampling into the first mip, further
+// downsampling into the smaller mips, then upsampling and adding them back up the chain.
+
+#import fullscreen
+#import BloomParameters
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var<uniform> parameters: BloomParameters;
+
+fn tap(uv: vec2f, offset: vec2f) -> vec3f {
+    let texel = 1.0 / vec2f(textureDimensions(source));
+    return textureSample(source, source_sampler, uv + offset * texel).rgb;
+}
+
+// a 13 tap filter of the source at twice the resolution of the target, which avoids the
+// flickering of a plain box filter
+fn downsample(uv: vec2f) -> vec3f {
+    let a = tap(uv, vec2f(-2.0, -2.0));
+    let b = tap(uv, vec2f(0.0, -2.0));
+    let c = tap(uv, vec2f(2.0, -2.0));
+    let d = tap(uv, vec2f(-2.0, 0.0));
+    let e = tap(uv, vec2f(0.0, 0.0));
+    let f = tap(uv, vec2f(2.0, 0.0));
+    let g = tap(uv, vec2f(-2.0, 2.0));
+    let h = tap(uv, vec2f(0.0, 2.0));
+    let i = tap(uv, vec2f(2.0, 2.0));
+    let j = tap(uv, vec2f(-1.0, -1.0));
+    let k = tap(uv, vec2f(1.0, -1.0));
+    let l = tap(uv, vec2f(-1.0, 1.0));
+    let m = tap(uv, vec2f(1.0, 1.0));
+    return e * 0.125 + (a + c + g + i) * 0.03125 + (b + d + f + h) * 0.0625
+        + (j + k + l + m) * 0.125;
+}
+
+@fragment
+fn prefilter(vertex: FullscreenVertex) -> @location(0) vec4f {
+    let c = downsample(vertex.uv);
+    // a soft knee around the threshold, so that the bloom fades in instead of popping
+    let brightness = max(c.r, max(c.g, c.b));
+    let knee = parameters.threshold * parameters.knee;
+    var soft = clamp(brightness - parameters.threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 1e-5);
+    let contribution = max(soft, brightness - parameters.threshold) / max(brightness, 1e-5);
+    return vec4f(c * contribution, 1.0);
+}
+
+@fragment
+fn downsample_mip(vertex: FullscreenVertex) -> @location(0) vec4f {
+    return vec4f(downsample(vertex.uv), 1.0);
+}
+
+// a 3x3 tent filter of the smaller mip, added onto the larger one by blending
+@fragment
+fn upsample_mip(vertex: FullscreenVertex) -> @location(0) vec4f {
+    let uv = vertex.uv;
+    let c = tap(uv, vec2f(0.0, 0.0)) * 4.0
+        + (tap(uv, vec2f(-1.0, 0.0)) + tap(uv, vec2f(1.0, 0.0)) + tap(uv, vec2f(0.0, -1.0))
+            + tap(uv, vec2f(0.0, 1.0))) * 2.0
+        + tap(uv, vec2f(-1.0, -1.0)) + tap(uv, vec2f(1.0, -1.0)) + tap(uv, vec2f(-1.0, 1.0))
+        + tap(uv, vec2f(1.0, 1.0));
+    return vec4f(c / 16.0, 1.0);
+}
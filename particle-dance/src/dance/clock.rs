@@ -0,0 +1,110 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// The generator time of a dance, advanced by the sub-app every frame independently of the wall
+/// clock, so that it can be paused, stepped, sped up, reversed and seeked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationClock {
+    time: f32,
+    /// Generator time per second, negative when playing backwards.
+    speed: f32,
+    paused: bool,
+    /// Frames to step while paused, negative to step backwards.
+    pending_steps: i32,
+}
+
+impl AnimationClock {
+    /// The speed of the window, a keyframe every 10 seconds.
+    pub const DEFAULT_SPEED: f32 = 0.1;
+    /// The wall time a single step moves the clock by, at its speed.
+    pub const STEP_DURATION: f32 = 1.0 / 60.0;
+
+    pub fn new(time: f32) -> Self {
+        Self {
+            time,
+            speed: Self::DEFAULT_SPEED,
+            paused: false,
+            pending_steps: 0,
+        }
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Jumps to `time`, keeping the clock running or paused.
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        assert!(speed.is_finite(), "the speed of a clock must be finite");
+        self.speed = speed;
+    }
+
+    /// Plays backwards if playing forwards and the other way round.
+    pub fn reverse(&mut self) {
+        self.speed = -self.speed;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.pending_steps = 0;
+    }
+
+    pub fn toggle_paused(&mut self) {
+        self.set_paused(!self.paused);
+    }
+
+    /// Pauses the clock and moves it by `n_frames` steps of `STEP_DURATION` over the next frames,
+    /// backwards for a negative count, regardless of the direction it plays in.
+    pub fn step(&mut self, n_frames: i32) {
+        self.paused = true;
+        self.pending_steps = self.pending_steps.saturating_add(n_frames);
+    }
+
+    /// Advances the clock by a frame that took `delta` seconds. Returns whether the points should
+    /// be simulated this frame, which they are not while paused unless stepping.
+    pub fn tick(&mut self, delta: f32) -> bool {
+        if !self.paused {
+            self.time += self.speed * delta;
+            return true;
+        }
+        if self.pending_steps == 0 {
+            return false;
+        }
+        let direction = self.pending_steps.signum();
+        self.pending_steps -= direction;
+        self.time += direction as f32 * self.speed.abs() * Self::STEP_DURATION;
+        true
+    }
+}
+
+impl Default for AnimationClock {
+    fn default() -> Self {
+        Self::new(0.0)
+    }
+}
+
+/// An `AnimationClock` shared between a dance and whatever controls it from outside the window,
+/// like the web page around the canvas.
+#[derive(Debug, Clone, Default)]
+pub struct ClockHandle(Arc<Mutex<AnimationClock>>);
+
+impl ClockHandle {
+    pub fn new(clock: AnimationClock) -> Self {
+        Self(Arc::new(Mutex::new(clock)))
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, AnimationClock> {
+        // the clock is valid after every method, even one that panicked
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
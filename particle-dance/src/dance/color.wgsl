@@ -0,0 +1,13 @@
+// The default coloring, blending the transformation colors by their inverse squared distance to
+// the point.
+fn color(point: vec2f, transformation_idx: u32) -> vec4f {
+    var totalLength: f32 = 0.0;
+    var color = vec4f(0.0);
+    for (var i: u32 = 0; i < arrayLength(&transformations); i++) {
+        let s = (point.xy - transformations[i].transformation.center);
+        let t = 1. / dot(s, s);
+        color += t * transformations[i].transformation.color;
+        totalLength += t;
+    }
+    return color / totalLength;
+}
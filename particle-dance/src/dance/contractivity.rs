@@ -0,0 +1,129 @@
+use glam::{Mat2, Mat3};
+use itertools::Itertools;
+
+use super::{ComputedTransformation, Transformation};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MapAnalysis {
+    /// Largest factor by which the map stretches distances.
+    pub operator_norm: f32,
+    /// Factor by which the map scales areas, negative if it flips orientation.
+    pub determinant: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttractorEstimate {
+    /// Every map is a contraction, so the attractor is a bounded set.
+    Bounded,
+    /// Some maps expand, but the random walk contracts on average. The point cloud settles, but
+    /// points regularly wander off along the expanding maps.
+    BoundedOnAverage,
+    /// The random walk expands on average and the points escape to infinity.
+    Unbounded,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractivityReport {
+    pub maps: Vec<MapAnalysis>,
+    /// Expected logarithm of the operator norm of a uniformly selected map.
+    pub mean_log_norm: f32,
+}
+
+impl ContractivityReport {
+    pub fn analyze(transformations: &[Transformation]) -> Self {
+        let maps = transformations
+            .iter()
+            .map(|&transformation| analyze_map(ComputedTransformation::compute(transformation)))
+            .collect_vec();
+        let mean_log_norm = if maps.is_empty() {
+            0.0
+        } else {
+            maps.iter().map(|map| map.operator_norm.ln()).sum::<f32>() / maps.len() as f32
+        };
+        Self {
+            maps,
+            mean_log_norm,
+        }
+    }
+
+    pub fn max_operator_norm(&self) -> f32 {
+        self.maps
+            .iter()
+            .map(|map| map.operator_norm)
+            .fold(0.0, f32::max)
+    }
+
+    pub fn is_contractive(&self) -> bool {
+        self.max_operator_norm() < 1.0
+    }
+
+    pub fn estimate_attractor(&self) -> AttractorEstimate {
+        if self.is_contractive() {
+            AttractorEstimate::Bounded
+        } else if self.mean_log_norm < 0.0 {
+            AttractorEstimate::BoundedOnAverage
+        } else {
+            AttractorEstimate::Unbounded
+        }
+    }
+}
+
+fn analyze_map(matrix: impl Into<Mat3>) -> MapAnalysis {
+    let matrix = matrix.into();
+    let linear = Mat2::from_cols(matrix.x_axis.truncate(), matrix.y_axis.truncate());
+
+    // the largest singular value of a 2x2 matrix, from the eigenvalues of `linear^T * linear`
+    let determinant = linear.determinant();
+    let frobenius_sq = linear.x_axis.length_squared() + linear.y_axis.length_squared();
+    let discriminant = (frobenius_sq * frobenius_sq - 4.0 * determinant * determinant).max(0.0);
+    let operator_norm = ((frobenius_sq + discriminant.sqrt()) * 0.5).sqrt();
+
+    MapAnalysis {
+        operator_norm,
+        determinant,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundEnforcement {
+    /// Shrinks each offending map down to the bound, leaving the others untouched.
+    Clamp,
+    /// Shrinks every map by the same factor so that the largest one meets the bound, preserving
+    /// the proportions between the maps.
+    Renormalize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContractivityBound {
+    pub max_operator_norm: f32,
+    pub enforcement: BoundEnforcement,
+}
+
+impl ContractivityBound {
+    /// Rescales the transformations that exceed the bound, returning whether any was changed.
+    pub fn enforce(&self, transformations: &mut [Transformation]) -> bool {
+        let report = ContractivityReport::analyze(transformations);
+        let max_operator_norm = report.max_operator_norm();
+        if max_operator_norm <= self.max_operator_norm {
+            return false;
+        }
+
+        // transformations are similarities, whose operator norm is the absolute scale
+        match self.enforcement {
+            BoundEnforcement::Clamp => {
+                for (transformation, map) in transformations.iter_mut().zip(&report.maps) {
+                    if map.operator_norm > self.max_operator_norm {
+                        transformation.scale *= self.max_operator_norm / map.operator_norm;
+                    }
+                }
+            }
+            BoundEnforcement::Renormalize => {
+                let factor = self.max_operator_norm / max_operator_norm;
+                for transformation in transformations {
+                    transformation.scale *= factor;
+                }
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,185 @@
+use std::{mem, num::NonZero, thread};
+
+use glam::{Mat3, Vec2, Vec4};
+use itertools::Itertools;
+
+use crate::{
+    hash::{hash, hash_vec2},
+    image::Image,
+};
+
+use super::{ComputedTransformation, Point, Transformation, sim::Simulator};
+
+/// A pure-Rust counterpart of the GPU `Simulator` and `Renderer`, applying the same
+/// transformation matrices, selection rule and coloring as `sim.wgsl` and `render.wgsl`.
+///
+/// Both pick transformations and respawn positions with the same `hash` streams, but
+/// floating-point results are not bit-for-bit identical to the GPU's, so individual trajectories
+/// may differ slightly. The point clouds and rendered images stay statistically comparable.
+/// Custom `Snippets` are not mirrored: the points move by the plain affine maps, as with the
+/// default `variation` snippet, and are colored like the default `color` snippet does.
+#[derive(Debug, Clone)]
+pub struct CpuSimulator {
+    points: Vec<Point>,
+    transformations: Vec<ComputedTransformation>,
+    frame: u32,
+    n_respawns: u32,
+}
+
+impl CpuSimulator {
+    pub fn new(points: Vec<Point>, transformations: &[Transformation]) -> Self {
+        let mut simulator = Self {
+            points,
+            transformations: vec![],
+            frame: 0,
+            n_respawns: 0,
+        };
+        simulator.set_transformations(transformations);
+        simulator
+    }
+
+    pub fn set_transformations(&mut self, transformations: &[Transformation]) {
+        assert!(
+            !transformations.is_empty(),
+            "a transformation set needs at least one transformation"
+        );
+        self.transformations = transformations
+            .iter()
+            .copied()
+            .map(ComputedTransformation::new)
+            .collect_vec();
+    }
+
+    pub fn points(&self) -> &[Point] {
+        &self.points
+    }
+
+    /// Number of points respawned since the last call, see `DanceSubApp::take_respawn_count`.
+    pub fn take_respawn_count(&mut self) -> u32 {
+        mem::take(&mut self.n_respawns)
+    }
+
+    pub fn step(&mut self) {
+        let frame = self.frame;
+        self.frame = self.frame.wrapping_add(1);
+
+        let matrices = self
+            .transformations
+            .iter()
+            .map(|transformation| Mat3::from(transformation.matrix))
+            .collect_vec();
+        let chunk_len = self.points.len().div_ceil(n_threads()).max(1);
+
+        let n_respawns: u32 = thread::scope(|scope| {
+            self.points
+                .chunks_mut(chunk_len)
+                .enumerate()
+                .map(|(chunk_idx, chunk)| {
+                    let matrices = &matrices;
+                    scope.spawn(move || {
+                        let mut n_respawns = 0;
+                        for (i, point) in chunk.iter_mut().enumerate() {
+                            let index = (chunk_idx * chunk_len + i) as u32;
+                            let (pos, respawned) = simulate(point.pos, index, frame, matrices);
+                            point.pos = pos;
+                            n_respawns += respawned as u32;
+                        }
+                        n_respawns
+                    })
+                })
+                .collect_vec()
+                .into_iter()
+                .map(|handle| handle.join().expect("simulation thread panicked"))
+                .sum()
+        });
+        self.n_respawns = self.n_respawns.wrapping_add(n_respawns);
+    }
+
+    /// Rasterizes the points onto a black target of the given size, like `Renderer::render` does
+    /// with the default `RenderOptions` and `Background`: one pixel per point, the point drawn
+    /// last winning, and no post-processing. Other options are not mirrored.
+    pub fn render(&self, width: u32, height: u32) -> Image {
+        let chunk_len = self.points.len().div_ceil(n_threads()).max(1);
+        let n_pixels = width as usize * height as usize;
+
+        // every thread draws into its own layer, later layers cover earlier ones like later
+        // points overwrite earlier ones on the GPU
+        let layers = thread::scope(|scope| {
+            self.points
+                .chunks(chunk_len)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        let mut layer = vec![None; n_pixels];
+                        for point in chunk {
+                            if let Some(idx) = pixel_index(point.pos, width, height) {
+                                layer[idx] = Some(point_color(point.pos, &self.transformations));
+                            }
+                        }
+                        layer
+                    })
+                })
+                .collect_vec()
+                .into_iter()
+                .map(|handle| handle.join().expect("rasterization thread panicked"))
+                .collect_vec()
+        });
+
+        let mut image = Image::new(width, height, Vec4::new(0.0, 0.0, 0.0, 1.0));
+        for layer in layers {
+            for (pixel, color) in image.pixels_mut().iter_mut().zip(layer) {
+                if let Some(color) = color {
+                    *pixel = color;
+                }
+            }
+        }
+        image
+    }
+}
+
+fn n_threads() -> usize {
+    thread::available_parallelism().map_or(1, NonZero::get)
+}
+
+fn simulate(point: Vec2, index: u32, frame: u32, matrices: &[Mat3]) -> (Vec2, bool) {
+    let idx = hash(frame, Simulator::SELECTION_STREAM, index) % matrices.len() as u32;
+    let matrix = matrices[idx as usize];
+    let next = (matrix * point.extend(1.0)).truncate();
+
+    let escaped = next
+        .abs()
+        .cmpgt(Vec2::splat(Simulator::RESPAWN_BOUND))
+        .any();
+    if !next.is_finite() || escaped {
+        (
+            hash_vec2(frame, Simulator::RESPAWN_STREAM, index) * 2.0 - 1.0,
+            true,
+        )
+    } else {
+        (next, false)
+    }
+}
+
+fn pixel_index(pos: Vec2, width: u32, height: u32) -> Option<usize> {
+    let x = (pos.x + 1.0) * 0.5 * width as f32;
+    let y = (1.0 - pos.y) * 0.5 * height as f32;
+    if !(0.0..width as f32).contains(&x) || !(0.0..height as f32).contains(&y) {
+        return None;
+    }
+    Some(y as usize * width as usize + x as usize)
+}
+
+/// Mirrors the default `color` snippet used by `render.wgsl`, clamped like a unorm target would.
+fn point_color(pos: Vec2, transformations: &[ComputedTransformation]) -> Vec4 {
+    let (color, total_weight) =
+        transformations
+            .iter()
+            .fold((Vec4::ZERO, 0.0), |(color, total_weight), computed| {
+                let s = pos - computed.transformation.center;
+                let weight = 1.0 / s.dot(s);
+                (
+                    color + weight * computed.transformation.color,
+                    total_weight + weight,
+                )
+            });
+    (color / total_weight).clamp(Vec4::ZERO, Vec4::ONE)
+}
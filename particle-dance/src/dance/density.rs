@@ -0,0 +1,353 @@
+use std::{
+    io::{self, Write},
+    mem,
+};
+
+use color_eyre::eyre::{Result, ensure};
+use glam::{UVec2, Vec4};
+use itertools::Itertools;
+
+use crate::{app::Gpu, image::Image};
+
+use super::{
+    Dance, Point,
+    histogram::{Bin, Histogram},
+    render::Viewport,
+};
+
+/// An offline render of how often the points visit each pixel over many simulation steps, rather
+/// than where they are in a single frame. It is rendered in tiles, so it can be far larger than
+/// a texture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DensityRender {
+    pub width: u32,
+    pub height: u32,
+    /// The steps simulated before counting, for the points to settle on the attractor.
+    pub warmup_steps: u32,
+    /// The steps whose points are counted.
+    pub steps: u32,
+    /// The size of the tiles the image is rendered in, in pixels. Smaller ones are used when a
+    /// tile's histogram would not fit in a buffer.
+    pub tile_size: u32,
+    /// Brightens sparse regions when above 1, applied to the log density as `d^(1 / gamma)`.
+    pub gamma: f32,
+}
+
+impl DensityRender {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            warmup_steps: 20,
+            steps: 100,
+            tile_size: 1024,
+            gamma: 1.0,
+        }
+    }
+
+    /// The brightness of a pixel visited `count` times, where the most visited pixel of the whole
+    /// image was visited `max_count` times.
+    fn brightness(self, count: u32, max_count: u32) -> f32 {
+        if count == 0 {
+            return 0.0;
+        }
+        let density = (count as f32).ln_1p() / (max_count as f32).ln_1p();
+        density.powf(self.gamma.recip())
+    }
+
+    /// The origins and sizes of the tiles, in rows from the top.
+    fn tiles(self, tile_size: u32) -> impl Iterator<Item = (UVec2, UVec2)> {
+        (0..self.height)
+            .step_by(tile_size as usize)
+            .flat_map(move |y| {
+                (0..self.width).step_by(tile_size as usize).map(move |x| {
+                    let min = UVec2::new(x, y);
+                    let size =
+                        UVec2::splat(tile_size).min(UVec2::new(self.width, self.height) - min);
+                    (min, size)
+                })
+            })
+    }
+}
+
+/// The raw bins of a density render, before they are normalized and tone mapped: how many points
+/// landed in each pixel and the sum of their premultiplied colors. In row-major order, starting
+/// from the top row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DensityField {
+    width: u32,
+    height: u32,
+    bins: Vec<Bin>,
+}
+
+impl DensityField {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            bins: vec![Bin::default(); width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bins(&self) -> &[Bin] {
+        &self.bins
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Bin {
+        assert!(x < self.width && y < self.height);
+        self.bins[y as usize * self.width as usize + x as usize]
+    }
+
+    /// Copies `src` into the field with its top left corner at `x`, `y`.
+    pub fn copy_from(&mut self, x: u32, y: u32, src: &DensityField) {
+        for (src_y, src_row) in src.bins.chunks(src.width as usize).enumerate() {
+            let start = (y as usize + src_y) * self.width as usize + x as usize;
+            self.bins[start..start + src_row.len()].copy_from_slice(src_row);
+        }
+    }
+
+    /// Writes the field as a NumPy `.npy` array of 32-bit floats, shaped (height, width, 4) with
+    /// the summed red, green and blue followed by the count. Counts stay exact up to 2^24.
+    pub fn write_npy(&self, mut writer: impl Write) -> io::Result<()> {
+        Self::write_npy_header(&mut writer, self.width, self.height)?;
+        self.write_npy_rows(writer)
+    }
+
+    /// Writes the header of a `.npy` array, for fields written a few rows at a time with
+    /// `write_npy_rows`.
+    pub fn write_npy_header(mut writer: impl Write, width: u32, height: u32) -> io::Result<()> {
+        let mut header = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({height}, {width}, 4), }}"
+        );
+        // the magic, version and length take 10 bytes, and the data starts 64-byte aligned
+        let len = (10 + header.len() + 1).next_multiple_of(64) - 10;
+        header = format!("{header:<0$}\n", len - 1);
+        writer.write_all(b"\x93NUMPY\x01\x00")?;
+        writer.write_all(&(len as u16).to_le_bytes())?;
+        writer.write_all(header.as_bytes())
+    }
+
+    /// Writes the bins as `.npy` rows, without a header.
+    pub fn write_npy_rows(&self, mut writer: impl Write) -> io::Result<()> {
+        let bytes = self
+            .bins
+            .iter()
+            .flat_map(|bin| bin.color.extend(bin.count as f32).to_array())
+            .flat_map(f32::to_le_bytes)
+            .collect_vec();
+        writer.write_all(&bytes)
+    }
+
+    /// Writes the counts as a grayscale PFM.
+    pub fn write_counts_pfm(&self, writer: impl Write) -> io::Result<()> {
+        self.write_pfm(writer, "Pf", |bin| vec![bin.count as f32])
+    }
+
+    /// Writes the summed colors as an RGB PFM. Dividing them by the counts gives the average
+    /// colors.
+    pub fn write_colors_pfm(&self, writer: impl Write) -> io::Result<()> {
+        self.write_pfm(writer, "PF", |bin| bin.color.to_array().to_vec())
+    }
+
+    /// PFM stores rows from the bottom, so unlike the other formats it can't be written a row at
+    /// a time.
+    fn write_pfm(
+        &self,
+        mut writer: impl Write,
+        kind: &str,
+        channels: impl Fn(Bin) -> Vec<f32>,
+    ) -> io::Result<()> {
+        // a negative scale marks little-endian floats
+        write!(writer, "{kind}\n{} {}\n-1.0\n", self.width, self.height)?;
+        let bytes = self
+            .bins
+            .chunks(self.width as usize)
+            .rev()
+            .flatten()
+            .flat_map(|&bin| channels(bin))
+            .flat_map(f32::to_le_bytes)
+            .collect_vec();
+        writer.write_all(&bytes)
+    }
+}
+
+// replaying the simulation reads the points back, which would block the browser
+#[cfg(not(target_arch = "wasm32"))]
+impl Dance {
+    /// Renders `render` into an image.
+    pub fn render_density(&mut self, render: DensityRender, gpu: &Gpu) -> Result<Image> {
+        let mut image = Image::new(render.width, render.height, Vec4::ZERO);
+        let mut y = 0;
+        self.render_density_rows(render, gpu, |rows| {
+            image.copy_from(0, y, &rows);
+            y += rows.height();
+            Ok(())
+        })?;
+        Ok(image)
+    }
+
+    /// Renders `render` as a binary PPM, a row of tiles at a time, so that it never has to fit in
+    /// memory as a whole.
+    pub fn write_density_ppm(
+        &mut self,
+        render: DensityRender,
+        mut writer: impl Write,
+        gpu: &Gpu,
+    ) -> Result<()> {
+        Image::write_ppm_header(&mut writer, render.width, render.height)?;
+        self.render_density_rows(render, gpu, |rows| Ok(rows.write_ppm_rows(&mut writer)?))
+    }
+
+    /// Renders `render` in rows of tiles from the top, handing each row to `write_rows` as an
+    /// image as wide as the render.
+    ///
+    /// Every tile replays the simulation from the current points, so that they all count the same
+    /// point stream. It is replayed twice, first to find the most visited pixel of the whole image
+    /// and then to normalize every tile by it, which keeps the tiles from showing. The points and
+    /// frame are restored afterwards.
+    pub fn render_density_rows(
+        &mut self,
+        render: DensityRender,
+        gpu: &Gpu,
+        mut write_rows: impl FnMut(Image) -> Result<()>,
+    ) -> Result<()> {
+        let mut max_count = 0;
+        self.density_field_rows(render, gpu, |rows| {
+            max_count = rows
+                .bins
+                .iter()
+                .map(|bin| bin.count)
+                .fold(max_count, u32::max);
+            Ok(())
+        })?;
+        self.density_field_rows(render, gpu, |rows| {
+            let pixels = rows
+                .bins
+                .into_iter()
+                .map(|bin| {
+                    let brightness = render.brightness(bin.count, max_count);
+                    (bin.average_color() * brightness).extend(1.0)
+                })
+                .collect();
+            write_rows(Image::from_pixels(rows.width, rows.height, pixels))
+        })
+    }
+
+    /// Counts the points of `render` into a field of raw bins, see `DensityField`.
+    pub fn render_density_field(
+        &mut self,
+        render: DensityRender,
+        gpu: &Gpu,
+    ) -> Result<DensityField> {
+        let mut field = DensityField::new(render.width, render.height);
+        let mut y = 0;
+        self.density_field_rows(render, gpu, |rows| {
+            field.copy_from(0, y, &rows);
+            y += rows.height;
+            Ok(())
+        })?;
+        Ok(field)
+    }
+
+    /// Counts the points of `render` into a `.npy` array, a row of tiles at a time, see
+    /// `DensityField::write_npy`.
+    pub fn write_density_npy(
+        &mut self,
+        render: DensityRender,
+        mut writer: impl Write,
+        gpu: &Gpu,
+    ) -> Result<()> {
+        DensityField::write_npy_header(&mut writer, render.width, render.height)?;
+        self.density_field_rows(render, gpu, |rows| Ok(rows.write_npy_rows(&mut writer)?))
+    }
+
+    /// Counts the points of `render` in rows of tiles from the top, handing each row to
+    /// `write_rows` as a field as wide as the render. Every tile replays the simulation from the
+    /// current points, which are restored afterwards along with the frame.
+    pub fn density_field_rows(
+        &mut self,
+        render: DensityRender,
+        gpu: &Gpu,
+        mut write_rows: impl FnMut(DensityField) -> Result<()>,
+    ) -> Result<()> {
+        ensure!(
+            render.width > 0 && render.height > 0 && render.tile_size > 0,
+            "a density render needs a size and a tile size of at least 1"
+        );
+        let max_tile_size = (Histogram::max_pixels(gpu) as f64).sqrt() as u32;
+        let tile_size = render.tile_size.min(max_tile_size);
+
+        let points = self.read_points(gpu)?;
+        let frame = self.simulator.frame();
+
+        let mut rows = DensityField::new(render.width, 0);
+        for (min, size) in render.tiles(tile_size) {
+            if min.x == 0 {
+                rows = DensityField::new(render.width, size.y);
+            }
+            let bins = self.count_tile(render, min, size, &points, frame, gpu)?;
+            rows.copy_from(
+                min.x,
+                0,
+                &DensityField {
+                    width: size.x,
+                    height: size.y,
+                    bins,
+                },
+            );
+            if min.x + size.x == render.width {
+                write_rows(mem::replace(&mut rows, DensityField::new(render.width, 0)))?;
+            }
+        }
+
+        self.write_points(&points, gpu);
+        self.simulator.set_frame(frame);
+        Ok(())
+    }
+
+    /// Simulates `render` from `points` at `frame`, counting the points in the tile at `min` of
+    /// `size` pixels.
+    fn count_tile(
+        &mut self,
+        render: DensityRender,
+        min: UVec2,
+        size: UVec2,
+        points: &[Point],
+        frame: u32,
+        gpu: &Gpu,
+    ) -> Result<Vec<Bin>> {
+        self.write_points(points, gpu);
+        self.simulator.set_frame(frame);
+        for _ in 0..render.warmup_steps {
+            self.simulator.step(gpu);
+        }
+
+        let min = min.as_ivec2();
+        let viewport = Viewport::from_pixels(
+            min,
+            min + size.as_ivec2(),
+            UVec2::new(render.width, render.height),
+        );
+        self.histogram.clear(size, gpu);
+        for _ in 0..render.steps {
+            self.simulator.step(gpu);
+            self.histogram.accumulate(
+                self.point_buffer.range(..),
+                &self.transformation_buffer,
+                self.simulator.frame().wrapping_sub(1),
+                self.renderer.camera(),
+                viewport,
+                gpu,
+            );
+        }
+        self.histogram.read(gpu)
+    }
+}
@@ -0,0 +1,174 @@
+use std::{mem, num::NonZero, sync::Arc};
+
+use color_eyre::eyre::{Report, Result, eyre};
+use log::error;
+use winit as w;
+
+use crate::{random::Rng, time::Instant};
+
+use super::{
+    DanceSubApp, DanceSubAppBuilder, Transformation, TransformationSet, clock::ClockHandle,
+    contractivity::ContractivityBound, cpu::CpuSimulator, seeding::PointDistribution,
+};
+
+/// A window for machines without any GPU adapter, showing the dance simulated and rasterized on
+/// the CPU by `CpuSimulator` and presented through `softbuffer`.
+///
+/// Only the transformations and the clock of the `DanceSubAppBuilder` are honored, the camera,
+/// render options and snippets need the GPU renderer. The point count is capped to what the CPU
+/// keeps up with.
+pub struct FallbackApp {
+    window_attributes: w::window::WindowAttributes,
+    n_points: usize,
+    transformations: TransformationSet,
+    contractivity_bound: Option<ContractivityBound>,
+    clock: ClockHandle,
+    window: Option<FallbackWindow>,
+    last_frame: Instant,
+}
+
+struct FallbackWindow {
+    window: Arc<w::window::Window>,
+    surface: softbuffer::Surface<Arc<w::window::Window>, Arc<w::window::Window>>,
+    simulator: CpuSimulator,
+}
+
+impl FallbackApp {
+    pub const MAX_POINTS: usize = 200_000;
+
+    pub fn new(window_attributes: w::window::WindowAttributes, dance: DanceSubAppBuilder) -> Self {
+        Self {
+            window_attributes,
+            n_points: dance.n_points.min(Self::MAX_POINTS),
+            transformations: dance.transformations(),
+            contractivity_bound: dance.contractivity_bound,
+            clock: dance.clock,
+            window: None,
+            last_frame: Instant::now(),
+        }
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        let event_loop = w::event_loop::EventLoop::new()?;
+        Ok(event_loop.run_app(self)?)
+    }
+
+    fn transformations_at(&self, t: f32) -> Vec<Transformation> {
+        DanceSubApp::bounded_transformations(self.transformations.at(t), self.contractivity_bound)
+    }
+
+    fn create_window(&self, event_loop: &w::event_loop::ActiveEventLoop) -> Result<FallbackWindow> {
+        let window = Arc::new(event_loop.create_window(self.window_attributes.clone())?);
+        let context = softbuffer::Context::new(window.clone()).map_err(softbuffer_error)?;
+        let surface =
+            softbuffer::Surface::new(&context, window.clone()).map_err(softbuffer_error)?;
+        let points = PointDistribution::Square.sample(self.n_points, &mut Rng::new());
+        let simulator =
+            CpuSimulator::new(points, &self.transformations_at(self.clock.lock().time()));
+        Ok(FallbackWindow {
+            window,
+            surface,
+            simulator,
+        })
+    }
+
+    fn redraw(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let delta = now - mem::replace(&mut self.last_frame, now);
+        let (time, simulate) = {
+            let mut clock = self.clock.lock();
+            let simulate = clock.tick(delta.as_secs_f32());
+            (clock.time(), simulate)
+        };
+        let transformations = self.transformations_at(time);
+
+        let Some(FallbackWindow {
+            window,
+            surface,
+            simulator,
+        }) = &mut self.window
+        else {
+            return Ok(());
+        };
+        simulator.set_transformations(&transformations);
+        if simulate {
+            simulator.step();
+        }
+
+        let size = window.inner_size();
+        let (Some(width), Some(height)) = (NonZero::new(size.width), NonZero::new(size.height))
+        else {
+            return Ok(());
+        };
+        surface.resize(width, height).map_err(softbuffer_error)?;
+        let image = simulator.render(size.width, size.height);
+        let mut buffer = surface.buffer_mut().map_err(softbuffer_error)?;
+        for (pixel, color) in buffer.iter_mut().zip(image.pixels()) {
+            *pixel = pack_pixel(color.truncate().to_array());
+        }
+        window.pre_present_notify();
+        buffer.present().map_err(softbuffer_error)?;
+        Ok(())
+    }
+}
+
+impl w::application::ApplicationHandler for FallbackApp {
+    fn resumed(&mut self, event_loop: &w::event_loop::ActiveEventLoop) {
+        if self.window.is_some() {
+            return;
+        }
+
+        event_loop.set_control_flow(w::event_loop::ControlFlow::Poll);
+
+        match self.create_window(event_loop) {
+            Ok(window) => self.window = Some(window),
+            Err(error) => {
+                error!("failed to create fallback window: {error:?}");
+                event_loop.exit();
+            }
+        }
+        self.last_frame = Instant::now();
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &w::event_loop::ActiveEventLoop,
+        _: w::window::WindowId,
+        event: w::event::WindowEvent,
+    ) {
+        use w::event::WindowEvent as E;
+        match event {
+            E::CloseRequested => event_loop.exit(),
+
+            E::RedrawRequested => {
+                if let Err(error) = self.redraw() {
+                    error!("failed to draw fallback frame: {error:?}");
+                    event_loop.exit();
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &w::event_loop::ActiveEventLoop) {
+        if event_loop.exiting() {
+            return;
+        }
+
+        if let Some(window) = &self.window {
+            window.window.request_redraw();
+        }
+    }
+}
+
+fn softbuffer_error(error: softbuffer::SoftBufferError) -> Report {
+    eyre!("softbuffer: {error}")
+}
+
+/// Packs a color into the `0RGB` pixels `softbuffer` presents, quantized like `Image::write_ppm`.
+fn pack_pixel(rgb: [f32; 3]) -> u32 {
+    rgb.into_iter()
+        .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u32)
+        .fold(0, |pixel, channel| (pixel << 8) | channel)
+}
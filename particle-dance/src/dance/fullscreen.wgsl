@@ -0,0 +1,14 @@
+// A vertex stage drawing a single triangle that covers the whole target, for passes that work on
+// every pixel. Draw it with three vertices.
+
+struct FullscreenVertex {
+    @builtin(position) position: vec4f,
+    // texture coordinates, from the top left corner
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn fullscreen(@builtin(vertex_index) index: u32) -> FullscreenVertex {
+    let uv = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    return FullscreenVertex(vec4f(uv * 2.0 - 1.0, 0.0, 1.0), vec2f(uv.x, 1.0 - uv.y));
+}
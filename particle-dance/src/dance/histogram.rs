@@ -0,0 +1,284 @@
+use std::iter;
+
+use bytemuck::{Pod, Zeroable};
+use color_eyre::eyre::Result;
+use glam::{UVec2, UVec4, Vec2, Vec3};
+use wgpu as g;
+
+use crate::{
+    app::Gpu,
+    data::{Buffer, BufferRange, UniformBuffer},
+    impl_wgsl_struct, shader,
+};
+
+use super::{ComputedTransformation, Point, render::Camera, render::Viewport, sim::Simulator};
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(super) struct HistogramParameters {
+    /// The simulation frame that produced the points, to tell which transformation moved them.
+    last_frame: u32,
+    /// The index of the first point bound.
+    point_offset: u32,
+    size: UVec2,
+    camera_center: Vec2,
+    camera_zoom: f32,
+    /// The value of a color channel of 1 in the fixed point sums.
+    color_scale: f32,
+    viewport_center: Vec2,
+    viewport_scale: Vec2,
+}
+
+impl_wgsl_struct!(HistogramParameters {
+    last_frame: u32,
+    point_offset: u32,
+    size: UVec2,
+    camera_center: Vec2,
+    camera_zoom: f32,
+    color_scale: f32,
+    viewport_center: Vec2,
+    viewport_scale: Vec2,
+});
+
+/// The points counted in each pixel of a histogram and the sum of their premultiplied colors.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Bin {
+    pub color: Vec3,
+    pub count: u32,
+}
+
+impl Bin {
+    /// The average color of the points in the bin, black without any.
+    pub fn average_color(self) -> Vec3 {
+        if self.count == 0 {
+            Vec3::ZERO
+        } else {
+            self.color / self.count as f32
+        }
+    }
+}
+
+/// Counts where points land over many frames, pixel by pixel, on the GPU.
+#[derive(Debug)]
+pub(super) struct Histogram {
+    parameter_buffer: UniformBuffer<HistogramParameters>,
+    bin_buffer: Buffer<UVec4>,
+    size: UVec2,
+    bind_group_layout: g::BindGroupLayout,
+    pipeline_layout: g::PipelineLayout,
+    pipeline: g::ComputePipeline,
+}
+
+impl Histogram {
+    pub(super) const SOURCE: &str = include_str!("histogram.wgsl");
+    const INVOCATIONS_PER_WORKGROUP: u32 = 64;
+    /// Colors are summed in fixed point, so a bin overflows after 2^32 / 255 points of a full
+    /// channel.
+    const COLOR_SCALE: f32 = 255.0;
+
+    /// `source` is a composed `histogram.wgsl`.
+    pub(super) fn new(source: &str, gpu: &Gpu) -> Self {
+        let parameter_buffer = UniformBuffer::new(
+            &HistogramParameters::zeroed(),
+            Some("histogram parameter buffer"),
+            gpu,
+        );
+        let bin_buffer = Self::create_bin_buffer(UVec2::ONE, gpu);
+
+        let storage = |binding, read_only| g::BindGroupLayoutEntry {
+            binding,
+            visibility: g::ShaderStages::COMPUTE,
+            ty: g::BindingType::Buffer {
+                ty: g::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&g::BindGroupLayoutDescriptor {
+                    label: Some("histogram bind group layout"),
+                    entries: &[
+                        storage(0, true),
+                        g::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: g::ShaderStages::COMPUTE,
+                            ty: g::BindingType::Buffer {
+                                ty: g::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        storage(2, false),
+                        storage(3, true),
+                    ],
+                });
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&g::PipelineLayoutDescriptor {
+                label: Some("histogram pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipeline = Self::create_pipeline(&pipeline_layout, source, gpu);
+
+        Self {
+            parameter_buffer,
+            bin_buffer,
+            size: UVec2::ONE,
+            bind_group_layout,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+
+    /// Builds the pipeline from a composed `histogram.wgsl`, to swap in with `set_pipeline`.
+    pub(super) fn compile_shader(&self, source: &str, gpu: &Gpu) -> Result<g::ComputePipeline> {
+        shader::catch_validation_errors(gpu, || {
+            Self::create_pipeline(&self.pipeline_layout, source, gpu)
+        })
+    }
+
+    pub(super) fn set_pipeline(&mut self, pipeline: g::ComputePipeline) {
+        self.pipeline = pipeline;
+    }
+
+    fn create_pipeline(layout: &g::PipelineLayout, source: &str, gpu: &Gpu) -> g::ComputePipeline {
+        let shader = gpu.device.create_shader_module(g::ShaderModuleDescriptor {
+            label: Some("histogram.wgsl"),
+            source: g::ShaderSource::Wgsl(source.into()),
+        });
+        gpu.device
+            .create_compute_pipeline(&g::ComputePipelineDescriptor {
+                label: Some("histogram pipeline"),
+                layout: Some(layout),
+                module: &shader,
+                entry_point: Some("accumulate"),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+    }
+
+    fn create_bin_buffer(size: UVec2, gpu: &Gpu) -> Buffer<UVec4> {
+        Buffer::new(
+            size.element_product() as usize,
+            Some("histogram bin buffer"),
+            g::BufferUsages::STORAGE | g::BufferUsages::COPY_SRC | g::BufferUsages::COPY_DST,
+            gpu,
+        )
+    }
+
+    /// The largest number of pixels a histogram can have.
+    pub(super) fn max_pixels(gpu: &Gpu) -> u64 {
+        let limits = gpu.device.limits();
+        (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size)
+            / size_of::<UVec4>() as u64
+    }
+
+    /// Empties the histogram, resizing it to `size` pixels.
+    pub(super) fn clear(&mut self, size: UVec2, gpu: &Gpu) {
+        if size != self.size {
+            self.bin_buffer = Self::create_bin_buffer(size, gpu);
+            self.size = size;
+        }
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&g::CommandEncoderDescriptor {
+                label: Some("histogram clear command encoder"),
+            });
+        encoder.clear_buffer(&self.bin_buffer, 0, None);
+        gpu.queue.submit(iter::once(encoder.finish()));
+    }
+
+    /// Counts `points`, as last moved by simulation frame `last_frame`, in the pixels of the part
+    /// of the image in `viewport`, as seen by `camera`.
+    pub(super) fn accumulate(
+        &self,
+        points: BufferRange<'_, Point>,
+        transformations: &Buffer<ComputedTransformation>,
+        last_frame: u32,
+        camera: Camera,
+        viewport: Viewport,
+        gpu: &Gpu,
+    ) {
+        // bound a chunk at a time, like the simulation, to stay within the binding size limits
+        let chunk_len = Simulator::FULL_POINT_CHUNK_LEN as usize;
+        for start in (0..points.len()).step_by(chunk_len) {
+            let chunk = points.buffer().range(
+                points.offset() + start..points.offset() + points.len().min(start + chunk_len),
+            );
+            self.parameter_buffer.write(
+                &HistogramParameters {
+                    last_frame,
+                    point_offset: (points.offset() + start) as u32,
+                    size: self.size,
+                    camera_center: camera.center,
+                    camera_zoom: camera.zoom,
+                    color_scale: Self::COLOR_SCALE,
+                    viewport_center: viewport.center,
+                    viewport_scale: viewport.scale,
+                },
+                gpu,
+            );
+            let bind_group = gpu.device.create_bind_group(&g::BindGroupDescriptor {
+                label: Some("histogram bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    g::BindGroupEntry {
+                        binding: 0,
+                        resource: transformations.as_entire_binding(),
+                    },
+                    g::BindGroupEntry {
+                        binding: 1,
+                        resource: self.parameter_buffer.as_entire_binding(),
+                    },
+                    g::BindGroupEntry {
+                        binding: 2,
+                        resource: self.bin_buffer.as_entire_binding(),
+                    },
+                    g::BindGroupEntry {
+                        binding: 3,
+                        resource: chunk.binding(),
+                    },
+                ],
+            });
+
+            let mut encoder = gpu
+                .device
+                .create_command_encoder(&g::CommandEncoderDescriptor {
+                    label: Some("histogram command encoder"),
+                });
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&g::ComputePassDescriptor {
+                    label: Some("histogram compute pass"),
+                    timestamp_writes: None,
+                });
+                compute_pass.set_pipeline(&self.pipeline);
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                compute_pass.dispatch_workgroups(
+                    (chunk.len() as u32).div_ceil(Self::INVOCATIONS_PER_WORKGROUP),
+                    1,
+                    1,
+                );
+            }
+            // the parameters are rewritten for the next chunk, which only affects later submits
+            gpu.queue.submit(iter::once(encoder.finish()));
+        }
+    }
+
+    /// Copies the bins back to the CPU, row by row from the top.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(super) fn read(&self, gpu: &Gpu) -> Result<Vec<Bin>> {
+        Ok(self
+            .bin_buffer
+            .read_to_vec(gpu)?
+            .into_iter()
+            .map(|bin| Bin {
+                color: bin.truncate().as_vec3() / Self::COLOR_SCALE,
+                count: bin.w,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,44 @@
+// Counts the points landing in each pixel, along with the sum of their colors, see
+// `histogram.rs`.
+
+#import hash
+#import streams
+#import Point
+#import ComputedTransformation
+#import HistogramParameters
+#import color
+
+@group(0) @binding(0) var<storage> transformations: array<ComputedTransformation>;
+@group(0) @binding(1) var<uniform> parameters: HistogramParameters;
+// red, green and blue sums in fixed point followed by the count, per pixel from the top row
+@group(0) @binding(2) var<storage, read_write> bins: array<atomic<u32>>;
+@group(0) @binding(3) var<storage> points: array<Point>;
+
+// the transformation that moved the point in the last simulation step, as in `render.wgsl`
+fn last_transformation(index: u32) -> u32 {
+    let selection = hash(parameters.last_frame, SELECTION_STREAM, index);
+    return selection % arrayLength(&transformations);
+}
+
+@compute @workgroup_size(64)
+fn accumulate(@builtin(global_invocation_id) id: vec3u) {
+    if id.x >= arrayLength(&points) {
+        return;
+    }
+
+    let point = points[id.x].pos;
+    let image = (point - parameters.camera_center) * parameters.camera_zoom;
+    let ndc = (image - parameters.viewport_center) * parameters.viewport_scale;
+    let pixel = floor(vec2f(ndc.x + 1.0, 1.0 - ndc.y) * 0.5 * vec2f(parameters.size));
+    if any(pixel < vec2f(0.0)) || any(pixel >= vec2f(parameters.size)) {
+        return;
+    }
+
+    let c = color(point, last_transformation(parameters.point_offset + id.x));
+    let rgb = vec3u(round(clamp(c.rgb * c.a, vec3f(0.0), vec3f(1.0)) * parameters.color_scale));
+    let bin = (u32(pixel.y) * parameters.size.x + u32(pixel.x)) * 4u;
+    atomicAdd(&bins[bin], rgb.r);
+    atomicAdd(&bins[bin + 1u], rgb.g);
+    atomicAdd(&bins[bin + 2u], rgb.b);
+    atomicAdd(&bins[bin + 3u], 1u);
+}
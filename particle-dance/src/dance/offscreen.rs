@@ -0,0 +1,105 @@
+use std::iter;
+
+use color_eyre::eyre::Result;
+use glam::Vec4;
+use itertools::Itertools;
+use wgpu as g;
+
+use crate::{app::Gpu, data::Buffer, image::Image};
+
+/// A texture to render a `Dance` into without a window, whose contents can be read back.
+#[derive(Debug)]
+pub struct OffscreenTarget {
+    texture: g::Texture,
+    staging_buffer: Buffer<u8>,
+    padded_bytes_per_row: u32,
+}
+
+impl OffscreenTarget {
+    pub const FORMAT: g::TextureFormat = g::TextureFormat::Rgba8Unorm;
+    const BYTES_PER_PIXEL: u32 = 4;
+
+    pub fn new(width: u32, height: u32, gpu: &Gpu) -> Self {
+        let texture = gpu.device.create_texture(&g::TextureDescriptor {
+            label: Some("offscreen target texture"),
+            size: g::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: g::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: g::TextureUsages::RENDER_ATTACHMENT | g::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let padded_bytes_per_row =
+            (width * Self::BYTES_PER_PIXEL).next_multiple_of(g::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let staging_buffer = Buffer::new(
+            padded_bytes_per_row as usize * height as usize,
+            Some("offscreen target staging buffer"),
+            g::BufferUsages::MAP_READ | g::BufferUsages::COPY_DST,
+            gpu,
+        );
+
+        Self {
+            texture,
+            staging_buffer,
+            padded_bytes_per_row,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.texture.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.texture.height()
+    }
+
+    pub fn texture(&self) -> &g::Texture {
+        &self.texture
+    }
+
+    /// Copies the texture back to the CPU, blocking until the GPU is done with it.
+    pub fn read(&mut self, gpu: &Gpu) -> Result<Image> {
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&g::CommandEncoderDescriptor {
+                label: Some("offscreen target readback command encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            g::TexelCopyBufferInfo {
+                buffer: &self.staging_buffer,
+                layout: g::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            self.texture.size(),
+        );
+        gpu.queue.submit(iter::once(encoder.finish()));
+
+        self.staging_buffer.map_block(g::MapMode::Read, .., gpu)?;
+        let pixels = {
+            let bytes = self.staging_buffer.slice(..).get_mapped_range();
+            bytes
+                .chunks(self.padded_bytes_per_row as usize)
+                .flat_map(|row| {
+                    row[..(self.width() * Self::BYTES_PER_PIXEL) as usize]
+                        .chunks(Self::BYTES_PER_PIXEL as usize)
+                        .map(|pixel| {
+                            Vec4::from_array([0, 1, 2, 3].map(|i| pixel[i] as f32 / 255.0))
+                        })
+                })
+                .collect_vec()
+        };
+        self.staging_buffer.unmap();
+
+        Ok(Image::from_pixels(self.width(), self.height(), pixels))
+    }
+}
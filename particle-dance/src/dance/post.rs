@@ -0,0 +1,488 @@
+use bytemuck::{Pod, Zeroable};
+use color_eyre::eyre::Result;
+use glam::Vec2;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use wgpu as g;
+
+use crate::{app::Gpu, data::UniformBuffer, impl_wgsl_struct, shader};
+
+use super::{
+    ShaderSources,
+    background::{Background, BackgroundPass},
+    bloom::{Bloom, BloomPass, BloomPipelines},
+    render::Viewport,
+    trails::{TrailPipelines, Trails, TrailsPass},
+};
+
+/// The curve that maps unbounded HDR values into the displayable range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMapping {
+    /// Values are clamped.
+    #[default]
+    None,
+    Reinhard,
+    /// A fit of the ACES filmic curve, with punchy contrast.
+    Aces,
+    /// Desaturates very bright colors towards white instead of skewing their hue.
+    AgX,
+}
+
+impl ToneMapping {
+    /// The matching `TONE_MAPPING_*` constant in `post.wgsl`.
+    fn wgsl_kind(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Reinhard => 1,
+            Self::Aces => 2,
+            Self::AgX => 3,
+        }
+    }
+}
+
+/// The post-processing applied to the HDR target, in the order of the fields. The default
+/// leaves the image as it is.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcess {
+    /// Blends every frame into the faded earlier ones instead of starting from black.
+    pub trails: Option<Trails>,
+    pub bloom: Option<Bloom>,
+    /// In stops, each one doubling the brightness.
+    pub exposure: f32,
+    pub tone_mapping: ToneMapping,
+    /// 0 is grayscale, 1 leaves colors as they are.
+    pub saturation: f32,
+    /// Like `saturation`, but adds less to colors the more saturated they already are.
+    pub vibrance: f32,
+    /// How much the corners are darkened, from 0 to 1.
+    pub vignette: f32,
+    /// Applied last, as `c^(1 / gamma)`.
+    pub gamma: f32,
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self {
+            trails: None,
+            bloom: None,
+            exposure: 0.0,
+            tone_mapping: ToneMapping::default(),
+            saturation: 1.0,
+            vibrance: 0.0,
+            vignette: 0.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(super) struct PostParameters {
+    /// A linear factor, unlike `PostProcess::exposure`.
+    exposure: f32,
+    tone_mapping: u32,
+    saturation: f32,
+    vibrance: f32,
+    vignette: f32,
+    gamma: f32,
+    /// `Bloom::strength` spread over the mips of the chain, which add up.
+    bloom_strength: f32,
+    _padding: [u32; 1],
+    /// Maps the texture coordinates of the target to those of the whole image, for the vignette.
+    uv_offset: Vec2,
+    uv_scale: Vec2,
+}
+
+impl_wgsl_struct!(PostParameters {
+    exposure: f32,
+    tone_mapping: u32,
+    saturation: f32,
+    vibrance: f32,
+    vignette: f32,
+    gamma: f32,
+    bloom_strength: f32,
+    _padding: [u32; 1],
+    uv_offset: Vec2,
+    uv_scale: Vec2,
+});
+
+impl PostParameters {
+    fn new(post_process: PostProcess, n_bloom_mips: u32, viewport: Viewport) -> Self {
+        let bloom_strength = post_process
+            .bloom
+            .map_or(0.0, |bloom| bloom.strength / n_bloom_mips as f32);
+        let (uv_offset, uv_scale) = viewport.uv_transform();
+        Self {
+            exposure: post_process.exposure.exp2(),
+            tone_mapping: post_process.tone_mapping.wgsl_kind(),
+            saturation: post_process.saturation,
+            vibrance: post_process.vibrance,
+            vignette: post_process.vignette,
+            gamma: post_process.gamma,
+            bloom_strength,
+            _padding: [0; 1],
+            uv_offset,
+            uv_scale,
+        }
+    }
+}
+
+/// The float16 target the points of each frame are blended into.
+#[derive(Debug)]
+struct HdrTarget {
+    texture: g::Texture,
+    view: g::TextureView,
+}
+
+/// Owns the HDR target and the passes that turn it into the final image.
+#[derive(Debug)]
+pub(super) struct PostProcessor {
+    post_process: PostProcess,
+    parameter_buffer: UniformBuffer<PostParameters>,
+    trails_pass: TrailsPass,
+    bloom_pass: BloomPass,
+    background_pass: BackgroundPass,
+    /// Bound in place of the bloom without it.
+    no_bloom: g::TextureView,
+    sampler: g::Sampler,
+    bind_group_layout: g::BindGroupLayout,
+    pipeline_layout: g::PipelineLayout,
+    dst_format: g::TextureFormat,
+    pipeline: g::RenderPipeline,
+    target: Option<HdrTarget>,
+}
+
+/// The pipelines of the post-processing passes, see `PostProcessor::compile_shaders`.
+#[derive(Debug)]
+pub(super) struct PostPipelines {
+    post: g::RenderPipeline,
+    trails: TrailPipelines,
+    bloom: BloomPipelines,
+    background: g::RenderPipeline,
+}
+
+impl PostProcessor {
+    pub(super) const SOURCE: &str = include_str!("post.wgsl");
+    pub(super) const HDR_FORMAT: g::TextureFormat = g::TextureFormat::Rgba16Float;
+
+    pub(super) fn new(
+        dst_format: g::TextureFormat,
+        post_process: PostProcess,
+        sources: &ShaderSources,
+        gpu: &Gpu,
+    ) -> Self {
+        let parameter_buffer = UniformBuffer::new(
+            &PostParameters::new(post_process, 1, Viewport::default()),
+            Some("post-processing parameter buffer"),
+            gpu,
+        );
+        let trails_pass = TrailsPass::new(&sources.trails, gpu);
+        let bloom_pass = BloomPass::new(&sources.bloom, gpu);
+        let background_pass = BackgroundPass::new(dst_format, &sources.background, gpu);
+        if let Some(bloom) = post_process.bloom {
+            bloom_pass.set_bloom(bloom, gpu);
+        }
+        let no_bloom = gpu
+            .device
+            .create_texture(&g::TextureDescriptor {
+                label: Some("no bloom texture"),
+                size: g::Extent3d::default(),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: g::TextureDimension::D2,
+                format: Self::HDR_FORMAT,
+                usage: g::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&Default::default());
+        let sampler = gpu.device.create_sampler(&g::SamplerDescriptor {
+            label: Some("post-processing sampler"),
+            mag_filter: g::FilterMode::Linear,
+            min_filter: g::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&g::BindGroupLayoutDescriptor {
+                    label: Some("post-processing bind group layout"),
+                    entries: &[
+                        g::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: g::ShaderStages::FRAGMENT,
+                            ty: g::BindingType::Texture {
+                                sample_type: g::TextureSampleType::Float { filterable: false },
+                                view_dimension: g::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        g::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: g::ShaderStages::FRAGMENT,
+                            ty: g::BindingType::Buffer {
+                                ty: g::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        g::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: g::ShaderStages::FRAGMENT,
+                            ty: g::BindingType::Texture {
+                                sample_type: g::TextureSampleType::Float { filterable: true },
+                                view_dimension: g::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        g::BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: g::ShaderStages::FRAGMENT,
+                            ty: g::BindingType::Sampler(g::SamplerBindingType::Filtering),
+                            count: None,
+                        },
+                    ]
+                    .into_iter()
+                    .chain(BackgroundPass::layout_entries())
+                    .collect_vec(),
+                });
+
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&g::PipelineLayoutDescriptor {
+                label: Some("post-processing pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipeline = Self::create_pipeline(&pipeline_layout, dst_format, &sources.post, gpu);
+
+        Self {
+            post_process,
+            parameter_buffer,
+            trails_pass,
+            bloom_pass,
+            background_pass,
+            no_bloom,
+            sampler,
+            bind_group_layout,
+            pipeline_layout,
+            dst_format,
+            pipeline,
+            target: None,
+        }
+    }
+
+    pub(super) fn set_post_process(&mut self, post_process: PostProcess, gpu: &Gpu) {
+        if post_process.trails.is_none() {
+            self.trails_pass.drop_accumulation();
+        }
+        match post_process.bloom {
+            Some(bloom) => self.bloom_pass.set_bloom(bloom, gpu),
+            None => self.bloom_pass.drop_chain(),
+        }
+        self.post_process = post_process;
+    }
+
+    pub(super) fn background(&self) -> &Background {
+        self.background_pass.background()
+    }
+
+    pub(super) fn set_background(&mut self, background: Background, gpu: &Gpu) {
+        self.background_pass.set_background(background, gpu);
+    }
+
+    /// Builds the pipelines of every pass from `sources`, to swap in with `set_pipelines`.
+    pub(super) fn compile_shaders(
+        &self,
+        sources: &ShaderSources,
+        gpu: &Gpu,
+    ) -> Result<PostPipelines> {
+        Ok(PostPipelines {
+            post: shader::catch_validation_errors(gpu, || {
+                Self::create_pipeline(&self.pipeline_layout, self.dst_format, &sources.post, gpu)
+            })?,
+            trails: self.trails_pass.compile_shader(&sources.trails, gpu)?,
+            bloom: self.bloom_pass.compile_shader(&sources.bloom, gpu)?,
+            background: self
+                .background_pass
+                .compile_shader(&sources.background, gpu)?,
+        })
+    }
+
+    pub(super) fn set_pipelines(&mut self, pipelines: PostPipelines) {
+        self.pipeline = pipelines.post;
+        self.trails_pass.set_pipelines(pipelines.trails);
+        self.bloom_pass.set_pipelines(pipelines.bloom);
+        self.background_pass.set_pipeline(pipelines.background);
+    }
+
+    /// Fills `dst`, which covers `viewport` of the image, with just the background, for drawing
+    /// points over it without the HDR target.
+    pub(super) fn fill_background(
+        &mut self,
+        encoder: &mut g::CommandEncoder,
+        dst: &g::TextureView,
+        viewport: Viewport,
+        gpu: &Gpu,
+    ) {
+        self.background_pass.fill(encoder, dst, viewport, gpu);
+    }
+
+    fn create_pipeline(
+        layout: &g::PipelineLayout,
+        dst_format: g::TextureFormat,
+        source: &str,
+        gpu: &Gpu,
+    ) -> g::RenderPipeline {
+        let shader = gpu.device.create_shader_module(g::ShaderModuleDescriptor {
+            label: Some("post.wgsl"),
+            source: g::ShaderSource::Wgsl(source.into()),
+        });
+        gpu.device
+            .create_render_pipeline(&g::RenderPipelineDescriptor {
+                label: Some("post-processing pipeline"),
+                layout: Some(layout),
+                primitive: Default::default(),
+                vertex: g::VertexState {
+                    module: &shader,
+                    entry_point: Some("fullscreen"),
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                fragment: Some(g::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fragment"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(g::ColorTargetState {
+                        format: dst_format,
+                        blend: Some(g::BlendState::REPLACE),
+                        write_mask: g::ColorWrites::ALL,
+                    })],
+                }),
+                depth_stencil: None,
+                multisample: Default::default(),
+                multiview: None,
+                cache: None,
+            })
+    }
+
+    /// The HDR target, recreated if its size no longer matches `size`.
+    pub(super) fn target(&mut self, size: g::Extent3d, gpu: &Gpu) -> &g::TextureView {
+        if self
+            .target
+            .as_ref()
+            .is_some_and(|target| target.texture.size() != size)
+        {
+            self.target = None;
+        }
+
+        let target = self.target.get_or_insert_with(|| {
+            let texture = gpu.device.create_texture(&g::TextureDescriptor {
+                label: Some("hdr target texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: g::TextureDimension::D2,
+                format: Self::HDR_FORMAT,
+                usage: g::TextureUsages::RENDER_ATTACHMENT | g::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&Default::default());
+            HdrTarget { texture, view }
+        });
+        &target.view
+    }
+
+    /// Frees the HDR target until `target` is called again.
+    pub(super) fn drop_target(&mut self) {
+        self.target = None;
+        self.trails_pass.drop_accumulation();
+        self.bloom_pass.drop_chain();
+    }
+
+    /// Starts the trails over from the next frame, for when the earlier frames no longer line up
+    /// with it. A new target size does this on its own.
+    pub(super) fn reset_trails(&mut self) {
+        self.trails_pass.reset();
+    }
+
+    /// Post-processes the HDR target, or the trails it was blended into, into `dst`, which
+    /// covers `viewport` of the image, and composites it over the background.
+    pub(super) fn apply(
+        &mut self,
+        encoder: &mut g::CommandEncoder,
+        dst: &g::TextureView,
+        viewport: Viewport,
+        gpu: &Gpu,
+    ) {
+        let target = self
+            .target
+            .as_ref()
+            .expect("the HDR target is created before rendering into it");
+
+        let size = target.texture.size();
+        let image = match self.post_process.trails {
+            Some(trails) => self
+                .trails_pass
+                .apply(encoder, &target.view, size, trails, gpu),
+            None => target.view.clone(),
+        };
+        let (bloom, n_bloom_mips) = match self.post_process.bloom {
+            Some(bloom) => (
+                self.bloom_pass.apply(encoder, &image, size, bloom, gpu),
+                bloom.n_mips(size.width, size.height),
+            ),
+            None => (self.no_bloom.clone(), 1),
+        };
+        self.parameter_buffer.write(
+            &PostParameters::new(self.post_process, n_bloom_mips, viewport),
+            gpu,
+        );
+        self.background_pass.prepare(viewport, gpu);
+
+        let bind_group = gpu.device.create_bind_group(&g::BindGroupDescriptor {
+            label: Some("post-processing bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                g::BindGroupEntry {
+                    binding: 0,
+                    resource: g::BindingResource::TextureView(&image),
+                },
+                g::BindGroupEntry {
+                    binding: 1,
+                    resource: self.parameter_buffer.as_entire_binding(),
+                },
+                g::BindGroupEntry {
+                    binding: 2,
+                    resource: g::BindingResource::TextureView(&bloom),
+                },
+                g::BindGroupEntry {
+                    binding: 3,
+                    resource: g::BindingResource::Sampler(&self.sampler),
+                },
+            ]
+            .into_iter()
+            .chain(self.background_pass.bind_group_entries())
+            .collect_vec(),
+        });
+        let mut post_pass = encoder.begin_render_pass(&g::RenderPassDescriptor {
+            label: Some("post-processing pass"),
+            color_attachments: &[Some(g::RenderPassColorAttachment {
+                view: dst,
+                resolve_target: None,
+                ops: g::Operations {
+                    load: g::LoadOp::Clear(g::Color::TRANSPARENT),
+                    store: g::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        post_pass.set_pipeline(&self.pipeline);
+        post_pass.set_bind_group(0, &bind_group, &[]);
+        post_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,95 @@
+// Turns the HDR target into the final image: bloom, exposure, tone mapping, color grading and
+// vignette, composited over the background.
+
+#import fullscreen
+#import PostParameters
+#import background
+
+const TONE_MAPPING_NONE: u32 = 0u;
+const TONE_MAPPING_REINHARD: u32 = 1u;
+const TONE_MAPPING_ACES: u32 = 2u;
+const TONE_MAPPING_AGX: u32 = 3u;
+
+@group(0) @binding(0) var hdr: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> parameters: PostParameters;
+// the first mip of the bloom chain, see `bloom.wgsl`
+@group(0) @binding(2) var bloom: texture_2d<f32>;
+@group(0) @binding(3) var bloom_sampler: sampler;
+
+fn luminance(c: vec3f) -> f32 {
+    return dot(c, vec3f(0.2126, 0.7152, 0.0722));
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3f) -> vec3f {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3f(0.0), vec3f(1.0));
+}
+
+// Troy Sobotka's AgX with the polynomial fit of its default contrast curve by Benjamin Wrensch
+fn agx(color: vec3f) -> vec3f {
+    let inset = mat3x3f(
+        vec3f(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3f(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3f(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3f(
+        vec3f(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3f(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3f(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var x = inset * color;
+    x = clamp(log2(max(x, vec3f(1e-10))), vec3f(min_ev), vec3f(max_ev));
+    x = (x - min_ev) / (max_ev - min_ev);
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    x = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+        - 0.00232;
+    x = outset * x;
+    // the curve produces display encoded values, the target encodes them itself
+    return pow(max(x, vec3f(0.0)), vec3f(2.2));
+}
+
+fn tone_map(c: vec3f) -> vec3f {
+    switch parameters.tone_mapping {
+        case TONE_MAPPING_REINHARD: {
+            return c / (1.0 + c);
+        }
+        case TONE_MAPPING_ACES: {
+            return aces(c);
+        }
+        case TONE_MAPPING_AGX: {
+            return agx(c);
+        }
+        default: {
+            return c;
+        }
+    }
+}
+
+@fragment
+fn fragment(vertex: FullscreenVertex) -> @location(0) vec4f {
+    let texel = textureLoad(hdr, vec2i(vertex.position.xy), 0);
+    let glow = textureSample(bloom, bloom_sampler, vertex.uv).rgb * parameters.bloom_strength;
+    // the target holds premultiplied colors, which are graded straight, additive blending can
+    // carry the alpha past 1 while the colors keep their brightness
+    let a = clamp(texel.a, 0.0, 1.0);
+    // glow where there are no points is light without coverage, added over the background
+    let coverage = select(1.0, a, a > 0.0);
+    var c = tone_map(max((texel.rgb + glow) / coverage * parameters.exposure, vec3f(0.0)));
+
+    let luma = vec3f(luminance(c));
+    c = mix(luma, c, parameters.saturation);
+    // vibrance saturates dull colors more than ones that are saturated already
+    let chroma = max(c.r, max(c.g, c.b)) - min(c.r, min(c.g, c.b));
+    c = mix(luma, c, 1.0 + parameters.vibrance * (1.0 - clamp(chroma, 0.0, 1.0)));
+
+    let d = vertex.uv * parameters.uv_scale + parameters.uv_offset - 0.5;
+    c *= max(1.0 - parameters.vignette * 2.0 * dot(d, d), 0.0);
+
+    c = pow(clamp(c, vec3f(0.0), vec3f(1.0)), vec3f(1.0 / parameters.gamma)) * coverage;
+    let bg = background_color(vertex.uv * parameters.uv_scale + parameters.uv_offset);
+    return vec4f(c + bg.rgb * (1.0 - a), a + bg.a * (1.0 - a));
+}
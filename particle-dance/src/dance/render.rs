@@ -1,55 +1,277 @@
 use std::{iter, mem};
 
+use bytemuck::{Pod, Zeroable};
 use color_eyre::eyre::Result;
-use wgpu::{self as g, TextureView};
+use glam::{IVec2, UVec2, Vec2};
+use serde::{Deserialize, Serialize};
+use wgpu as g;
 
-use crate::{app::Context, data::Buffer};
+use crate::{
+    app::Gpu,
+    data::{Buffer, BufferRange, UniformBuffer},
+    impl_wgsl_struct, shader,
+};
 
-use super::{ComputedTransformation, Point};
+use super::{
+    ComputedTransformation, Point, ShaderSources,
+    background::Background,
+    post::{PostPipelines, PostProcess, PostProcessor},
+};
+
+/// How overlapping points combine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    /// The point drawn last wins.
+    #[default]
+    Replace,
+    /// Colors add up, so dense regions get brighter. Saturates quickly without `hdr`.
+    Additive,
+    /// Points are composited over each other by their alpha.
+    Alpha,
+    /// The brightest point wins, per channel.
+    Max,
+}
+
+/// The shape points are drawn as.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Splat {
+    /// A single pixel.
+    #[default]
+    Pixel,
+    /// A disc of `radius` pixels.
+    Disc { radius: f32 },
+    /// A Gaussian falloff cut off at `radius` pixels, three standard deviations out.
+    Gaussian { radius: f32 },
+    /// A square `size` pixels across, what a pixel becomes in supersampled stills.
+    Square { size: f32 },
+}
+
+impl Splat {
+    fn radius(self) -> f32 {
+        match self {
+            Self::Pixel => 0.0,
+            Self::Disc { radius } | Self::Gaussian { radius } => radius,
+            Self::Square { size } => size / 2.0,
+        }
+    }
+
+    /// The same shape with its radius multiplied by `factor`. A pixel grows into a square, so
+    /// that it keeps covering as much of the image.
+    pub(super) fn scaled(self, factor: f32) -> Self {
+        match self {
+            Self::Pixel if factor == 1.0 => Self::Pixel,
+            Self::Pixel => Self::Square { size: factor },
+            Self::Disc { radius } => Self::Disc {
+                radius: radius * factor,
+            },
+            Self::Gaussian { radius } => Self::Gaussian {
+                radius: radius * factor,
+            },
+            Self::Square { size } => Self::Square {
+                size: size * factor,
+            },
+        }
+    }
+
+    /// The matching `SPLAT_*` constant in `render.wgsl`.
+    fn wgsl_kind(self) -> u32 {
+        match self {
+            Self::Pixel => 0,
+            Self::Disc { .. } => 1,
+            Self::Gaussian { .. } => 2,
+            Self::Square { .. } => 3,
+        }
+    }
+}
+
+/// The part of the plane that is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Camera {
+    /// The point drawn at the center of the image.
+    pub center: Vec2,
+    /// 1 shows the square from -1 to 1 around `center`, larger values zoom in.
+    pub zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            center: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+/// The part of the image a render covers, for rendering it in tiles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Viewport {
+    /// The center of the part, in normalized device coordinates of the whole image.
+    pub(super) center: Vec2,
+    /// How many times larger the whole image is than the part.
+    pub(super) scale: Vec2,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            center: Vec2::ZERO,
+            scale: Vec2::ONE,
+        }
+    }
+}
+
+impl Viewport {
+    /// The pixels from `min` up to `max` of an image of `size` pixels, which may reach past its
+    /// edges.
+    pub(super) fn from_pixels(min: IVec2, max: IVec2, size: UVec2) -> Self {
+        let size = size.as_vec2();
+        let sum = (min + max).as_vec2() / size;
+        Self {
+            center: Vec2::new(sum.x - 1.0, 1.0 - sum.y),
+            scale: size / (max - min).as_vec2(),
+        }
+    }
+
+    /// The offset and scale from the texture coordinates of the part to those of the whole image.
+    pub(super) fn uv_transform(self) -> (Vec2, Vec2) {
+        let uv_scale = self.scale.recip();
+        let offset = Vec2::new(
+            self.center.x + 1.0 - uv_scale.x,
+            1.0 - uv_scale.y - self.center.y,
+        ) / 2.0;
+        (offset, uv_scale)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderOptions {
+    pub blend_mode: BlendMode,
+    pub splat: Splat,
+    /// Scales the premultiplied color of every point, to keep additive blending in range.
+    pub intensity: f32,
+    /// Renders into a float16 target first and post-processes it into the destination, so that
+    /// blending happens at full range and precision. Without it points are drawn straight into
+    /// the destination and `post_process` is ignored.
+    pub hdr: bool,
+    pub post_process: PostProcess,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            blend_mode: BlendMode::default(),
+            splat: Splat::default(),
+            intensity: 1.0,
+            hdr: true,
+            post_process: PostProcess::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(super) struct RenderParameters {
+    /// The simulation frame that produced the points, to tell which transformation moved them.
+    last_frame: u32,
+    splat: u32,
+    /// The splat radius in normalized device coordinates.
+    splat_radius: Vec2,
+    camera_center: Vec2,
+    camera_zoom: f32,
+    intensity: f32,
+    viewport_center: Vec2,
+    viewport_scale: Vec2,
+}
+
+impl_wgsl_struct!(RenderParameters {
+    last_frame: u32,
+    splat: u32,
+    splat_radius: Vec2,
+    camera_center: Vec2,
+    camera_zoom: f32,
+    intensity: f32,
+    viewport_center: Vec2,
+    viewport_scale: Vec2,
+});
 
 #[derive(Debug)]
 pub(super) struct Renderer {
+    options: RenderOptions,
+    camera: Camera,
+    viewport: Viewport,
+    parameter_buffer: UniformBuffer<RenderParameters>,
+    bind_group_layout: g::BindGroupLayout,
     bind_group: g::BindGroup,
+    pipeline_layout: g::PipelineLayout,
+    dst_format: g::TextureFormat,
+    source: String,
     pipeline: g::RenderPipeline,
+    post_processor: PostProcessor,
+}
+
+/// The pipelines of a `Renderer`, see `Renderer::compile_shaders`.
+#[derive(Debug)]
+pub(super) struct RendererPipelines {
+    render: g::RenderPipeline,
+    /// Kept to rebuild the pipeline when the render options change.
+    source: String,
+    post: PostPipelines,
 }
 
 impl Renderer {
+    pub(super) const SOURCE: &str = include_str!("render.wgsl");
+    /// Splats are drawn as a triangle strip per point.
+    const SPLAT_VERTICES: u32 = 4;
+
     pub(super) fn new(
         transformations: &Buffer<ComputedTransformation>,
         dst_format: g::TextureFormat,
-        context: &Context,
+        options: RenderOptions,
+        sources: &ShaderSources,
+        gpu: &Gpu,
     ) -> Self {
-        let shader = context
-            .device
-            .create_shader_module(g::include_wgsl!("render.wgsl"));
+        let parameter_buffer = UniformBuffer::new(
+            &RenderParameters::zeroed(),
+            Some("render parameter buffer"),
+            gpu,
+        );
 
         let bind_group_layout =
-            context
-                .device
+            gpu.device
                 .create_bind_group_layout(&g::BindGroupLayoutDescriptor {
                     label: Some("render bind group layout"),
-                    entries: &[g::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: g::ShaderStages::FRAGMENT,
-                        ty: g::BindingType::Buffer {
-                            ty: g::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
+                    entries: &[
+                        g::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: g::ShaderStages::VERTEX_FRAGMENT,
+                            ty: g::BindingType::Buffer {
+                                ty: g::BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        g::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: g::ShaderStages::VERTEX_FRAGMENT,
+                            ty: g::BindingType::Buffer {
+                                ty: g::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
                         },
-                        count: None,
-                    }],
+                    ],
                 });
 
-        let bind_group = context.device.create_bind_group(&g::BindGroupDescriptor {
-            label: Some("render bind group"),
-            layout: &bind_group_layout,
-            entries: &[g::BindGroupEntry {
-                binding: 0,
-                resource: transformations.as_entire_binding(),
-            }],
-        });
+        let bind_group =
+            Self::create_bind_group(&bind_group_layout, transformations, &parameter_buffer, gpu);
 
-        let pipeline_layout = context
+        let pipeline_layout = gpu
             .device
             .create_pipeline_layout(&g::PipelineLayoutDescriptor {
                 label: Some("render pipeline layout"),
@@ -57,24 +279,186 @@ impl Renderer {
                 push_constant_ranges: &[],
             });
 
+        let pipeline =
+            Self::create_pipeline(&pipeline_layout, dst_format, options, &sources.render, gpu);
+
+        let post_processor = PostProcessor::new(dst_format, options.post_process, sources, gpu);
+
+        Self {
+            options,
+            camera: Camera::default(),
+            viewport: Viewport::default(),
+            parameter_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline_layout,
+            dst_format,
+            source: sources.render.clone(),
+            pipeline,
+            post_processor,
+        }
+    }
+
+    pub(super) fn options(&self) -> RenderOptions {
+        self.options
+    }
+
+    /// Only rebuilds the pipeline if the blend mode, the kind of splat or `hdr` changed, so that
+    /// the other options can change every frame.
+    pub(super) fn set_options(&mut self, options: RenderOptions, gpu: &Gpu) {
+        let pipeline_changed = options.blend_mode != self.options.blend_mode
+            || options.splat.wgsl_kind() != self.options.splat.wgsl_kind()
+            || options.hdr != self.options.hdr;
+        if pipeline_changed {
+            self.pipeline = Self::create_pipeline(
+                &self.pipeline_layout,
+                self.dst_format,
+                options,
+                &self.source,
+                gpu,
+            );
+        }
+        self.post_processor
+            .set_post_process(options.post_process, gpu);
+        if !options.hdr {
+            self.post_processor.drop_target();
+        }
+        self.options = options;
+    }
+
+    pub(super) fn camera(&self) -> Camera {
+        self.camera
+    }
+
+    /// Moves the camera, starting the trails over if it changed.
+    pub(super) fn set_camera(&mut self, camera: Camera) {
+        if camera != self.camera {
+            self.post_processor.reset_trails();
+        }
+        self.camera = camera;
+    }
+
+    /// Moves the camera keeping the trails, for gradual moves they can follow.
+    pub(super) fn move_camera(&mut self, camera: Camera) {
+        self.camera = camera;
+    }
+
+    /// Restricts the renders to a part of the image, or back to all of it with the default.
+    pub(super) fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = viewport;
+    }
+
+    pub(super) fn dst_format(&self) -> g::TextureFormat {
+        self.dst_format
+    }
+
+    /// Frees the HDR target and the textures of the passes that depend on its size, until the
+    /// next render.
+    pub(super) fn drop_targets(&mut self) {
+        self.post_processor.drop_target();
+    }
+
+    /// Starts the trails over from the next frame.
+    pub(super) fn reset_trails(&mut self) {
+        self.post_processor.reset_trails();
+    }
+
+    /// Builds the pipelines of the points and the post-processing from `sources`, to swap in
+    /// with `set_pipelines`.
+    pub(super) fn compile_shaders(
+        &self,
+        sources: &ShaderSources,
+        gpu: &Gpu,
+    ) -> Result<RendererPipelines> {
+        Ok(RendererPipelines {
+            render: shader::catch_validation_errors(gpu, || {
+                Self::create_pipeline(
+                    &self.pipeline_layout,
+                    self.dst_format,
+                    self.options,
+                    &sources.render,
+                    gpu,
+                )
+            })?,
+            source: sources.render.clone(),
+            post: self.post_processor.compile_shaders(sources, gpu)?,
+        })
+    }
+
+    pub(super) fn set_pipelines(&mut self, pipelines: RendererPipelines) {
+        self.pipeline = pipelines.render;
+        self.source = pipelines.source;
+        self.post_processor.set_pipelines(pipelines.post);
+    }
+
+    pub(super) fn background(&self) -> &Background {
+        self.post_processor.background()
+    }
+
+    pub(super) fn set_background(&mut self, background: Background, gpu: &Gpu) {
+        self.post_processor.set_background(background, gpu);
+    }
+
+    fn create_pipeline(
+        layout: &g::PipelineLayout,
+        dst_format: g::TextureFormat,
+        options: RenderOptions,
+        source: &str,
+        gpu: &Gpu,
+    ) -> g::RenderPipeline {
+        let shader = gpu.device.create_shader_module(g::ShaderModuleDescriptor {
+            label: Some("render.wgsl"),
+            source: g::ShaderSource::Wgsl(source.into()),
+        });
+
+        let (entry_point, step_mode, topology) = match options.splat {
+            Splat::Pixel => (
+                "vertex",
+                g::VertexStepMode::Vertex,
+                g::PrimitiveTopology::PointList,
+            ),
+            Splat::Disc { .. } | Splat::Gaussian { .. } | Splat::Square { .. } => (
+                "vertex_splat",
+                g::VertexStepMode::Instance,
+                g::PrimitiveTopology::TriangleStrip,
+            ),
+        };
         let vertex_buffer_layout = g::VertexBufferLayout {
             array_stride: mem::size_of::<Point>() as u64,
-            step_mode: g::VertexStepMode::Vertex,
+            step_mode,
             attributes: &g::vertex_attr_array![0 => Float32x2],
         };
 
-        let pipeline = context
-            .device
+        let add = |operation| g::BlendComponent {
+            src_factor: g::BlendFactor::One,
+            dst_factor: g::BlendFactor::One,
+            operation,
+        };
+        let blend = match options.blend_mode {
+            BlendMode::Replace => g::BlendState::REPLACE,
+            BlendMode::Additive => g::BlendState {
+                color: add(g::BlendOperation::Add),
+                alpha: add(g::BlendOperation::Add),
+            },
+            // the fragment shader outputs premultiplied colors
+            BlendMode::Alpha => g::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Max => g::BlendState {
+                color: add(g::BlendOperation::Max),
+                alpha: add(g::BlendOperation::Max),
+            },
+        };
+
+        gpu.device
             .create_render_pipeline(&g::RenderPipelineDescriptor {
                 label: Some("render pipeline"),
-                layout: Some(&pipeline_layout),
+                layout: Some(layout),
                 primitive: g::PrimitiveState {
-                    topology: g::PrimitiveTopology::PointList,
+                    topology,
                     ..Default::default()
                 },
                 vertex: g::VertexState {
                     module: &shader,
-                    entry_point: Some("vertex"),
+                    entry_point: Some(entry_point),
                     compilation_options: Default::default(),
                     buffers: &[vertex_buffer_layout],
                 },
@@ -83,8 +467,12 @@ impl Renderer {
                     entry_point: Some("fragment"),
                     compilation_options: Default::default(),
                     targets: &[Some(g::ColorTargetState {
-                        format: dst_format,
-                        blend: Some(g::BlendState::REPLACE),
+                        format: if options.hdr {
+                            PostProcessor::HDR_FORMAT
+                        } else {
+                            dst_format
+                        },
+                        blend: Some(blend),
                         write_mask: g::ColorWrites::ALL,
                     })],
                 }),
@@ -92,45 +480,120 @@ impl Renderer {
                 multisample: Default::default(),
                 multiview: None,
                 cache: None,
-            });
+            })
+    }
 
-        Self {
-            bind_group,
-            pipeline,
-        }
+    pub(super) fn set_transformations(
+        &mut self,
+        transformations: &Buffer<ComputedTransformation>,
+        gpu: &Gpu,
+    ) {
+        self.bind_group = Self::create_bind_group(
+            &self.bind_group_layout,
+            transformations,
+            &self.parameter_buffer,
+            gpu,
+        );
+    }
+
+    fn create_bind_group(
+        layout: &g::BindGroupLayout,
+        transformations: &Buffer<ComputedTransformation>,
+        parameters: &UniformBuffer<RenderParameters>,
+        gpu: &Gpu,
+    ) -> g::BindGroup {
+        gpu.device.create_bind_group(&g::BindGroupDescriptor {
+            label: Some("render bind group"),
+            layout,
+            entries: &[
+                g::BindGroupEntry {
+                    binding: 0,
+                    resource: transformations.as_entire_binding(),
+                },
+                g::BindGroupEntry {
+                    binding: 1,
+                    resource: parameters.as_entire_binding(),
+                },
+            ],
+        })
     }
 
+    /// Renders `points` as last moved by simulation frame `last_frame`.
     pub(super) fn render(
-        &self,
-        points: &Buffer<Point>,
-        dst: &TextureView,
-        context: &Context,
+        &mut self,
+        points: BufferRange<'_, Point>,
+        last_frame: u32,
+        dst: &g::Texture,
+        gpu: &Gpu,
     ) -> Result<()> {
-        let mut encoder = context
+        let size = Vec2::new(dst.width() as f32, dst.height() as f32);
+        self.parameter_buffer.write(
+            &RenderParameters {
+                last_frame,
+                splat: self.options.splat.wgsl_kind(),
+                splat_radius: self.options.splat.radius() * 2.0 / size,
+                camera_center: self.camera.center,
+                camera_zoom: self.camera.zoom,
+                intensity: self.options.intensity,
+                viewport_center: self.viewport.center,
+                viewport_scale: self.viewport.scale,
+            },
+            gpu,
+        );
+
+        let dst_view = dst.create_view(&g::TextureViewDescriptor {
+            label: Some("render target view"),
+            ..Default::default()
+        });
+        let hdr_target = self
+            .options
+            .hdr
+            .then(|| self.post_processor.target(dst.size(), gpu).clone());
+
+        let mut encoder = gpu
             .device
             .create_command_encoder(&g::CommandEncoderDescriptor {
                 label: Some("render command encoder"),
             });
+        // the HDR target starts out transparent, the background goes under it in post-processing
+        let load = if hdr_target.is_some() {
+            g::LoadOp::Clear(g::Color::TRANSPARENT)
+        } else {
+            self.post_processor
+                .fill_background(&mut encoder, &dst_view, self.viewport, gpu);
+            g::LoadOp::Load
+        };
         {
             let mut render_pass = encoder.begin_render_pass(&g::RenderPassDescriptor {
                 label: Some("render pass"),
                 color_attachments: &[Some(g::RenderPassColorAttachment {
-                    view: dst,
+                    view: hdr_target.as_ref().unwrap_or(&dst_view),
                     resolve_target: None,
                     ops: g::Operations {
-                        load: g::LoadOp::Clear(g::Color::BLACK),
+                        load,
                         store: g::StoreOp::Store,
                     },
                 })],
                 ..Default::default()
             });
             render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_vertex_buffer(0, points.slice(..));
+            render_pass.set_vertex_buffer(0, points.slice());
             render_pass.set_bind_group(0, &self.bind_group, &[]);
-            render_pass.draw(0..(points.len() as u32), 0..1);
+            let n_points = points.len() as u32;
+            match self.options.splat {
+                Splat::Pixel => render_pass.draw(0..n_points, 0..1),
+                Splat::Disc { .. } | Splat::Gaussian { .. } | Splat::Square { .. } => {
+                    render_pass.draw(0..Self::SPLAT_VERTICES, 0..n_points)
+                }
+            }
+        }
+
+        if hdr_target.is_some() {
+            self.post_processor
+                .apply(&mut encoder, &dst_view, self.viewport, gpu);
         }
 
-        context.queue.submit(iter::once(encoder.finish()));
+        gpu.queue.submit(iter::once(encoder.finish()));
         Ok(())
     }
 }
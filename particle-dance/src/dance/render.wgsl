@@ -1,36 +1,80 @@
-struct Transformation {
-    center: vec2f,
-    scale: f32,
-    angle: f32,
-    color: vec4f,
-    matrix: mat3x3f,
-}
+#import hash
+#import streams
+#import ComputedTransformation
+#import RenderParameters
+#import color
+
+const SPLAT_PIXEL: u32 = 0u;
+const SPLAT_DISC: u32 = 1u;
+const SPLAT_GAUSSIAN: u32 = 2u;
+const SPLAT_SQUARE: u32 = 3u;
 
-@group(0) @binding(0) var<storage> transformations: array<Transformation>;
+@group(0) @binding(0) var<storage> transformations: array<ComputedTransformation>;
+@group(0) @binding(1) var<uniform> parameters: RenderParameters;
 
 struct Vertex {
     @builtin(position) position: vec4f,
     @location(0) point: vec2f,
+    @location(1) @interpolate(flat) transformation_idx: u32,
+    // position within the splat, in units of its radius
+    @location(2) offset: vec2f,
+}
+
+// where `point` ends up in normalized device coordinates
+fn view(point: vec2f) -> vec2f {
+    let image = (point - parameters.camera_center) * parameters.camera_zoom;
+    return (image - parameters.viewport_center) * parameters.viewport_scale;
+}
+
+// the transformation that moved the point in the last simulation step, see `sim.wgsl`
+fn last_transformation(index: u32) -> u32 {
+    let selection = hash(parameters.last_frame, SELECTION_STREAM, index);
+    return selection % arrayLength(&transformations);
+}
+
+@vertex
+fn vertex(@builtin(vertex_index) index: u32, @location(0) point: vec2f) -> Vertex {
+    var v: Vertex;
+    v.position = vec4f(view(point), 0.0, 1.0);
+    v.point = point;
+    v.transformation_idx = last_transformation(index);
+    v.offset = vec2f(0.0);
+    return v;
 }
 
+// one quad per point, drawn as a triangle strip
 @vertex
-fn vertex(@location(0) point: vec2f) -> Vertex {
+fn vertex_splat(
+    @builtin(vertex_index) corner: u32,
+    @builtin(instance_index) index: u32,
+    @location(0) point: vec2f,
+) -> Vertex {
+    let offset = vec2f(f32(corner & 1u), f32(corner >> 1u)) * 2.0 - 1.0;
     var v: Vertex;
-    v.position = vec4f(point, 0.0, 1.0);
+    v.position = vec4f(view(point) + offset * parameters.splat_radius, 0.0, 1.0);
     v.point = point;
+    v.transformation_idx = last_transformation(index);
+    v.offset = offset;
     return v;
 }
 
 @fragment
-fn fragment(@location(0) point: vec2f) -> @location(0) vec4f {
-    var totalLength: f32 = 0.0;
-    var color = vec4f(0.0);
-    for (var i: u32 = 0; i < arrayLength(&transformations); i++) {
-        let s = (point.xy - transformations[i].center);
-        let t = 1. / dot(s, s);
-        // return vec4f(t, 0., 0., 1.);
-        color += t * transformations[i].color;
-        totalLength += t;
+fn fragment(
+    @location(0) point: vec2f,
+    @location(1) @interpolate(flat) transformation_idx: u32,
+    @location(2) offset: vec2f,
+) -> @location(0) vec4f {
+    var weight = parameters.intensity;
+    let r2 = dot(offset, offset);
+    if parameters.splat != SPLAT_PIXEL && parameters.splat != SPLAT_SQUARE {
+        if r2 > 1.0 {
+            discard;
+        }
+        if parameters.splat == SPLAT_GAUSSIAN {
+            // the radius is three standard deviations
+            weight *= exp(-4.5 * r2);
+        }
     }
-    return color / totalLength;
+    let c = color(point, transformation_idx);
+    return vec4f(c.rgb * c.a, c.a) * weight;
 }
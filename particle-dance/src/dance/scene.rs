@@ -0,0 +1,76 @@
+use std::{fs, path::Path};
+
+use color_eyre::eyre::{Result, WrapErr, ensure};
+use glam::Vec4;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Transformation, background::Background, render::RenderOptions, snippets::Snippets,
+    transformations::TransformationGenerator,
+};
+
+/// A generated dance saved to a JSON file, to be opened again in the window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    /// The seed of the `TransformationGenerator`.
+    pub seed: u32,
+    /// One per transformation.
+    pub colors: Vec<Vec4>,
+    /// The generator time the dance starts at.
+    #[serde(default)]
+    pub time: f32,
+    /// Loops the dance over this many keyframes, see `TransformationGenerator::looping`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub period: Option<u32>,
+    pub n_points: usize,
+    /// Replaces the default shader snippets of the window.
+    #[serde(default)]
+    pub snippets: Snippets,
+    /// How the points are drawn, including the post-processing.
+    #[serde(default)]
+    pub render_options: RenderOptions,
+    /// What the points are drawn over, any but an image.
+    #[serde(default)]
+    pub background: Background,
+}
+
+impl Scene {
+    /// The points of the window.
+    pub const DEFAULT_N_POINTS: usize = 2_000_000;
+
+    pub fn generator(&self) -> TransformationGenerator {
+        let generator = TransformationGenerator::with_seed(self.colors.clone(), self.seed);
+        match self.period {
+            Some(period) => generator.looping(period),
+            None => generator,
+        }
+    }
+
+    /// The transformations at the start of the dance.
+    pub fn transformations(&self) -> Vec<Transformation> {
+        self.generator().generate(self.time)
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        let scene: Self = serde_json::from_str(&json)
+            .wrap_err_with(|| format!("failed to parse {}", path.display()))?;
+        ensure!(
+            !scene.colors.is_empty(),
+            "{}: a transformation set needs at least one transformation",
+            path.display()
+        );
+        ensure!(
+            scene.period != Some(0),
+            "{}: a loop needs at least one keyframe",
+            path.display()
+        );
+        Ok(scene)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json + "\n").wrap_err_with(|| format!("failed to write {}", path.display()))
+    }
+}
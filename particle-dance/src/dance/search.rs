@@ -0,0 +1,259 @@
+use std::ops::Range;
+
+use color_eyre::eyre::{Result, ensure};
+use glam::{Vec3, vec3};
+use itertools::Itertools;
+
+use crate::{app::Gpu, image::Image, random::Rng};
+
+use super::{
+    Dance, analysis::AttractorStatistics, background::Background, cpu::CpuSimulator,
+    offscreen::OffscreenTarget, render::RenderOptions, scene::Scene, seeding::PointDistribution,
+    snippets::Snippets, transformations::TransformationGenerator,
+};
+
+/// Heuristics for how interesting the thumbnail of an attractor looks, each from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score {
+    /// The weighted mean of the others, 0 when the points escape or nothing is in view.
+    pub total: f32,
+    /// Highest when about a quarter of the image is lit, lower for specks and filled frames.
+    pub coverage: f32,
+    /// Highest for a box-counting dimension around 1.6, lower for dust, lines and filled areas.
+    pub dimension: f32,
+    /// The fraction of the lit pixels on an edge, low for flat blobs.
+    pub edge_density: f32,
+    /// How much the colors of the lit pixels vary, saturating at a spread of about a fifth of the
+    /// range.
+    pub color_variance: f32,
+}
+
+impl Score {
+    const COVERAGE_TARGET: f32 = 0.25;
+    const DIMENSION_TARGET: f32 = 1.6;
+    const DIMENSION_TOLERANCE: f32 = 0.6;
+    /// The luminance difference between neighboring pixels that counts as an edge.
+    const EDGE_THRESHOLD: f32 = 0.1;
+    const FULL_COLOR_VARIANCE: f32 = 0.05;
+
+    /// Scores a thumbnail rendered over a black background.
+    pub fn new(thumbnail: &Image, statistics: &AttractorStatistics) -> Self {
+        let (width, height) = (thumbnail.width(), thumbnail.height());
+        let luminance = |x: u32, y: u32| {
+            thumbnail
+                .get(x, y)
+                .truncate()
+                .dot(vec3(0.2126, 0.7152, 0.0722))
+        };
+        let lit = thumbnail
+            .pixels()
+            .iter()
+            .filter(|pixel| pixel.truncate().max_element() > 0.0)
+            .map(|pixel| pixel.truncate())
+            .collect_vec();
+        if lit.is_empty() || statistics.lyapunov_exponent >= 0.0 {
+            return Self {
+                total: 0.0,
+                coverage: 0.0,
+                dimension: 0.0,
+                edge_density: 0.0,
+                color_variance: 0.0,
+            };
+        }
+
+        let lit_fraction = lit.len() as f32 / thumbnail.pixels().len() as f32;
+        let coverage = peak(lit_fraction, Self::COVERAGE_TARGET, Self::COVERAGE_TARGET);
+        let dimension = peak(
+            statistics.box_counting_dimension,
+            Self::DIMENSION_TARGET,
+            Self::DIMENSION_TOLERANCE,
+        );
+
+        let n_edges = (0..height)
+            .cartesian_product(0..width)
+            .filter(|&(y, x)| {
+                let l = luminance(x, y);
+                l > 0.0
+                    && [
+                        (x.wrapping_sub(1), y),
+                        (x + 1, y),
+                        (x, y.wrapping_sub(1)),
+                        (x, y + 1),
+                    ]
+                    .into_iter()
+                    .filter(|&(x, y)| x < width && y < height)
+                    .any(|(x, y)| (luminance(x, y) - l).abs() > Self::EDGE_THRESHOLD)
+            })
+            .count();
+        let edge_density = n_edges as f32 / lit.len() as f32;
+
+        let mean = lit.iter().sum::<Vec3>() / lit.len() as f32;
+        let variance = lit
+            .iter()
+            .map(|&color| color.distance_squared(mean))
+            .sum::<f32>()
+            / lit.len() as f32;
+        let color_variance = (variance / Self::FULL_COLOR_VARIANCE).min(1.0);
+
+        Self {
+            total: 0.3 * coverage + 0.3 * dimension + 0.2 * edge_density + 0.2 * color_variance,
+            coverage,
+            dimension,
+            edge_density,
+            color_variance,
+        }
+    }
+}
+
+/// 1 at `target`, falling off linearly to 0 at `tolerance` from it.
+fn peak(value: f32, target: f32, tolerance: f32) -> f32 {
+    (1.0 - (value - target).abs() / tolerance).max(0.0)
+}
+
+/// Tries many seeds for the `TransformationGenerator`, rendering each one small, and keeps the
+/// most interesting by their `Score`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeedSearch {
+    pub seeds: Range<u32>,
+    /// How many of the best seeds are kept.
+    pub top: usize,
+    pub n_transformations: usize,
+    /// The generator time the transformations are taken at.
+    pub time: f32,
+    /// The points simulated for each seed, far fewer than the window needs.
+    pub n_points: usize,
+    /// The steps simulated for the points to settle on the attractor.
+    pub steps: u32,
+    /// The width and height of the thumbnails.
+    pub thumbnail_size: u32,
+}
+
+impl SeedSearch {
+    pub fn new(seeds: Range<u32>) -> Self {
+        Self {
+            seeds,
+            top: 10,
+            n_transformations: TransformationGenerator::DEFAULT_COLORS.len(),
+            time: 0.0,
+            n_points: 20_000,
+            steps: 30,
+            thumbnail_size: 128,
+        }
+    }
+
+    /// The scene of `seed`, with the points of the window rather than of the search.
+    pub fn scene(&self, seed: u32) -> Scene {
+        Scene {
+            seed,
+            colors: TransformationGenerator::default_colors(self.n_transformations),
+            time: self.time,
+            period: None,
+            n_points: Scene::DEFAULT_N_POINTS,
+            snippets: Snippets::default(),
+            render_options: RenderOptions::default(),
+            background: Background::default(),
+        }
+    }
+}
+
+/// A seed tried by a `SeedSearch`.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub scene: Scene,
+    pub score: Score,
+    pub statistics: AttractorStatistics,
+    pub thumbnail: Image,
+}
+
+// reading the points back would block the browser
+#[cfg(not(target_arch = "wasm32"))]
+impl SeedSearch {
+    /// Scores every seed, simulating on `gpu` or on the CPU without one, and returns the `top`
+    /// best first. `progress` sees every candidate as it is scored.
+    pub fn run(
+        &self,
+        gpu: Option<&Gpu>,
+        mut progress: impl FnMut(&Candidate),
+    ) -> Result<Vec<Candidate>> {
+        ensure!(
+            self.n_transformations > 0,
+            "a transformation set needs at least one transformation"
+        );
+        ensure!(
+            self.n_points > 0 && self.thumbnail_size > 0,
+            "a search needs points and a thumbnail size of at least 1"
+        );
+        let mut renderer = gpu.map(|gpu| ThumbnailRenderer::new(self, gpu));
+        let mut best = Vec::<Candidate>::new();
+        for seed in self.seeds.clone() {
+            let candidate = self.evaluate(seed, renderer.as_mut(), gpu)?;
+            progress(&candidate);
+            let index = best.partition_point(|other| other.score.total >= candidate.score.total);
+            if index < self.top {
+                best.insert(index, candidate);
+                best.truncate(self.top);
+            }
+        }
+        Ok(best)
+    }
+
+    fn evaluate(
+        &self,
+        seed: u32,
+        renderer: Option<&mut ThumbnailRenderer>,
+        gpu: Option<&Gpu>,
+    ) -> Result<Candidate> {
+        let scene = self.scene(seed);
+        let transformations = scene.transformations();
+        let points = PointDistribution::Square.sample(self.n_points, &mut Rng::with_seed(seed));
+
+        let (points, thumbnail) = match (renderer, gpu) {
+            (Some(renderer), Some(gpu)) => {
+                let dance = &mut renderer.dance;
+                dance.write_points(&points, gpu);
+                dance.write_transformations(&transformations, gpu);
+                dance.simulator.set_frame(0);
+                for _ in 0..self.steps {
+                    dance.step(gpu);
+                }
+                dance.render(renderer.target.texture(), gpu)?;
+                (dance.read_points(gpu)?, renderer.target.read(gpu)?)
+            }
+            _ => {
+                let mut simulator = CpuSimulator::new(points, &transformations);
+                for _ in 0..self.steps {
+                    simulator.step();
+                }
+                let thumbnail = simulator.render(self.thumbnail_size, self.thumbnail_size);
+                (simulator.points().to_vec(), thumbnail)
+            }
+        };
+
+        let statistics = AttractorStatistics::from_points(&points, &transformations);
+        Ok(Candidate {
+            scene,
+            score: Score::new(&thumbnail, &statistics),
+            statistics,
+            thumbnail,
+        })
+    }
+}
+
+/// A dance and target reused for every seed, rather than compiling the pipelines each time.
+#[cfg(not(target_arch = "wasm32"))]
+struct ThumbnailRenderer {
+    dance: Dance,
+    target: OffscreenTarget,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ThumbnailRenderer {
+    fn new(search: &SeedSearch, gpu: &Gpu) -> Self {
+        let points = PointDistribution::Square.sample(search.n_points, &mut Rng::with_seed(0));
+        let transformations = search.scene(0).transformations();
+        Self {
+            dance: Dance::new(&points, &transformations, OffscreenTarget::FORMAT, gpu),
+            target: OffscreenTarget::new(search.thumbnail_size, search.thumbnail_size, gpu),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::iter;
+
+use glam::{Vec2, vec2, vec3};
+use itertools::Itertools;
+
+use crate::{image::Image, random::Rng};
+
+use super::Point;
+
+#[derive(Debug, Clone)]
+pub enum PointDistribution {
+    /// Uniform over the `[-1, 1]²` square.
+    Square,
+    /// Uniform over the unit disc.
+    Disc,
+    /// Normal distribution centered on the origin.
+    Gaussian { std_dev: f32 },
+    /// Proportional to the weights of an image stretched over the `[-1, 1]²` square.
+    Mask(ImageMask),
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageMask {
+    width: u32,
+    height: u32,
+    weights: Vec<f32>,
+}
+
+impl ImageMask {
+    /// `weights` are in row-major order, starting from the top row.
+    pub fn new(width: u32, height: u32, weights: Vec<f32>) -> Self {
+        assert_eq!(weights.len(), width as usize * height as usize);
+        assert!(weights.iter().all(|&weight| weight >= 0.0));
+        Self {
+            width,
+            height,
+            weights,
+        }
+    }
+
+    /// Weighs each pixel of `image` by its luminance. The pixels are premultiplied, so that
+    /// transparent ones weigh nothing.
+    pub fn from_image(image: &Image) -> Self {
+        let weights = image
+            .pixels()
+            .iter()
+            .map(|pixel| pixel.truncate().dot(vec3(0.2126, 0.7152, 0.0722)).max(0.0))
+            .collect_vec();
+        Self::new(image.width(), image.height(), weights)
+    }
+
+    /// The density of `points` over the `[-1, 1]²` square, ignoring the points outside of it.
+    pub fn from_points(points: &[Point], width: u32, height: u32) -> Self {
+        let mut weights = vec![0.0; width as usize * height as usize];
+        for point in points {
+            let x = (point.pos.x + 1.0) * 0.5 * width as f32;
+            let y = (1.0 - point.pos.y) * 0.5 * height as f32;
+            if (0.0..width as f32).contains(&x) && (0.0..height as f32).contains(&y) {
+                weights[y as usize * width as usize + x as usize] += 1.0;
+            }
+        }
+        Self::new(width, height, weights)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl PointDistribution {
+    pub fn sample(&self, n_points: usize, rng: &mut Rng) -> Vec<Point> {
+        match self {
+            Self::Square => iter::repeat_with(|| rng.random::<Vec2>() * 2.0 - 1.0)
+                .map(|pos| Point { pos })
+                .take(n_points)
+                .collect_vec(),
+
+            Self::Disc => iter::repeat_with(|| rng.random_unit_disk())
+                .map(|pos| Point { pos })
+                .take(n_points)
+                .collect_vec(),
+
+            &Self::Gaussian { std_dev } => iter::repeat_with(|| rng.random_gaussian_pair())
+                .map(|pos| Point { pos: pos * std_dev })
+                .take(n_points)
+                .collect_vec(),
+
+            Self::Mask(mask) => mask.sample(n_points, rng),
+        }
+    }
+}
+
+impl ImageMask {
+    fn sample(&self, n_points: usize, rng: &mut Rng) -> Vec<Point> {
+        let cumulative_weights = self
+            .weights
+            .iter()
+            .scan(0.0, |sum, &weight| {
+                *sum += weight;
+                Some(*sum)
+            })
+            .collect_vec();
+        let total_weight = cumulative_weights.last().copied().unwrap_or(0.0);
+        if total_weight <= 0.0 {
+            return PointDistribution::Square.sample(n_points, rng);
+        }
+
+        let size = vec2(self.width as f32, self.height as f32);
+        iter::repeat_with(|| {
+            let target = rng.random::<f32>() * total_weight;
+            let idx = cumulative_weights
+                .partition_point(|&weight| weight <= target)
+                .min(self.weights.len() - 1);
+            let pixel = vec2(
+                (idx % self.width as usize) as f32,
+                (idx / self.width as usize) as f32,
+            );
+            let uv = (pixel + rng.random::<Vec2>()) / size;
+            Point {
+                pos: vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0),
+            }
+        })
+        .take(n_points)
+        .collect_vec()
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    f32::consts::{PI, TAU},
+    fs,
+    path::Path,
+};
+
+use color_eyre::eyre::{Result, WrapErr, ensure};
+use glam::Vec4;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Transformation,
+    bloom::Bloom,
+    post::PostProcess,
+    render::{Camera, RenderOptions, Splat},
+    trails::Trails,
+    transformations::TransformationGenerator,
+};
+
+/// A scripted show: cues played one after the other, each crossfading from the one before.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sequence {
+    pub cues: Vec<Cue>,
+    /// Starts over after the last cue, transitioning from it into the first one like between any
+    /// other two. Otherwise the last cue is held.
+    #[serde(default)]
+    pub looping: bool,
+}
+
+/// A part of a `Sequence`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cue {
+    pub transformations: CueTransformations,
+    /// The camera of the previous cue when left out, the default one for the first.
+    #[serde(default)]
+    pub camera: Option<Camera>,
+    /// The render options of the previous cue when left out, the defaults for the first.
+    #[serde(default)]
+    pub render_options: Option<RenderOptions>,
+    /// The seconds from the start of the transition into the cue to the start of the next one.
+    pub duration: f32,
+    /// How the cue takes over from the previous one.
+    #[serde(default)]
+    pub transition: Transition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CueTransformations {
+    /// Animated by a `TransformationGenerator`, like a `Scene`.
+    Generated {
+        seed: u32,
+        /// One per transformation.
+        colors: Vec<Vec4>,
+        /// The generator time the cue starts at.
+        #[serde(default)]
+        time: f32,
+        /// The generator time per second of the cue.
+        #[serde(default = "CueTransformations::default_speed")]
+        speed: f32,
+        /// See `TransformationGenerator::looping`.
+        #[serde(default)]
+        period: Option<u32>,
+    },
+    /// Held as they are.
+    Fixed(Vec<Transformation>),
+}
+
+impl CueTransformations {
+    fn default_speed() -> f32 {
+        0.1
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Generated { colors, .. } => colors.len(),
+            Self::Fixed(transformations) => transformations.len(),
+        }
+    }
+
+    /// The transformations `elapsed` seconds after the start of the cue.
+    fn at(&self, elapsed: f32) -> Vec<Transformation> {
+        match self {
+            &Self::Generated {
+                seed,
+                ref colors,
+                time,
+                speed,
+                period,
+            } => {
+                let generator = TransformationGenerator::with_seed(colors.clone(), seed);
+                match period {
+                    Some(period) => generator.looping(period),
+                    None => generator,
+                }
+                .generate(time + speed * elapsed)
+            }
+            Self::Fixed(transformations) => transformations.clone(),
+        }
+    }
+
+    fn colors(&self) -> Vec<Vec4> {
+        match self {
+            Self::Generated { colors, .. } => colors.clone(),
+            Self::Fixed(transformations) => transformations
+                .iter()
+                .map(|transformation| transformation.color)
+                .collect_vec(),
+        }
+    }
+}
+
+/// The crossfade into a cue, a cut when it takes no time.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transition {
+    /// In seconds, at most the duration of the cue.
+    pub duration: f32,
+    pub curve: Curve,
+}
+
+/// How a transition progresses over its duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    #[default]
+    Linear,
+    /// Starts slowly.
+    EaseIn,
+    /// Ends slowly.
+    EaseOut,
+    /// Starts and ends slowly.
+    EaseInOut,
+}
+
+impl Curve {
+    /// The progress at the fraction `t` of the duration, both from 0 to 1.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => t * (2.0 - t),
+            Self::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// What a `Sequence` shows at some point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequenceState {
+    pub transformations: Vec<Transformation>,
+    pub camera: Camera,
+    pub render_options: RenderOptions,
+}
+
+impl Sequence {
+    /// The speed of the clock a sequence is played by, its durations being in seconds.
+    pub const SPEED: f32 = 1.0;
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        let sequence: Self = serde_json::from_str(&json)
+            .wrap_err_with(|| format!("failed to parse {}", path.display()))?;
+        sequence
+            .validate()
+            .wrap_err_with(|| format!("invalid sequence {}", path.display()))?;
+        Ok(sequence)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json + "\n").wrap_err_with(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Checks that there are cues, that each has transformations and that their timings make
+    /// sense.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.cues.is_empty(), "a sequence needs at least one cue");
+        for (i, cue) in self.cues.iter().enumerate() {
+            ensure!(
+                cue.transformations.len() > 0,
+                "cue {i}: a transformation set needs at least one transformation"
+            );
+            ensure!(
+                cue.duration.is_finite() && cue.duration > 0.0,
+                "cue {i}: the duration must be positive"
+            );
+            if let Some(camera) = cue.camera {
+                ensure!(
+                    camera.zoom.is_finite() && camera.zoom > 0.0,
+                    "cue {i}: the camera zoom must be positive"
+                );
+            }
+            ensure!(
+                (0.0..=cue.duration).contains(&cue.transition.duration),
+                "cue {i}: the transition must take from 0 to the duration of the cue"
+            );
+            if let CueTransformations::Generated { speed, period, .. } = cue.transformations {
+                ensure!(speed.is_finite(), "cue {i}: the speed must be finite");
+                ensure!(
+                    period != Some(0),
+                    "cue {i}: a loop needs at least one keyframe"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The seconds the cues take together.
+    pub fn duration(&self) -> f32 {
+        self.cues.iter().map(|cue| cue.duration).sum()
+    }
+
+    /// The colors of the first cue.
+    pub fn colors(&self) -> Vec<Vec4> {
+        self.cues[0].transformations.colors()
+    }
+
+    /// The index of the cue shown `time` seconds in, and the seconds since it started. Before
+    /// the start is the start, after the end the last cue unless looping.
+    pub fn cue_at(&self, time: f32) -> (usize, f32) {
+        assert!(!self.cues.is_empty(), "a sequence needs at least one cue");
+        let time = if self.looping {
+            time.rem_euclid(self.duration())
+        } else {
+            time.max(0.0)
+        };
+        let mut start = 0.0;
+        for (i, cue) in self.cues.iter().enumerate() {
+            if time < start + cue.duration {
+                return (i, time - start);
+            }
+            start += cue.duration;
+        }
+        // past the end, or at it after rounding in a loop
+        let last = self.cues.len() - 1;
+        if self.looping {
+            (0, 0.0)
+        } else {
+            (last, time - (start - self.cues[last].duration))
+        }
+    }
+
+    /// What is shown `time` seconds in: the cue at that time, crossfaded from the one before it
+    /// while transitioning.
+    pub fn at(&self, time: f32) -> SequenceState {
+        let (index, elapsed) = self.cue_at(time);
+        let cue = &self.cues[index];
+        let state = self.cue_state(index, elapsed);
+
+        let previous = match index {
+            0 if self.looping => Some(self.cues.len() - 1),
+            0 => None,
+            _ => Some(index - 1),
+        };
+        match previous {
+            Some(previous) if elapsed < cue.transition.duration => {
+                let from = self.cue_state(previous, self.cues[previous].duration + elapsed);
+                let s = cue
+                    .transition
+                    .curve
+                    .apply(elapsed / cue.transition.duration);
+                from.crossfade(&state, s)
+            }
+            _ => state,
+        }
+    }
+
+    /// Cue `index` on its own, `elapsed` seconds after it started.
+    fn cue_state(&self, index: usize, elapsed: f32) -> SequenceState {
+        let mut cues = self.cues[..=index].iter().rev();
+        SequenceState {
+            transformations: self.cues[index].transformations.at(elapsed),
+            camera: cues.clone().find_map(|cue| cue.camera).unwrap_or_default(),
+            render_options: cues.find_map(|cue| cue.render_options).unwrap_or_default(),
+        }
+    }
+}
+
+impl SequenceState {
+    /// The state `s` of the way from `self` to `other`. Numbers are interpolated, choices like the
+    /// blend mode switch halfway, and trails and bloom fade in and out.
+    pub fn crossfade(&self, other: &Self, s: f32) -> Self {
+        Self {
+            transformations: morph(&self.transformations, &other.transformations, s),
+            camera: Camera {
+                center: self.camera.center.lerp(other.camera.center, s),
+                // evenly in scale rather than in zoom
+                zoom: self.camera.zoom * (other.camera.zoom / self.camera.zoom).powf(s),
+            },
+            render_options: crossfade_render_options(self.render_options, other.render_options, s),
+        }
+    }
+}
+
+/// Morphs one transformation set into another, `s` of the way. The maps of the larger set without
+/// a counterpart grow out of or shrink into an identity map of their own color. Points that pick
+/// an identity map stay where they are, so the mass of the attractor is that of the smaller set at
+/// its end of the morph.
+pub fn morph(from: &[Transformation], to: &[Transformation], s: f32) -> Vec<Transformation> {
+    let identity = |transformation: &Transformation| Transformation {
+        scale: 1.0,
+        angle: 0.0,
+        ..*transformation
+    };
+    (0..from.len().max(to.len()))
+        .map(|i| match (from.get(i), to.get(i)) {
+            (Some(a), Some(b)) => lerp_transformation(a, b, s),
+            (Some(a), None) => lerp_transformation(a, &identity(a), s),
+            (None, Some(b)) => lerp_transformation(&identity(b), b, s),
+            (None, None) => unreachable!(),
+        })
+        .collect_vec()
+}
+
+fn lerp_transformation(a: &Transformation, b: &Transformation, s: f32) -> Transformation {
+    // the short way round
+    let turn = (b.angle - a.angle + PI).rem_euclid(TAU) - PI;
+    Transformation {
+        center: a.center.lerp(b.center, s),
+        scale: lerp(a.scale, b.scale, s),
+        angle: a.angle + turn * s,
+        color: a.color.lerp(b.color, s),
+    }
+}
+
+fn crossfade_render_options(a: RenderOptions, b: RenderOptions, s: f32) -> RenderOptions {
+    let splat = match (a.splat, b.splat) {
+        (Splat::Disc { radius: ra }, Splat::Disc { radius: rb }) => Splat::Disc {
+            radius: lerp(ra, rb, s),
+        },
+        (Splat::Gaussian { radius: ra }, Splat::Gaussian { radius: rb }) => Splat::Gaussian {
+            radius: lerp(ra, rb, s),
+        },
+        (Splat::Square { size: sa }, Splat::Square { size: sb }) => Splat::Square {
+            size: lerp(sa, sb, s),
+        },
+        (a, b) => switch(a, b, s),
+    };
+    let (pa, pb) = (a.post_process, b.post_process);
+    RenderOptions {
+        blend_mode: switch(a.blend_mode, b.blend_mode, s),
+        splat,
+        intensity: lerp(a.intensity, b.intensity, s),
+        hdr: switch(a.hdr, b.hdr, s),
+        post_process: PostProcess {
+            trails: crossfade_option(
+                pa.trails,
+                pb.trails,
+                s,
+                |trails| Trails {
+                    decay: 1.0,
+                    ..trails
+                },
+                |a, b, s| Trails {
+                    mode: switch(a.mode, b.mode, s),
+                    decay: lerp(a.decay, b.decay, s),
+                },
+            ),
+            bloom: crossfade_option(
+                pa.bloom,
+                pb.bloom,
+                s,
+                |bloom| Bloom {
+                    strength: 0.0,
+                    ..bloom
+                },
+                |a, b, s| Bloom {
+                    strength: lerp(a.strength, b.strength, s),
+                    radius: lerp(a.radius, b.radius, s),
+                    threshold: lerp(a.threshold, b.threshold, s),
+                    knee: lerp(a.knee, b.knee, s),
+                },
+            ),
+            exposure: lerp(pa.exposure, pb.exposure, s),
+            tone_mapping: switch(pa.tone_mapping, pb.tone_mapping, s),
+            saturation: lerp(pa.saturation, pb.saturation, s),
+            vibrance: lerp(pa.vibrance, pb.vibrance, s),
+            vignette: lerp(pa.vignette, pb.vignette, s),
+            gamma: lerp(pa.gamma, pb.gamma, s),
+        },
+    }
+}
+
+/// Crossfades effects that may be off, fading them from or to `neutral`, a version that does
+/// nothing, and turning them off at the end.
+fn crossfade_option<T: Copy>(
+    a: Option<T>,
+    b: Option<T>,
+    s: f32,
+    neutral: impl Fn(T) -> T,
+    crossfade: impl Fn(T, T, f32) -> T,
+) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(crossfade(a, b, s)),
+        (Some(a), None) => (s < 1.0).then(|| crossfade(a, neutral(a), s)),
+        (None, Some(b)) => (s > 0.0).then(|| crossfade(neutral(b), b, s)),
+        (None, None) => None,
+    }
+}
+
+/// Exactly `a` at 0 and `b` at 1.
+fn lerp(a: f32, b: f32, s: f32) -> f32 {
+    a * (1.0 - s) + b * s
+}
+
+/// `a` for the first half of a crossfade and `b` for the second.
+fn switch<T>(a: T, b: T, s: f32) -> T {
+    if s < 0.5 { a } else { b }
+}
@@ -1,16 +1,44 @@
 use std::{mem, num::NonZero};
 
+use bytemuck::{Pod, Zeroable};
+use color_eyre::eyre::Result;
 use wgpu as g;
 
-use crate::{app::Context, data::Buffer};
+use crate::{
+    app::Gpu,
+    data::{Buffer, BufferRange, Readback, UniformBuffer},
+    impl_wgsl_struct, shader,
+};
 
 use super::{ComputedTransformation, Point};
 
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(super) struct Parameters {
+    frame: u32,
+    respawn_bound: f32,
+    _padding: [u32; 2],
+}
+
+impl_wgsl_struct!(Parameters {
+    frame: u32,
+    respawn_bound: f32,
+    _padding: [u32; 2],
+});
+
 #[derive(Debug)]
 pub(super) struct Simulator {
-    transformation_bind_group: g::BindGroup,
+    frame: u32,
+    parameter_buffer: UniformBuffer<Parameters>,
+    respawn_buffer: Buffer<u32>,
+    bind_group_layout: g::BindGroupLayout,
+    bind_group: g::BindGroup,
+    point_bind_group_layout: g::BindGroupLayout,
+    dispatch_buffer: Option<Buffer<u32>>,
+    dispatch_stride: u32,
     full_point_chunk_bind_group: Option<g::BindGroup>,
     point_rest_chunk_bind_group: Option<g::BindGroup>,
+    pipeline_layout: g::PipelineLayout,
     pipeline: g::ComputePipeline,
     n_full_dispatches: u32,
     n_rest_points: u32,
@@ -19,131 +47,323 @@ pub(super) struct Simulator {
 impl Simulator {
     const INVOCATIONS_PER_WORKGROUP: u32 = 64;
     const MAX_WORKGROUPS_PER_DISPATCH: u32 = 65535;
-    const FULL_POINT_CHUNK_LEN: u32 =
+    pub(super) const FULL_POINT_CHUNK_LEN: u32 =
         Self::INVOCATIONS_PER_WORKGROUP * Self::MAX_WORKGROUPS_PER_DISPATCH;
 
+    /// Points farther than this from the origin along either axis are respawned.
+    pub(super) const RESPAWN_BOUND: f32 = 100.0;
+
+    pub(super) const SOURCE: &str = include_str!("sim.wgsl");
+
+    /// `hash` streams used by the shaders, which import them from the `streams` module.
+    pub(super) const SELECTION_STREAM: u32 = 0;
+    pub(super) const RESPAWN_STREAM: u32 = 1;
+
+    /// `source` is a composed `sim.wgsl`.
     pub(super) fn new(
-        points: &Buffer<Point>,
+        points: BufferRange<'_, Point>,
         transformations: &Buffer<ComputedTransformation>,
-        context: &Context,
+        source: &str,
+        gpu: &Gpu,
     ) -> Self {
-        assert!(u32::try_from(points.size()).is_ok());
-        let n_points = points.len() as u32;
+        let parameter_buffer = UniformBuffer::new(
+            &Parameters {
+                frame: 0,
+                respawn_bound: Self::RESPAWN_BOUND,
+                _padding: [0; 2],
+            },
+            Some("simulation parameter buffer"),
+            gpu,
+        );
 
-        let shader = context
-            .device
-            .create_shader_module(g::include_wgsl!("sim.wgsl"));
+        let respawn_buffer = Buffer::from_data(
+            &[0],
+            Some("respawn counter buffer"),
+            g::BufferUsages::STORAGE | g::BufferUsages::COPY_SRC | g::BufferUsages::COPY_DST,
+            gpu,
+        );
 
-        let transformation_bind_group_layout =
-            context
-                .device
+        let bind_group_layout =
+            gpu.device
                 .create_bind_group_layout(&g::BindGroupLayoutDescriptor {
-                    label: Some("simulation transformation bind group layout"),
-                    entries: &[g::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: g::ShaderStages::COMPUTE,
-                        ty: g::BindingType::Buffer {
-                            ty: g::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
+                    label: Some("simulation bind group layout"),
+                    entries: &[
+                        g::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: g::ShaderStages::COMPUTE,
+                            ty: g::BindingType::Buffer {
+                                ty: g::BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        g::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: g::ShaderStages::COMPUTE,
+                            ty: g::BindingType::Buffer {
+                                ty: g::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        g::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: g::ShaderStages::COMPUTE,
+                            ty: g::BindingType::Buffer {
+                                ty: g::BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
                         },
-                        count: None,
-                    }],
+                    ],
                 });
 
-        let transformation_bind_group = context.device.create_bind_group(&g::BindGroupDescriptor {
-            label: Some("simulation transformation bind group"),
-            layout: &transformation_bind_group_layout,
-            entries: &[g::BindGroupEntry {
-                binding: 0,
-                resource: transformations.as_entire_binding(),
-            }],
-        });
-
-        let n_full_point_chunks = n_points / Self::FULL_POINT_CHUNK_LEN;
-        let n_rest_points = n_points % Self::FULL_POINT_CHUNK_LEN;
+        let bind_group = Self::create_bind_group(
+            &bind_group_layout,
+            transformations,
+            &parameter_buffer,
+            &respawn_buffer,
+            gpu,
+        );
 
         let point_bind_group_layout =
-            context
-                .device
+            gpu.device
                 .create_bind_group_layout(&g::BindGroupLayoutDescriptor {
                     label: Some("simulation point bind group layout"),
-                    entries: &[g::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: g::ShaderStages::COMPUTE,
-                        ty: g::BindingType::Buffer {
-                            ty: g::BufferBindingType::Storage { read_only: false },
-                            has_dynamic_offset: true,
-                            min_binding_size: None,
+                    entries: &[
+                        g::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: g::ShaderStages::COMPUTE,
+                            ty: g::BindingType::Buffer {
+                                ty: g::BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: true,
+                                min_binding_size: None,
+                            },
+                            count: None,
                         },
-                        count: None,
-                    }],
+                        g::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: g::ShaderStages::COMPUTE,
+                            ty: g::BindingType::Buffer {
+                                ty: g::BufferBindingType::Uniform,
+                                has_dynamic_offset: true,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
                 });
 
-        let full_point_chunk_bind_group = (n_full_point_chunks != 0).then(|| {
-            context.device.create_bind_group(&g::BindGroupDescriptor {
-                label: Some("simulation full point chunk bind group"),
-                layout: &point_bind_group_layout,
-                entries: &[g::BindGroupEntry {
-                    binding: 0,
-                    resource: g::BindingResource::Buffer(g::BufferBinding {
-                        buffer: points,
-                        offset: 0,
-                        size: NonZero::new(
-                            Self::FULL_POINT_CHUNK_LEN as u64 * mem::size_of::<Point>() as u64,
-                        ),
-                    }),
-                }],
-            })
-        });
-
-        let point_rest_chunk_bind_group = (n_rest_points != 0).then(|| {
-            context.device.create_bind_group(&g::BindGroupDescriptor {
-                label: Some("simulation bind group"),
-                layout: &point_bind_group_layout,
-                entries: &[g::BindGroupEntry {
-                    binding: 0,
-                    resource: g::BindingResource::Buffer(g::BufferBinding {
-                        buffer: points,
-                        offset: n_full_point_chunks as u64
-                            * Self::FULL_POINT_CHUNK_LEN as u64
-                            * mem::size_of::<Point>() as u64,
-                        size: NonZero::new(n_rest_points as u64 * mem::size_of::<Point>() as u64),
-                    }),
-                }],
-            })
-        });
-
-        let pipeline_layout = context
+        let pipeline_layout = gpu
             .device
             .create_pipeline_layout(&g::PipelineLayoutDescriptor {
                 label: Some("simulation pipeline layout"),
-                bind_group_layouts: &[&transformation_bind_group_layout, &point_bind_group_layout],
+                bind_group_layouts: &[&bind_group_layout, &point_bind_group_layout],
                 push_constant_ranges: &[],
             });
 
-        let pipeline = context
-            .device
+        let pipeline = Self::create_pipeline(&pipeline_layout, source, gpu);
+
+        let mut simulator = Self {
+            frame: 0,
+            parameter_buffer,
+            respawn_buffer,
+            bind_group_layout,
+            bind_group,
+            point_bind_group_layout,
+            dispatch_buffer: None,
+            dispatch_stride: gpu.device.limits().min_uniform_buffer_offset_alignment,
+            full_point_chunk_bind_group: None,
+            point_rest_chunk_bind_group: None,
+            pipeline_layout,
+            pipeline,
+            n_full_dispatches: 0,
+            n_rest_points: 0,
+        };
+        simulator.set_points(points, gpu);
+        simulator
+    }
+
+    /// The frame the next `step` simulates.
+    pub(super) fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Makes `frame` the next one simulated, to replay the same steps.
+    pub(super) fn set_frame(&mut self, frame: u32) {
+        self.frame = frame;
+    }
+
+    /// Builds the pipeline from a composed `sim.wgsl`, to swap in with `set_pipeline`.
+    pub(super) fn compile_shader(&self, source: &str, gpu: &Gpu) -> Result<g::ComputePipeline> {
+        shader::catch_validation_errors(gpu, || {
+            Self::create_pipeline(&self.pipeline_layout, source, gpu)
+        })
+    }
+
+    pub(super) fn set_pipeline(&mut self, pipeline: g::ComputePipeline) {
+        self.pipeline = pipeline;
+    }
+
+    fn create_pipeline(layout: &g::PipelineLayout, source: &str, gpu: &Gpu) -> g::ComputePipeline {
+        let shader = gpu.device.create_shader_module(g::ShaderModuleDescriptor {
+            label: Some("sim.wgsl"),
+            source: g::ShaderSource::Wgsl(source.into()),
+        });
+        gpu.device
             .create_compute_pipeline(&g::ComputePipelineDescriptor {
                 label: Some("simulation pipeline"),
-                layout: Some(&pipeline_layout),
+                layout: Some(layout),
                 module: &shader,
                 entry_point: Some("simulate"),
                 compilation_options: Default::default(),
                 cache: None,
-            });
+            })
+    }
 
-        Self {
-            transformation_bind_group,
-            full_point_chunk_bind_group,
-            point_rest_chunk_bind_group,
-            pipeline,
-            n_full_dispatches: n_full_point_chunks,
-            n_rest_points,
+    pub(super) fn set_transformations(
+        &mut self,
+        transformations: &Buffer<ComputedTransformation>,
+        gpu: &Gpu,
+    ) {
+        self.bind_group = Self::create_bind_group(
+            &self.bind_group_layout,
+            transformations,
+            &self.parameter_buffer,
+            &self.respawn_buffer,
+            gpu,
+        );
+    }
+
+    fn create_bind_group(
+        layout: &g::BindGroupLayout,
+        transformations: &Buffer<ComputedTransformation>,
+        parameters: &UniformBuffer<Parameters>,
+        respawns: &Buffer<u32>,
+        gpu: &Gpu,
+    ) -> g::BindGroup {
+        gpu.device.create_bind_group(&g::BindGroupDescriptor {
+            label: Some("simulation bind group"),
+            layout,
+            entries: &[
+                g::BindGroupEntry {
+                    binding: 0,
+                    resource: transformations.as_entire_binding(),
+                },
+                g::BindGroupEntry {
+                    binding: 1,
+                    resource: parameters.as_entire_binding(),
+                },
+                g::BindGroupEntry {
+                    binding: 2,
+                    resource: respawns.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Rebuilds the bind groups for the points in `points`, needed whenever the range changes.
+    pub(super) fn set_points(&mut self, points: BufferRange<'_, Point>, gpu: &Gpu) {
+        assert!(u32::try_from(points.byte_offset() + points.byte_size()).is_ok());
+        let n_points = points.len() as u32;
+
+        let n_full_point_chunks = n_points / Self::FULL_POINT_CHUNK_LEN;
+        let n_rest_points = n_points % Self::FULL_POINT_CHUNK_LEN;
+        let n_dispatches = n_full_point_chunks + (n_rest_points != 0) as u32;
+
+        // the offset of each dispatch's first point, one per uniform offset alignment
+        let stride_len = (self.dispatch_stride as usize / mem::size_of::<u32>()).max(1);
+        let mut dispatches = vec![0; n_dispatches as usize * stride_len];
+        for i in 0..n_dispatches {
+            dispatches[i as usize * stride_len] = i * Self::FULL_POINT_CHUNK_LEN;
         }
+        let dispatch_buffer = (n_dispatches != 0).then(|| {
+            Buffer::from_data(
+                &dispatches,
+                Some("simulation dispatch buffer"),
+                g::BufferUsages::UNIFORM,
+                gpu,
+            )
+        });
+
+        let dispatch_binding = || {
+            g::BindingResource::Buffer(g::BufferBinding {
+                buffer: dispatch_buffer
+                    .as_ref()
+                    .expect("no dispatch buffer for an empty point buffer"),
+                offset: 0,
+                size: NonZero::new(mem::size_of::<u32>() as u64),
+            })
+        };
+
+        let full_point_chunk_bind_group = (n_full_point_chunks != 0).then(|| {
+            gpu.device.create_bind_group(&g::BindGroupDescriptor {
+                label: Some("simulation full point chunk bind group"),
+                layout: &self.point_bind_group_layout,
+                entries: &[
+                    g::BindGroupEntry {
+                        binding: 0,
+                        resource: points
+                            .buffer()
+                            .range(
+                                points.offset()
+                                    ..points.offset() + Self::FULL_POINT_CHUNK_LEN as usize,
+                            )
+                            .binding(),
+                    },
+                    g::BindGroupEntry {
+                        binding: 1,
+                        resource: dispatch_binding(),
+                    },
+                ],
+            })
+        });
+
+        let point_rest_chunk_bind_group = (n_rest_points != 0).then(|| {
+            gpu.device.create_bind_group(&g::BindGroupDescriptor {
+                label: Some("simulation bind group"),
+                layout: &self.point_bind_group_layout,
+                entries: &[
+                    g::BindGroupEntry {
+                        binding: 0,
+                        resource: points
+                            .buffer()
+                            .range(
+                                points.offset()
+                                    + (n_full_point_chunks * Self::FULL_POINT_CHUNK_LEN) as usize
+                                    ..points.offset() + n_points as usize,
+                            )
+                            .binding(),
+                    },
+                    g::BindGroupEntry {
+                        binding: 1,
+                        resource: dispatch_binding(),
+                    },
+                ],
+            })
+        });
+
+        self.dispatch_buffer = dispatch_buffer;
+        self.full_point_chunk_bind_group = full_point_chunk_bind_group;
+        self.point_rest_chunk_bind_group = point_rest_chunk_bind_group;
+        self.n_full_dispatches = n_full_point_chunks;
+        self.n_rest_points = n_rest_points;
     }
 
-    pub(super) fn step(&self, context: &Context) {
+    pub(super) fn step(&mut self, gpu: &Gpu) {
+        self.parameter_buffer.write(
+            &Parameters {
+                frame: self.frame,
+                respawn_bound: Self::RESPAWN_BOUND,
+                _padding: [0; 2],
+            },
+            gpu,
+        );
+        self.frame = self.frame.wrapping_add(1);
+
         let bind_groups_offsets_lens = (0..self.n_full_dispatches)
             .filter_map(|i| {
                 Some((
@@ -158,29 +378,48 @@ impl Simulator {
                     .map(|bind_group| (bind_group, 0, self.n_rest_points)),
             );
 
-        let commands = bind_groups_offsets_lens.map(|(bind_group, offset, len)| {
-            let mut encoder = context
-                .device
-                .create_command_encoder(&g::CommandEncoderDescriptor {
-                    label: Some("simulation command encoder"),
-                });
-            {
-                let mut compute_pass = encoder.begin_compute_pass(&g::ComputePassDescriptor {
-                    label: Some("simulation compute pass"),
-                    timestamp_writes: None,
+        let commands =
+            bind_groups_offsets_lens
+                .enumerate()
+                .map(|(i, (bind_group, offset, len))| {
+                    let mut encoder =
+                        gpu.device
+                            .create_command_encoder(&g::CommandEncoderDescriptor {
+                                label: Some("simulation command encoder"),
+                            });
+                    {
+                        let mut compute_pass =
+                            encoder.begin_compute_pass(&g::ComputePassDescriptor {
+                                label: Some("simulation compute pass"),
+                                timestamp_writes: None,
+                            });
+                        compute_pass.set_pipeline(&self.pipeline);
+                        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+                        compute_pass.set_bind_group(
+                            1,
+                            bind_group,
+                            &[
+                                offset * mem::size_of::<Point>() as u32,
+                                i as u32 * self.dispatch_stride,
+                            ],
+                        );
+                        compute_pass.dispatch_workgroups(
+                            len.div_ceil(Self::INVOCATIONS_PER_WORKGROUP),
+                            1,
+                            1,
+                        );
+                    }
+                    encoder.finish()
                 });
-                compute_pass.set_pipeline(&self.pipeline);
-                compute_pass.set_bind_group(0, &self.transformation_bind_group, &[]);
-                compute_pass.set_bind_group(
-                    1,
-                    bind_group,
-                    &[offset * mem::size_of::<Point>() as u32],
-                );
-                compute_pass.dispatch_workgroups(len / Self::INVOCATIONS_PER_WORKGROUP, 1, 1);
-            }
-            encoder.finish()
-        });
 
-        context.queue.submit(commands);
+        gpu.queue.submit(commands);
+    }
+
+    /// Starts reading back the number of points respawned since the last call, as the single
+    /// element of the readback, and resets the counter.
+    pub(super) fn take_respawn_count(&mut self, gpu: &Gpu) -> Readback<u32> {
+        let readback = self.respawn_buffer.range(..).read_later(gpu);
+        self.respawn_buffer.write(0, &[0], gpu);
+        readback
     }
 }
@@ -1,19 +1,41 @@
-struct Transformation {
-    center: vec2f,
-    scale: f32,
-    angle: f32,
-    color: vec4f,
-    matrix: mat3x3f,
+#import hash
+#import streams
+#import Point
+#import ComputedTransformation
+#import Parameters
+#import variation
+
+struct Dispatch {
+    point_offset: u32,
 }
 
-@group(0) @binding(0) var<storage> transformations: array<Transformation>;
-@group(1) @binding(0) var<storage, read_write> points: array<vec2f>;
+@group(0) @binding(0) var<storage> transformations: array<ComputedTransformation>;
+@group(0) @binding(1) var<uniform> parameters: Parameters;
+@group(0) @binding(2) var<storage, read_write> respawns: atomic<u32>;
+@group(1) @binding(0) var<storage, read_write> points: array<Point>;
+@group(1) @binding(1) var<uniform> dispatch: Dispatch;
+
+fn is_finite(x: f32) -> bool {
+    return (bitcast<u32>(x) & 0x7f800000u) != 0x7f800000u;
+}
 
 @compute @workgroup_size(64)
 fn simulate(@builtin(global_invocation_id) id: vec3u) {
-    let point = points[id.x];
-    let hash = bitcast<u32>(point.x) ^ bitcast<u32>(point.y) ^ id.x;
-    let idx = hash % (arrayLength(&transformations));
+    if id.x >= arrayLength(&points) {
+        return;
+    }
+
+    let index = dispatch.point_offset + id.x;
+    let point = points[id.x].pos;
+    let idx = hash(parameters.frame, SELECTION_STREAM, index) % arrayLength(&transformations);
     let transformation = transformations[idx].matrix;
-    points[id.x] = (transformation * vec3f(point, 1.0)).xy;
+    var next = variation((transformation * vec3f(point, 1.0)).xy);
+
+    let escaped = any(abs(next) > vec2f(parameters.respawn_bound));
+    if !is_finite(next.x) || !is_finite(next.y) || escaped {
+        next = hash_vec2(parameters.frame, RESPAWN_STREAM, index) * 2.0 - 1.0;
+        atomicAdd(&respawns, 1u);
+    }
+
+    points[id.x].pos = next;
 }
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// Small WGSL functions a scene can supply to customize the dance, spliced into `render.wgsl` and
+/// `sim.wgsl` when the pipelines are built. `None` uses the defaults in `color.wgsl` and
+/// `variation.wgsl`.
+///
+/// Snippets can `#import` the same modules as the shaders, and read the `transformations` array.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snippets {
+    /// `fn color(point: vec2f, transformation_idx: u32) -> vec4f`, the color of a point given the
+    /// transformation that moved it in the last step.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// `fn variation(p: vec2f) -> vec2f`, applied to every point after its transformation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variation: Option<String>,
+}
+
+impl Snippets {
+    pub const DEFAULT_COLOR: &str = include_str!("color.wgsl");
+    pub const DEFAULT_VARIATION: &str = include_str!("variation.wgsl");
+}
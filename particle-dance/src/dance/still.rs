@@ -0,0 +1,393 @@
+use std::{io::Write, iter};
+
+use bytemuck::{Pod, Zeroable};
+use color_eyre::eyre::{Result, ensure};
+use glam::{UVec2, Vec4};
+use wgpu as g;
+
+use crate::{app::Gpu, data::UniformBuffer, image::Image, impl_wgsl_struct, shader};
+
+use super::{
+    Dance,
+    offscreen::OffscreenTarget,
+    render::{RenderOptions, Viewport},
+};
+
+/// The kernel supersampled stills are filtered down with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// The average of the samples within each pixel.
+    Box,
+    /// Softer than `Box`, with a standard deviation of half a pixel.
+    Gaussian,
+    /// Sharper than `Gaussian` without ringing much.
+    #[default]
+    Mitchell,
+}
+
+impl Filter {
+    /// How far the kernel reaches from the center of a pixel, in pixels.
+    fn support(self) -> f32 {
+        match self {
+            Self::Box => 0.5,
+            Self::Gaussian => 1.5,
+            Self::Mitchell => 2.0,
+        }
+    }
+
+    /// The matching `FILTER_*` constant in `still.wgsl`.
+    fn wgsl_kind(self) -> u32 {
+        match self {
+            Self::Box => 0,
+            Self::Gaussian => 1,
+            Self::Mitchell => 2,
+        }
+    }
+}
+
+/// A still image of the dance, rendered offscreen at a resolution independent of the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Still {
+    pub width: u32,
+    pub height: u32,
+    /// The number of samples rendered per pixel along each axis. Single pixel points cover a
+    /// single sample, so they come out dimmer the more samples there are.
+    pub supersampling: u32,
+    pub filter: Filter,
+    /// The size of the tiles the image is rendered in, in pixels. Smaller ones are used when the
+    /// supersampled tiles would not fit in a texture. Bloom does not reach across tiles, so tiles
+    /// much larger than its radius keep the seams from showing.
+    pub tile_size: u32,
+}
+
+impl Still {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            supersampling: 4,
+            filter: Filter::default(),
+            tile_size: 1024,
+        }
+    }
+
+    /// The pixels rendered around each tile so that the filter sees all the samples it reaches.
+    fn margin(self) -> u32 {
+        self.filter.support().ceil() as u32
+    }
+
+    /// The render options that make the supersampled tiles look like the image at its own
+    /// resolution: splats and bloom keep their size in pixels. Trails are left out, the tiles have
+    /// nothing to do with each other.
+    fn render_options(self, options: RenderOptions) -> RenderOptions {
+        let factor = self.supersampling as f32;
+        let mut options = RenderOptions {
+            splat: options.splat.scaled(factor),
+            ..options
+        };
+        options.post_process.trails = None;
+        if let Some(bloom) = &mut options.post_process.bloom {
+            bloom.radius *= factor;
+        }
+        options
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub(super) struct StillParameters {
+    supersampling: u32,
+    margin: u32,
+    kernel: u32,
+    support: f32,
+}
+
+impl_wgsl_struct!(StillParameters {
+    supersampling: u32,
+    margin: u32,
+    kernel: u32,
+    support: f32,
+});
+
+/// Filters supersampled tiles down to the output resolution.
+#[derive(Debug)]
+pub(super) struct Downsampler {
+    parameter_buffer: UniformBuffer<StillParameters>,
+    bind_group_layout: g::BindGroupLayout,
+    pipeline_layout: g::PipelineLayout,
+    pipeline: g::RenderPipeline,
+}
+
+impl Downsampler {
+    pub(super) const SOURCE: &str = include_str!("still.wgsl");
+
+    /// `source` is a composed `still.wgsl`.
+    pub(super) fn new(source: &str, gpu: &Gpu) -> Self {
+        let parameter_buffer = UniformBuffer::new(
+            &StillParameters::zeroed(),
+            Some("still parameter buffer"),
+            gpu,
+        );
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&g::BindGroupLayoutDescriptor {
+                    label: Some("still bind group layout"),
+                    entries: &[
+                        g::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: g::ShaderStages::FRAGMENT,
+                            ty: g::BindingType::Texture {
+                                sample_type: g::TextureSampleType::Float { filterable: false },
+                                view_dimension: g::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        g::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: g::ShaderStages::FRAGMENT,
+                            ty: g::BindingType::Buffer {
+                                ty: g::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&g::PipelineLayoutDescriptor {
+                label: Some("still pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipeline = Self::create_pipeline(&pipeline_layout, source, gpu);
+
+        Self {
+            parameter_buffer,
+            bind_group_layout,
+            pipeline_layout,
+            pipeline,
+        }
+    }
+
+    /// Builds the pipeline from a composed `still.wgsl`, to swap in with `set_pipeline`.
+    pub(super) fn compile_shader(&self, source: &str, gpu: &Gpu) -> Result<g::RenderPipeline> {
+        shader::catch_validation_errors(gpu, || {
+            Self::create_pipeline(&self.pipeline_layout, source, gpu)
+        })
+    }
+
+    pub(super) fn set_pipeline(&mut self, pipeline: g::RenderPipeline) {
+        self.pipeline = pipeline;
+    }
+
+    fn create_pipeline(layout: &g::PipelineLayout, source: &str, gpu: &Gpu) -> g::RenderPipeline {
+        let shader = gpu.device.create_shader_module(g::ShaderModuleDescriptor {
+            label: Some("still.wgsl"),
+            source: g::ShaderSource::Wgsl(source.into()),
+        });
+        gpu.device
+            .create_render_pipeline(&g::RenderPipelineDescriptor {
+                label: Some("still pipeline"),
+                layout: Some(layout),
+                primitive: Default::default(),
+                vertex: g::VertexState {
+                    module: &shader,
+                    entry_point: Some("fullscreen"),
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                fragment: Some(g::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fragment"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(g::ColorTargetState {
+                        format: OffscreenTarget::FORMAT,
+                        blend: Some(g::BlendState::REPLACE),
+                        write_mask: g::ColorWrites::ALL,
+                    })],
+                }),
+                depth_stencil: None,
+                multisample: Default::default(),
+                multiview: None,
+                cache: None,
+            })
+    }
+
+    /// Filters `samples`, a tile supersampled as in `still` with its margin, into `dst`.
+    fn apply(&self, samples: &g::Texture, dst: &g::Texture, still: Still, gpu: &Gpu) {
+        self.parameter_buffer.write(
+            &StillParameters {
+                supersampling: still.supersampling,
+                margin: still.margin(),
+                kernel: still.filter.wgsl_kind(),
+                support: still.filter.support(),
+            },
+            gpu,
+        );
+        let samples_view = samples.create_view(&Default::default());
+        let bind_group = gpu.device.create_bind_group(&g::BindGroupDescriptor {
+            label: Some("still bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                g::BindGroupEntry {
+                    binding: 0,
+                    resource: g::BindingResource::TextureView(&samples_view),
+                },
+                g::BindGroupEntry {
+                    binding: 1,
+                    resource: self.parameter_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&g::CommandEncoderDescriptor {
+                label: Some("still command encoder"),
+            });
+        {
+            let dst_view = dst.create_view(&Default::default());
+            let mut render_pass = encoder.begin_render_pass(&g::RenderPassDescriptor {
+                label: Some("still pass"),
+                color_attachments: &[Some(g::RenderPassColorAttachment {
+                    view: &dst_view,
+                    resolve_target: None,
+                    ops: g::Operations {
+                        load: g::LoadOp::Clear(g::Color::BLACK),
+                        store: g::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        gpu.queue.submit(iter::once(encoder.finish()));
+    }
+}
+
+impl Dance {
+    /// Renders `still` into an image.
+    pub fn render_still(&mut self, still: Still, gpu: &Gpu) -> Result<Image> {
+        let mut image = Image::new(still.width, still.height, Vec4::ZERO);
+        let mut y = 0;
+        self.render_still_rows(still, gpu, |rows| {
+            image.copy_from(0, y, &rows);
+            y += rows.height();
+            Ok(())
+        })?;
+        Ok(image)
+    }
+
+    /// Renders `still` as a binary PPM, a row of tiles at a time, so that it never has to fit in
+    /// memory as a whole.
+    pub fn write_still_ppm(
+        &mut self,
+        still: Still,
+        mut writer: impl Write,
+        gpu: &Gpu,
+    ) -> Result<()> {
+        Image::write_ppm_header(&mut writer, still.width, still.height)?;
+        self.render_still_rows(still, gpu, |rows| Ok(rows.write_ppm_rows(&mut writer)?))
+    }
+
+    /// Like `write_still_ppm`, but keeps the alpha channel in a binary PAM, for stills with a
+    /// transparent background.
+    pub fn write_still_pam(
+        &mut self,
+        still: Still,
+        mut writer: impl Write,
+        gpu: &Gpu,
+    ) -> Result<()> {
+        Image::write_pam_header(&mut writer, still.width, still.height)?;
+        self.render_still_rows(still, gpu, |rows| Ok(rows.write_pam_rows(&mut writer)?))
+    }
+
+    /// Renders `still` in rows of tiles from the top, handing each row to `write_rows` as an
+    /// image as wide as the still. The dance must have been created with
+    /// `OffscreenTarget::FORMAT`.
+    pub fn render_still_rows(
+        &mut self,
+        still: Still,
+        gpu: &Gpu,
+        mut write_rows: impl FnMut(Image) -> Result<()>,
+    ) -> Result<()> {
+        ensure!(
+            self.renderer.dst_format() == OffscreenTarget::FORMAT,
+            "stills need a dance rendering to {:?}, not {:?}",
+            OffscreenTarget::FORMAT,
+            self.renderer.dst_format()
+        );
+        ensure!(
+            still.width > 0 && still.height > 0 && still.supersampling > 0 && still.tile_size > 0,
+            "a still needs a size, a supersampling factor and a tile size of at least 1"
+        );
+        let max_samples = gpu.device.limits().max_texture_dimension_2d;
+        let max_tile_size = (max_samples / still.supersampling).saturating_sub(2 * still.margin());
+        ensure!(
+            max_tile_size > 0,
+            "supersampling {}x does not fit in textures of {max_samples} pixels",
+            still.supersampling
+        );
+        let tile_size = still.tile_size.min(max_tile_size);
+
+        let options = self.renderer.options();
+        self.renderer
+            .set_options(still.render_options(options), gpu);
+        let result = (0..still.height)
+            .step_by(tile_size as usize)
+            .try_for_each(|y| {
+                let height = tile_size.min(still.height - y);
+                let mut rows = Image::new(still.width, height, Vec4::ZERO);
+                for x in (0..still.width).step_by(tile_size as usize) {
+                    let width = tile_size.min(still.width - x);
+                    let tile =
+                        self.render_tile(still, UVec2::new(x, y), UVec2::new(width, height), gpu)?;
+                    rows.copy_from(x, 0, &tile);
+                }
+                write_rows(rows)
+            });
+        self.renderer.set_viewport(Viewport::default());
+        self.renderer.set_options(options, gpu);
+        self.renderer.drop_targets();
+        result
+    }
+
+    /// Renders the tile of `still` at `min` of `size` pixels.
+    fn render_tile(&mut self, still: Still, min: UVec2, size: UVec2, gpu: &Gpu) -> Result<Image> {
+        let margin = still.margin() as i32;
+        let min = min.as_ivec2() - margin;
+        let max = min + size.as_ivec2() + 2 * margin;
+        self.renderer.set_viewport(Viewport::from_pixels(
+            min,
+            max,
+            UVec2::new(still.width, still.height),
+        ));
+
+        let samples_size = (max - min).as_uvec2() * still.supersampling;
+        let samples = gpu.device.create_texture(&g::TextureDescriptor {
+            label: Some("still samples texture"),
+            size: g::Extent3d {
+                width: samples_size.x,
+                height: samples_size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: g::TextureDimension::D2,
+            format: OffscreenTarget::FORMAT,
+            usage: g::TextureUsages::RENDER_ATTACHMENT | g::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        self.render(&samples, gpu)?;
+
+        let mut target = OffscreenTarget::new(size.x, size.y, gpu);
+        self.downsampler
+            .apply(&samples, target.texture(), still, gpu);
+        target.read(gpu)
+    }
+}
@@ -0,0 +1,60 @@
+// Filters a supersampled tile down to the output resolution, see `still.rs`.
+
+#import fullscreen
+#import StillParameters
+
+const FILTER_BOX: u32 = 0u;
+const FILTER_GAUSSIAN: u32 = 1u;
+const FILTER_MITCHELL: u32 = 2u;
+
+@group(0) @binding(0) var samples: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> parameters: StillParameters;
+
+// the weight of a sample `x` output pixels away from the center of an output pixel
+fn kernel(offset: f32) -> f32 {
+    let x = abs(offset);
+    switch parameters.kernel {
+        case FILTER_BOX: {
+            return select(0.0, 1.0, x < 0.5);
+        }
+        case FILTER_GAUSSIAN: {
+            // a standard deviation of half a pixel
+            return exp(-2.0 * x * x);
+        }
+        default: {
+            // Mitchell-Netravali with B = C = 1/3
+            let x2 = x * x;
+            let x3 = x2 * x;
+            if x < 1.0 {
+                return (7.0 * x3 - 12.0 * x2 + 16.0 / 3.0) / 6.0;
+            }
+            if x < 2.0 {
+                return (-7.0 / 3.0 * x3 + 12.0 * x2 - 20.0 * x + 32.0 / 3.0) / 6.0;
+            }
+            return 0.0;
+        }
+    }
+}
+
+@fragment
+fn fragment(vertex: FullscreenVertex) -> @location(0) vec4f {
+    let factor = f32(parameters.supersampling);
+    // in samples, past the margin around the tile
+    let center = (vertex.position.xy + f32(parameters.margin)) * factor;
+    let reach = parameters.support * factor;
+    let size = vec2i(textureDimensions(samples));
+    let first = max(vec2i(floor(center - reach)), vec2i(0));
+    let last = min(vec2i(ceil(center + reach)), size - 1);
+
+    var sum = vec4f(0.0);
+    var total_weight = 0.0;
+    for (var y = first.y; y <= last.y; y++) {
+        let weight_y = kernel((f32(y) + 0.5 - center.y) / factor);
+        for (var x = first.x; x <= last.x; x++) {
+            let weight = kernel((f32(x) + 0.5 - center.x) / factor) * weight_y;
+            sum += textureLoad(samples, vec2i(x, y), 0) * weight;
+            total_weight += weight;
+        }
+    }
+    return clamp(sum / total_weight, vec4f(0.0), vec4f(1.0));
+}
@@ -0,0 +1,249 @@
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+use wgpu as g;
+
+use crate::{app::Gpu, shader};
+
+use super::post::PostProcessor;
+
+/// How earlier frames combine with the latest one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrailMode {
+    /// Each frame is added onto the faded earlier ones, so that trails brighten where points
+    /// linger.
+    #[default]
+    Fade,
+    /// An exponential moving average of the frames, which keeps the brightness of a single one.
+    Average,
+}
+
+/// Trails left by the points, from an accumulation of the frames that decays instead of being
+/// cleared.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Trails {
+    pub mode: TrailMode,
+    /// The fraction of the accumulated image that fades each frame, from 0 for trails that never
+    /// fade to 1 for no trails at all.
+    pub decay: f32,
+}
+
+impl Default for Trails {
+    fn default() -> Self {
+        Self {
+            mode: TrailMode::default(),
+            decay: 0.1,
+        }
+    }
+}
+
+/// The texture the frames accumulate in.
+#[derive(Debug)]
+struct Accumulation {
+    texture: g::Texture,
+    view: g::TextureView,
+}
+
+#[derive(Debug)]
+pub(super) struct TrailPipelines {
+    fade: g::RenderPipeline,
+    average: g::RenderPipeline,
+}
+
+/// Blends every frame into a persistent accumulation texture.
+#[derive(Debug)]
+pub(super) struct TrailsPass {
+    bind_group_layout: g::BindGroupLayout,
+    pipeline_layout: g::PipelineLayout,
+    pipelines: TrailPipelines,
+    accumulation: Option<Accumulation>,
+    /// Whether the next frame replaces the accumulation instead of blending into it.
+    reset: bool,
+}
+
+impl TrailsPass {
+    pub(super) const SOURCE: &str = include_str!("trails.wgsl");
+
+    /// `source` is a composed `trails.wgsl`.
+    pub(super) fn new(source: &str, gpu: &Gpu) -> Self {
+        let bind_group_layout =
+            gpu.device
+                .create_bind_group_layout(&g::BindGroupLayoutDescriptor {
+                    label: Some("trails bind group layout"),
+                    entries: &[g::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: g::ShaderStages::FRAGMENT,
+                        ty: g::BindingType::Texture {
+                            sample_type: g::TextureSampleType::Float { filterable: false },
+                            view_dimension: g::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    }],
+                });
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&g::PipelineLayoutDescriptor {
+                label: Some("trails pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipelines = Self::create_pipelines(&pipeline_layout, source, gpu);
+
+        Self {
+            bind_group_layout,
+            pipeline_layout,
+            pipelines,
+            accumulation: None,
+            reset: true,
+        }
+    }
+
+    /// Builds the pipelines from a composed `trails.wgsl`, to swap in with `set_pipelines`.
+    pub(super) fn compile_shader(&self, source: &str, gpu: &Gpu) -> Result<TrailPipelines> {
+        shader::catch_validation_errors(gpu, || {
+            Self::create_pipelines(&self.pipeline_layout, source, gpu)
+        })
+    }
+
+    pub(super) fn set_pipelines(&mut self, pipelines: TrailPipelines) {
+        self.pipelines = pipelines;
+    }
+
+    fn create_pipelines(layout: &g::PipelineLayout, source: &str, gpu: &Gpu) -> TrailPipelines {
+        let shader = gpu.device.create_shader_module(g::ShaderModuleDescriptor {
+            label: Some("trails.wgsl"),
+            source: g::ShaderSource::Wgsl(source.into()),
+        });
+        // the blend constant is the fraction of the accumulation that is kept
+        let create_pipeline = |src_factor| {
+            let component = g::BlendComponent {
+                src_factor,
+                dst_factor: g::BlendFactor::Constant,
+                operation: g::BlendOperation::Add,
+            };
+            gpu.device
+                .create_render_pipeline(&g::RenderPipelineDescriptor {
+                    label: Some("trails pipeline"),
+                    layout: Some(layout),
+                    primitive: Default::default(),
+                    vertex: g::VertexState {
+                        module: &shader,
+                        entry_point: Some("fullscreen"),
+                        compilation_options: Default::default(),
+                        buffers: &[],
+                    },
+                    fragment: Some(g::FragmentState {
+                        module: &shader,
+                        entry_point: Some("fragment"),
+                        compilation_options: Default::default(),
+                        targets: &[Some(g::ColorTargetState {
+                            format: PostProcessor::HDR_FORMAT,
+                            blend: Some(g::BlendState {
+                                color: component,
+                                alpha: component,
+                            }),
+                            write_mask: g::ColorWrites::ALL,
+                        })],
+                    }),
+                    depth_stencil: None,
+                    multisample: Default::default(),
+                    multiview: None,
+                    cache: None,
+                })
+        };
+        TrailPipelines {
+            fade: create_pipeline(g::BlendFactor::One),
+            average: create_pipeline(g::BlendFactor::OneMinusConstant),
+        }
+    }
+
+    /// Starts the trails over from the next frame.
+    pub(super) fn reset(&mut self) {
+        self.reset = true;
+    }
+
+    /// Frees the accumulation until `apply` is called again.
+    pub(super) fn drop_accumulation(&mut self) {
+        self.accumulation = None;
+        self.reset = true;
+    }
+
+    /// Blends `frame`, of size `size`, into the accumulation, returning the view of the latter.
+    pub(super) fn apply(
+        &mut self,
+        encoder: &mut g::CommandEncoder,
+        frame: &g::TextureView,
+        size: g::Extent3d,
+        trails: Trails,
+        gpu: &Gpu,
+    ) -> g::TextureView {
+        if self
+            .accumulation
+            .as_ref()
+            .is_some_and(|accumulation| accumulation.texture.size() != size)
+        {
+            self.drop_accumulation();
+        }
+        let accumulation = self.accumulation.get_or_insert_with(|| {
+            let texture = gpu.device.create_texture(&g::TextureDescriptor {
+                label: Some("trails accumulation texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: g::TextureDimension::D2,
+                format: PostProcessor::HDR_FORMAT,
+                usage: g::TextureUsages::RENDER_ATTACHMENT | g::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&Default::default());
+            Accumulation { texture, view }
+        });
+
+        let bind_group = gpu.device.create_bind_group(&g::BindGroupDescriptor {
+            label: Some("trails bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[g::BindGroupEntry {
+                binding: 0,
+                resource: g::BindingResource::TextureView(frame),
+            }],
+        });
+        // clearing rather than keeping none of the accumulation, as that turns an overflowed
+        // one into NaN, and keeping none makes both modes take all of the frame
+        let (load, kept) = if self.reset {
+            (g::LoadOp::Clear(g::Color::TRANSPARENT), 0.0)
+        } else {
+            (g::LoadOp::Load, 1.0 - trails.decay.clamp(0.0, 1.0) as f64)
+        };
+        self.reset = false;
+
+        let mut render_pass = encoder.begin_render_pass(&g::RenderPassDescriptor {
+            label: Some("trails pass"),
+            color_attachments: &[Some(g::RenderPassColorAttachment {
+                view: &accumulation.view,
+                resolve_target: None,
+                ops: g::Operations {
+                    load,
+                    store: g::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        render_pass.set_pipeline(match trails.mode {
+            TrailMode::Fade => &self.pipelines.fade,
+            TrailMode::Average => &self.pipelines.average,
+        });
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_blend_constant(g::Color {
+            r: kept,
+            g: kept,
+            b: kept,
+            a: kept,
+        });
+        render_pass.draw(0..3, 0..1);
+        drop(render_pass);
+
+        accumulation.view.clone()
+    }
+}
@@ -0,0 +1,11 @@
+// Blends the latest frame into the accumulated trails. How much of each is kept is set by the
+// blend state and constant, see `trails.rs`.
+
+#import fullscreen
+
+@group(0) @binding(0) var frame: texture_2d<f32>;
+
+@fragment
+fn fragment(vertex: FullscreenVertex) -> @location(0) vec4f {
+    return textureLoad(frame, vec2i(vertex.position.xy), 0);
+}
@@ -1,46 +1,104 @@
 use core::f32;
 use std::ops::{Add, Mul, Neg};
 
-use glam::{Vec2, Vec4};
+use glam::{Vec4, vec4};
 use itertools::Itertools;
 
-use crate::random::Rng;
+use crate::{
+    hash::{hash_f32, hash_vec2},
+    random::Rng,
+};
 
 use super::Transformation;
 
 #[derive(Debug, Clone)]
 pub struct TransformationGenerator {
     elts: Vec<(u32 /* seed */, Vec4 /* color */)>,
+    /// Keyframes repeat after this many, see `looping`.
+    period: Option<u32>,
 }
 
 impl TransformationGenerator {
+    // `hash` streams of the keyframe values
+    const TOTAL_SCALE_STREAM: u32 = 0;
+    const CENTER_STREAM: u32 = 1;
+    const SCALE_STREAM: u32 = 2;
+    const ANGLE_STREAM: u32 = 3;
+
+    /// The palette of the window, cycled through for larger transformation sets.
+    pub const DEFAULT_COLORS: [Vec4; 5] = [
+        vec4(0.9, 0.9, 0.6, 1.0),
+        vec4(0.6, 0.9, 0.9, 1.0),
+        vec4(0.9, 0.6, 0.9, 1.0),
+        vec4(0.9, 0.6, 0.4, 1.0),
+        vec4(0.4, 0.6, 0.9, 1.0),
+    ];
+
+    /// `n` colors from `DEFAULT_COLORS`.
+    pub fn default_colors(n: usize) -> Vec<Vec4> {
+        Self::DEFAULT_COLORS
+            .into_iter()
+            .cycle()
+            .take(n)
+            .collect_vec()
+    }
+
     pub fn new(colors: Vec<Vec4>) -> Self {
-        let mut rng = Rng::new();
+        Self::with_rng(colors, &mut Rng::new())
+    }
+
+    pub fn with_seed(colors: Vec<Vec4>, seed: u32) -> Self {
+        Self::with_rng(colors, &mut Rng::with_seed(seed))
+    }
+
+    fn with_rng(colors: Vec<Vec4>, rng: &mut Rng) -> Self {
         Self {
             elts: colors
                 .into_iter()
                 .map(|color| (rng.random(), color))
                 .collect_vec(),
+            period: None,
         }
     }
 
+    /// Wraps the keyframes modulo `period`, interpolating across the wrap like between any other
+    /// keyframes, so that `generate(t + period)` is `generate(t)` and an animation over one
+    /// period loops seamlessly.
+    pub fn looping(mut self, period: u32) -> Self {
+        assert!(period > 0, "a loop needs at least one keyframe");
+        self.period = Some(period);
+        self
+    }
+
+    pub fn period(&self) -> Option<u32> {
+        self.period
+    }
+
+    pub fn colors(&self) -> Vec<Vec4> {
+        self.elts.iter().map(|&(_, color)| color).collect_vec()
+    }
+
     pub fn generate(&self, t: f32) -> Vec<Transformation> {
-        let total_scale =
-            cubic_interpolate(|i| Rng::with_seed(i as u32).random::<f32>() * 0.1 + 0.85, t);
+        let period = self.period.map(|period| period as i32);
+        let t = period.map_or(t, |period| t.rem_euclid(period as f32));
+        // the index of keyframe `i` in the hash streams, wrapped in a loop
+        let key = |i: i32| period.map_or(i, |period| i.rem_euclid(period)) as u32;
+
+        let total_scale = cubic_interpolate(
+            |i| hash_f32(0, Self::TOTAL_SCALE_STREAM, key(i)) * 0.1 + 0.85,
+            t,
+        );
         let mut scale_sum = 0.0;
 
         let mut transformations = self
             .elts
             .iter()
             .map(|&(seed, color)| {
-                let rng = Rng::with_seed(seed);
-
                 let center =
-                    cubic_interpolate(|i| rng.clone().hash(i as u32).hash(1).random::<Vec2>(), t)
-                        - 0.5;
-                let scale = cubic_interpolate(|i| rng.clone().hash(i as u32).hash(2).random(), t);
+                    cubic_interpolate(|i| hash_vec2(seed, Self::CENTER_STREAM, key(i)), t) - 0.5;
+                let scale = cubic_interpolate(|i| hash_f32(seed, Self::SCALE_STREAM, key(i)), t);
                 let angle = cubic_interpolate(
-                    |i| rng.clone().hash(i as u32).hash(3).random::<f32>() * f32::consts::TAU,
+                    |i| hash_f32(seed, Self::ANGLE_STREAM, key(i)) * f32::consts::TAU,
                     t,
                 );
 
@@ -71,7 +129,7 @@ where
 {
     // based on <https://en.wikipedia.org/wiki/Cubic_Hermite_spline>
 
-    let ti = t as i32;
+    let ti = t.floor() as i32;
     let aa = f(ti - 1);
     let a = f(ti);
     let b = f(ti + 1);
@@ -80,7 +138,7 @@ where
     let da = (b + -aa) * 0.5;
     let db = (bb + -a) * 0.5;
 
-    let t = t.fract();
+    let t = t - t.floor();
     let t2 = t * t;
     let t3 = t2 * t;
     a * (2. * t3 - 3. * t2 + 1.)
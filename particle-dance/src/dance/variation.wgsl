@@ -0,0 +1,4 @@
+// The default variation, leaving the affine transformations as they are.
+fn variation(p: vec2f) -> vec2f {
+    return p;
+}
@@ -1,15 +1,20 @@
 use std::{
+    iter,
     marker::PhantomData,
     mem,
-    ops::{Deref, RangeBounds},
+    num::NonZero,
+    ops::{Bound, Deref, RangeBounds},
+    slice,
+    task::Poll,
 };
 
 use bytemuck::{Pod, Zeroable};
 use color_eyre::eyre::Result;
-use glam::{Mat3, Vec4, Vec4Swizzles};
+use futures::{FutureExt, channel::oneshot, future};
+use glam::{Mat3, UVec2, UVec4, Vec2, Vec4, Vec4Swizzles};
 use wgpu::{self as g, util::DeviceExt};
 
-use crate::app::Context;
+use crate::app::Gpu;
 
 #[derive(Debug)]
 pub struct Buffer<T: Pod> {
@@ -25,13 +30,23 @@ impl<T: Pod> Deref for Buffer<T> {
 }
 
 impl<T: Pod> Buffer<T> {
-    pub fn from_data(
-        data: &[T],
-        label: Option<&str>,
-        usage: g::BufferUsages,
-        context: &Context,
-    ) -> Self {
-        let raw = context
+    /// Creates a buffer of `len` elements with unspecified contents.
+    pub fn new(len: usize, label: Option<&str>, usage: g::BufferUsages, gpu: &Gpu) -> Self {
+        let raw = gpu.device.create_buffer(&g::BufferDescriptor {
+            label,
+            size: (len * mem::size_of::<T>()) as g::BufferAddress,
+            usage,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            raw,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn from_data(data: &[T], label: Option<&str>, usage: g::BufferUsages, gpu: &Gpu) -> Self {
+        let raw = gpu
             .device
             .create_buffer_init(&g::util::BufferInitDescriptor {
                 label,
@@ -53,22 +68,646 @@ impl<T: Pod> Buffer<T> {
         self.size() == 0
     }
 
+    /// A typed view of the elements in `bounds`.
+    pub fn range(&self, bounds: impl RangeBounds<usize>) -> BufferRange<'_, T> {
+        let start = match bounds.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match bounds.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(
+            start <= end && end <= self.len(),
+            "range {start}..{end} out of bounds of a buffer of length {}",
+            self.len()
+        );
+        BufferRange {
+            buffer: self,
+            start,
+            end,
+        }
+    }
+
+    /// Copies the whole buffer back to the CPU, see `BufferRange::read_to_vec_async`.
+    pub async fn read_to_vec_async(&self, gpu: &Gpu) -> Result<Vec<T>> {
+        self.range(..).read_to_vec_async(gpu).await
+    }
+
+    /// Copies the whole buffer back to the CPU, blocking until the GPU is done with it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_to_vec(&self, gpu: &Gpu) -> Result<Vec<T>> {
+        self.range(..).read_to_vec(gpu)
+    }
+
+    /// Schedules a write of `data` starting at element `offset`, which happens before any
+    /// command submitted afterwards. Needs `COPY_DST` usage.
+    pub fn write(&self, offset: usize, data: &[T], gpu: &Gpu) {
+        let range = self.range(offset..offset + data.len());
+        assert_copy_aligned(range.byte_offset());
+        assert_copy_aligned(range.byte_size());
+        gpu.queue
+            .write_buffer(self, range.byte_offset(), bytemuck::cast_slice(data));
+    }
+
+    /// Copies `src` into this buffer starting at element `offset`. Needs `COPY_DST` usage on this
+    /// buffer and `COPY_SRC` usage on the source.
+    pub fn copy_from(&self, offset: usize, src: BufferRange<'_, T>, gpu: &Gpu) {
+        let dst = self.range(offset..offset + src.len());
+        assert_copy_aligned(src.byte_offset());
+        assert_copy_aligned(dst.byte_offset());
+        assert_copy_aligned(src.byte_size());
+
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&g::CommandEncoderDescriptor {
+                label: Some("buffer copy command encoder"),
+            });
+        encoder.copy_buffer_to_buffer(
+            src.buffer,
+            src.byte_offset(),
+            self,
+            dst.byte_offset(),
+            src.byte_size(),
+        );
+        gpu.queue.submit(iter::once(encoder.finish()));
+    }
+
+    /// Maps the buffer itself, which needs `MAP_READ` or `MAP_WRITE` usage. Prefer
+    /// `read_to_vec` to read a buffer that is used on the GPU.
     pub fn map_block(
         &mut self,
         mode: g::MapMode,
         bounds: impl RangeBounds<g::BufferAddress>,
-        context: &Context,
+        gpu: &Gpu,
     ) -> Result<()> {
         let (tx, rx) = std::sync::mpsc::channel();
         self.raw.map_async(mode, bounds, move |result| {
             tx.send(result).expect("failed to send buffer map result")
         });
-        context.device.poll(g::PollType::Wait)?;
+        gpu.device.poll(g::PollType::Wait)?;
         rx.recv().expect("failed to recieve buffer map result")?;
         Ok(())
     }
 }
 
+/// A range of the elements of a `Buffer`, with offsets and lengths in elements.
+#[derive(Debug)]
+pub struct BufferRange<'a, T: Pod> {
+    buffer: &'a Buffer<T>,
+    start: usize,
+    end: usize,
+}
+
+impl<T: Pod> Clone for BufferRange<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Pod> Copy for BufferRange<'_, T> {}
+
+impl<'a, T: Pod> BufferRange<'a, T> {
+    pub fn buffer(&self) -> &'a Buffer<T> {
+        self.buffer
+    }
+
+    /// Index of the first element in the buffer.
+    pub fn offset(&self) -> usize {
+        self.start
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn byte_offset(&self) -> g::BufferAddress {
+        (self.start * mem::size_of::<T>()) as g::BufferAddress
+    }
+
+    pub fn byte_size(&self) -> g::BufferAddress {
+        (self.len() * mem::size_of::<T>()) as g::BufferAddress
+    }
+
+    /// A slice of just this range, for example for a vertex buffer.
+    pub fn slice(&self) -> g::BufferSlice<'a> {
+        self.buffer
+            .slice(self.byte_offset()..self.byte_offset() + self.byte_size())
+    }
+
+    /// A binding of just this range, for a bind group entry.
+    pub fn binding(&self) -> g::BindingResource<'a> {
+        g::BindingResource::Buffer(g::BufferBinding {
+            buffer: self.buffer,
+            offset: self.byte_offset(),
+            size: NonZero::new(self.byte_size()),
+        })
+    }
+
+    /// Copies the range back to the CPU through a temporary staging buffer, so the buffer itself
+    /// only needs `COPY_SRC` usage. Natively the device is polled without blocking until the copy
+    /// is mapped, on the web the browser maps it.
+    pub async fn read_to_vec_async(&self, gpu: &Gpu) -> Result<Vec<T>> {
+        self.read_later(gpu).wait(gpu).await
+    }
+
+    /// Copies the range back to the CPU, blocking until the GPU is done with it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_to_vec(&self, gpu: &Gpu) -> Result<Vec<T>> {
+        self.read_later(gpu).wait_blocking(gpu)
+    }
+
+    /// Starts copying the range back to the CPU, to be picked up once the GPU is done, for
+    /// example on a later frame. See `read_to_vec_async`.
+    pub fn read_later(&self, gpu: &Gpu) -> Readback<T> {
+        if self.is_empty() {
+            return Readback {
+                staging_buffer: None,
+                len: 0,
+                mapped: oneshot::channel().1,
+                _marker: PhantomData,
+            };
+        }
+        assert_copy_aligned(self.byte_offset());
+
+        // copies have to be a multiple of `COPY_BUFFER_ALIGNMENT` long, for example for an odd
+        // number of `u8`s, so copy a little more and drop the extra bytes
+        let size = self
+            .byte_size()
+            .next_multiple_of(g::COPY_BUFFER_ALIGNMENT)
+            .min(self.buffer.size() - self.byte_offset());
+        assert_copy_aligned(size);
+
+        let staging_buffer = gpu.device.create_buffer(&g::BufferDescriptor {
+            label: Some("readback staging buffer"),
+            size,
+            usage: g::BufferUsages::MAP_READ | g::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&g::CommandEncoderDescriptor {
+                label: Some("readback command encoder"),
+            });
+        encoder.copy_buffer_to_buffer(self.buffer, self.byte_offset(), &staging_buffer, 0, size);
+        gpu.queue.submit(iter::once(encoder.finish()));
+
+        let (tx, rx) = oneshot::channel();
+        staging_buffer.map_async(g::MapMode::Read, .., move |result| {
+            // the receiver is only dropped with the readback, when nobody is interested anymore
+            let _ = tx.send(result);
+        });
+        Readback {
+            staging_buffer: Some(staging_buffer),
+            len: self.len(),
+            mapped: rx,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// A copy of a buffer range on its way back to the CPU, see `BufferRange::read_later`.
+#[derive(Debug)]
+pub struct Readback<T: Pod> {
+    /// `None` for an empty range, which has nothing to copy.
+    staging_buffer: Option<g::Buffer>,
+    len: usize,
+    mapped: oneshot::Receiver<Result<(), g::BufferAsyncError>>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> Readback<T> {
+    /// The data if the copy is mapped already, without waiting for it. Natively mapping only
+    /// progresses when the device is polled, which this does.
+    pub fn try_take(&mut self, gpu: &Gpu) -> Result<Option<Vec<T>>> {
+        if self.staging_buffer.is_none() {
+            return Ok(Some(vec![]));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        gpu.device.poll(g::PollType::Poll)?;
+        #[cfg(target_arch = "wasm32")]
+        let _ = gpu;
+        let Some(result) = self.mapped.try_recv().expect("buffer map callback dropped") else {
+            return Ok(None);
+        };
+        result?;
+        Ok(Some(self.take_mapped()))
+    }
+
+    /// Waits for the data without blocking, natively polling the device whenever the task is.
+    pub async fn wait(mut self, gpu: &Gpu) -> Result<Vec<T>> {
+        if self.staging_buffer.is_none() {
+            return Ok(vec![]);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = gpu;
+        future::poll_fn(|cx| -> Poll<Result<()>> {
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                if let Err(error) = gpu.device.poll(g::PollType::Poll) {
+                    return Poll::Ready(Err(error.into()));
+                }
+                // nothing wakes the task natively once the copy is mapped, so it checks again
+                cx.waker().wake_by_ref();
+            }
+            self.mapped
+                .poll_unpin(cx)
+                .map(|result| Ok(result.expect("buffer map callback dropped")?))
+        })
+        .await?;
+        Ok(self.take_mapped())
+    }
+
+    /// Waits for the data, blocking until the GPU is done.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn wait_blocking(mut self, gpu: &Gpu) -> Result<Vec<T>> {
+        gpu.device.poll(g::PollType::Wait)?;
+        Ok(self
+            .try_take(gpu)?
+            .expect("buffer mapped after waiting for the device"))
+    }
+
+    fn take_mapped(&self) -> Vec<T> {
+        let staging_buffer = self
+            .staging_buffer
+            .as_ref()
+            .expect("empty readbacks have nothing mapped");
+        let data = {
+            let bytes = staging_buffer.slice(..).get_mapped_range();
+            // the mapped range is not necessarily aligned for `T`
+            bytemuck::pod_collect_to_vec(&bytes[..self.len * mem::size_of::<T>()])
+        };
+        staging_buffer.unmap();
+        data
+    }
+}
+
+fn assert_copy_aligned(bytes: g::BufferAddress) {
+    assert!(
+        bytes.is_multiple_of(g::COPY_BUFFER_ALIGNMENT),
+        "buffer copies need {}-byte alignment, got {bytes}",
+        g::COPY_BUFFER_ALIGNMENT
+    );
+}
+
+/// A type with the same size in Rust as in WGSL, so that it can be copied into buffers as is.
+///
+/// Structs implement it with `impl_wgsl_struct!`, which also checks their field offsets.
+///
+/// # Safety
+///
+/// `ALIGN` must be the alignment of the WGSL type named by `wgsl_name` and every field must sit at
+/// the offset WGSL gives it, padding included, the way `WgpuMat3x3` pads the columns of a `Mat3`.
+pub unsafe trait WgslLayout: Pod {
+    /// Alignment of the type in the storage address space.
+    const ALIGN: usize;
+
+    fn wgsl_name() -> String;
+
+    /// Definitions of the structs the type is made of, dependencies first.
+    fn wgsl_structs() -> Vec<WgslStruct> {
+        vec![]
+    }
+}
+
+macro_rules! impl_wgsl_layout {
+    ($($ty:ty => $name:literal, $align:expr);* $(;)?) => {
+        $(unsafe impl WgslLayout for $ty {
+            const ALIGN: usize = $align;
+
+            fn wgsl_name() -> String {
+                $name.to_owned()
+            }
+        })*
+    };
+}
+
+impl_wgsl_layout! {
+    u32 => "u32", 4;
+    i32 => "i32", 4;
+    f32 => "f32", 4;
+    UVec2 => "vec2u", 8;
+    Vec2 => "vec2f", 8;
+    UVec4 => "vec4u", 16;
+    Vec4 => "vec4f", 16;
+    WgpuMat3x3 => "mat3x3f", 16;
+}
+
+unsafe impl<T: WgslLayout, const N: usize> WgslLayout for [T; N] {
+    const ALIGN: usize = T::ALIGN;
+
+    fn wgsl_name() -> String {
+        format!("array<{}, {N}>", T::wgsl_name())
+    }
+
+    fn wgsl_structs() -> Vec<WgslStruct> {
+        T::wgsl_structs()
+    }
+}
+
+/// The WGSL definition of a Rust struct, see `impl_wgsl_struct!`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WgslStruct {
+    pub name: String,
+    pub fields: Vec<WgslField>,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WgslField {
+    pub name: &'static str,
+    pub ty: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+impl WgslStruct {
+    /// Fields whose name starts with an underscore are padding. They are left out of the
+    /// definition and the field before them is given their size instead.
+    pub fn definition(&self) -> String {
+        let mut members: Vec<(&WgslField, usize)> = vec![];
+        for field in &self.fields {
+            match members.last_mut() {
+                Some((_, size)) if field.name.starts_with('_') => *size += field.size,
+                _ => members.push((field, field.size)),
+            }
+        }
+
+        let mut definition = format!("struct {} {{\n", self.name);
+        for (field, size) in members {
+            let attribute = if size != field.size {
+                format!("@size({size}) ")
+            } else {
+                String::new()
+            };
+            definition += &format!("    {attribute}{}: {},\n", field.name, field.ty);
+        }
+        definition += "}\n";
+        definition
+    }
+}
+
+/// The WGSL offset of a field of `size` bytes and alignment `align` following `end`, along with
+/// the end of the field, panicking if the Rust field at `rust_offset` is elsewhere.
+#[doc(hidden)]
+pub const fn wgsl_field_offset(
+    end: usize,
+    size: usize,
+    align: usize,
+    rust_offset: usize,
+) -> (usize, usize) {
+    let offset = end.next_multiple_of(align);
+    assert!(
+        offset == rust_offset,
+        "a struct field is not at the offset WGSL would place it, add padding fields"
+    );
+    (offset, offset + size)
+}
+
+/// Implements `WgslLayout` for a `#[repr(C)]` struct from its fields, checking at compile time
+/// that every field sits where WGSL would place it. Padding fields should be named with a leading
+/// underscore, see `WgslStruct::definition`.
+#[macro_export]
+macro_rules! impl_wgsl_struct {
+    ($ty:ident { $($field:ident: $field_ty:ty),* $(,)? }) => {
+        unsafe impl $crate::data::WgslLayout for $ty {
+            const ALIGN: usize = {
+                let mut align = 1;
+                $(if <$field_ty as $crate::data::WgslLayout>::ALIGN > align {
+                    align = <$field_ty as $crate::data::WgslLayout>::ALIGN;
+                })*
+                align
+            };
+
+            fn wgsl_name() -> String {
+                stringify!($ty).to_owned()
+            }
+
+            fn wgsl_structs() -> Vec<$crate::data::WgslStruct> {
+                let mut structs: Vec<$crate::data::WgslStruct> = vec![];
+                $(for dependency in <$field_ty as $crate::data::WgslLayout>::wgsl_structs() {
+                    if !structs.contains(&dependency) {
+                        structs.push(dependency);
+                    }
+                })*
+                structs.push($crate::data::WgslStruct {
+                    name: stringify!($ty).to_owned(),
+                    fields: vec![$($crate::data::WgslField {
+                        name: stringify!($field),
+                        ty: <$field_ty as $crate::data::WgslLayout>::wgsl_name(),
+                        offset: ::std::mem::offset_of!($ty, $field),
+                        size: ::std::mem::size_of::<$field_ty>(),
+                    }),*],
+                    size: ::std::mem::size_of::<$ty>(),
+                });
+                structs
+            }
+        }
+
+        const _: () = {
+            // the listed fields must be the struct's, in order and with their types
+            let _ = |value: $ty| {
+                let $ty { $($field),* } = value;
+                $(let _: $field_ty = $field;)*
+            };
+
+            let end = 0;
+            $(let (_, end) = $crate::data::wgsl_field_offset(
+                end,
+                ::std::mem::size_of::<$field_ty>(),
+                <$field_ty as $crate::data::WgslLayout>::ALIGN,
+                ::std::mem::offset_of!($ty, $field),
+            );)*
+            assert!(
+                end.next_multiple_of(<$ty as $crate::data::WgslLayout>::ALIGN)
+                    == ::std::mem::size_of::<$ty>(),
+                "a struct is not as large as WGSL would make it, add padding fields"
+            );
+        };
+    };
+}
+
+/// Fails to compile if `T` cannot be an element of a WGSL storage array, which places elements
+/// at multiples of their alignment.
+pub const fn assert_storage_layout<T: WgslLayout>() {
+    assert!(
+        mem::size_of::<T>().is_multiple_of(T::ALIGN),
+        "the size of a storage array element must be a multiple of its WGSL alignment"
+    );
+}
+
+/// Fails to compile if `T` cannot be the type of a WGSL uniform, which additionally needs the
+/// size padded to 16 bytes.
+pub const fn assert_uniform_layout<T: WgslLayout>() {
+    assert_storage_layout::<T>();
+    assert!(
+        mem::size_of::<T>().is_multiple_of(16),
+        "the size of a uniform must be a multiple of 16 bytes"
+    );
+}
+
+/// A uniform buffer holding a single `T`.
+#[derive(Debug)]
+pub struct UniformBuffer<T: WgslLayout> {
+    buffer: Buffer<T>,
+}
+
+impl<T: WgslLayout> Deref for UniformBuffer<T> {
+    type Target = g::Buffer;
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl<T: WgslLayout> UniformBuffer<T> {
+    pub fn new(value: &T, label: Option<&str>, gpu: &Gpu) -> Self {
+        const { assert_uniform_layout::<T>() };
+        Self {
+            buffer: Buffer::from_data(
+                slice::from_ref(value),
+                label,
+                g::BufferUsages::UNIFORM | g::BufferUsages::COPY_DST,
+                gpu,
+            ),
+        }
+    }
+
+    /// Schedules a write of `value`, which happens before any command submitted afterwards.
+    pub fn write(&self, value: &T, gpu: &Gpu) {
+        self.buffer.write(0, slice::from_ref(value), gpu);
+    }
+}
+
+/// A buffer of a varying number of elements, which is reallocated with doubled capacity when it
+/// runs out of space.
+///
+/// Bind groups and bindings refer to the buffer they were created with, so the writes that
+/// reallocate return `true` to signal that they must be rebuilt. Bindings usually cover just
+/// `range(..)`, in which case they have to be rebuilt whenever `len` changes as well.
+#[derive(Debug)]
+pub struct GrowableBuffer<T: WgslLayout> {
+    buffer: Buffer<T>,
+    len: usize,
+    label: Option<String>,
+    usage: g::BufferUsages,
+}
+
+impl<T: WgslLayout> GrowableBuffer<T> {
+    /// Creates a buffer holding `data`. `COPY_SRC` and `COPY_DST` are added to `usage`, since
+    /// writes and reallocations need them.
+    pub fn from_data(data: &[T], label: Option<&str>, usage: g::BufferUsages, gpu: &Gpu) -> Self {
+        const { assert_storage_layout::<T>() };
+        let usage = usage | g::BufferUsages::COPY_SRC | g::BufferUsages::COPY_DST;
+        let label = label.map(str::to_owned);
+        let mut buffer = Self {
+            buffer: Buffer::new(data.len().max(1), label.as_deref(), usage, gpu),
+            len: 0,
+            label,
+            usage,
+        };
+        let _ = buffer.write(data, gpu);
+        buffer
+    }
+
+    /// Number of elements in use.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of elements that fit without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The underlying buffer, including the unused capacity.
+    pub fn buffer(&self) -> &Buffer<T> {
+        &self.buffer
+    }
+
+    /// A typed view of the elements in use within `bounds`.
+    pub fn range(&self, bounds: impl RangeBounds<usize>) -> BufferRange<'_, T> {
+        let start = match bounds.start_bound() {
+            Bound::Included(&start) => Bound::Included(start),
+            Bound::Excluded(&start) => Bound::Excluded(start),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let end = match bounds.end_bound() {
+            Bound::Included(&end) => Bound::Included(end),
+            Bound::Excluded(&end) => Bound::Excluded(end),
+            Bound::Unbounded => Bound::Excluded(self.len),
+        };
+        let range = self.buffer.range((start, end));
+        assert!(
+            range.offset() + range.len() <= self.len,
+            "range out of bounds of a buffer of length {}",
+            self.len
+        );
+        range
+    }
+
+    /// Replaces the contents with `data`, returning whether the buffer was reallocated.
+    #[must_use = "bind groups referring to the buffer must be rebuilt when it is reallocated"]
+    pub fn write(&mut self, data: &[T], gpu: &Gpu) -> bool {
+        let reallocated = data.len() > self.capacity();
+        if reallocated {
+            self.buffer = self.allocate(data.len(), gpu);
+        }
+        self.len = data.len();
+        self.buffer.write(0, data, gpu);
+        reallocated
+    }
+
+    /// Appends `data`, returning whether the buffer was reallocated.
+    #[must_use = "bind groups referring to the buffer must be rebuilt when it is reallocated"]
+    pub fn extend(&mut self, data: &[T], gpu: &Gpu) -> bool {
+        let new_len = self.len + data.len();
+        let reallocated = new_len > self.capacity();
+        if reallocated {
+            let buffer = self.allocate(new_len, gpu);
+            buffer.copy_from(0, self.range(..), gpu);
+            self.buffer = buffer;
+        }
+        self.buffer.write(self.len, data, gpu);
+        self.len = new_len;
+        reallocated
+    }
+
+    /// Changes the number of elements in use, keeping the first `len` elements. Elements past the
+    /// previous length have unspecified contents.
+    #[must_use = "bind groups referring to the buffer must be rebuilt when it is reallocated"]
+    pub fn resize(&mut self, len: usize, gpu: &Gpu) -> bool {
+        let reallocated = len > self.capacity();
+        if reallocated {
+            let buffer = self.allocate(len, gpu);
+            buffer.copy_from(0, self.range(..), gpu);
+            self.buffer = buffer;
+        }
+        self.len = len;
+        reallocated
+    }
+
+    fn allocate(&self, len: usize, gpu: &Gpu) -> Buffer<T> {
+        let max_len = gpu.device.limits().max_buffer_size as usize / mem::size_of::<T>();
+        let capacity = len
+            .max(self.capacity() * 2)
+            .next_power_of_two()
+            .min(max_len)
+            .max(len);
+        Buffer::new(capacity, self.label.as_deref(), self.usage, gpu)
+    }
+}
+
 #[derive(Debug, Clone, Copy, Zeroable, Pod)]
 #[repr(transparent)]
 pub struct WgpuMat3x3([Vec4; 3]);
@@ -0,0 +1,40 @@
+//! Stateless counter-based hashing. Unlike `Rng`, every value is addressed directly by a seed, a
+//! stream and an index, and the same functions exist in WGSL in `hash.wgsl`, so that values
+//! sampled on the GPU can be reproduced exactly on the CPU.
+
+use glam::{UVec3, Vec2, uvec3, vec2};
+
+/// The WGSL implementation, to be prepended to shaders that use it.
+pub const WGSL: &str = include_str!("hash.wgsl");
+
+// based on <https://jcgt.org/published/0009/03/02/>
+fn pcg3d(input: UVec3) -> UVec3 {
+    let mut v = UVec3::from_array(
+        input
+            .to_array()
+            .map(|x| x.wrapping_mul(1664525).wrapping_add(1013904223)),
+    );
+    v.x = v.x.wrapping_add(v.y.wrapping_mul(v.z));
+    v.y = v.y.wrapping_add(v.z.wrapping_mul(v.x));
+    v.z = v.z.wrapping_add(v.x.wrapping_mul(v.y));
+    v = v ^ (v >> 16);
+    v.x = v.x.wrapping_add(v.y.wrapping_mul(v.z));
+    v.y = v.y.wrapping_add(v.z.wrapping_mul(v.x));
+    v.z = v.z.wrapping_add(v.x.wrapping_mul(v.y));
+    v
+}
+
+pub fn hash(seed: u32, stream: u32, index: u32) -> u32 {
+    pcg3d(uvec3(seed, stream, index)).x
+}
+
+/// Uniformly distributed in `[0, 1)` with 24 bits of precision.
+pub fn hash_f32(seed: u32, stream: u32, index: u32) -> f32 {
+    (hash(seed, stream, index) >> 8) as f32 / 16777216.0
+}
+
+/// Uniformly distributed in `[0, 1)²` with 24 bits of precision.
+pub fn hash_vec2(seed: u32, stream: u32, index: u32) -> Vec2 {
+    let v = pcg3d(uvec3(seed, stream, index));
+    vec2((v.x >> 8) as f32, (v.y >> 8) as f32) / 16777216.0
+}
@@ -0,0 +1,27 @@
+// Counter-based hashing, kept bit-for-bit identical to `hash.rs`.
+
+// based on <https://jcgt.org/published/0009/03/02/>
+fn pcg3d(input: vec3u) -> vec3u {
+    var v = input * 1664525u + 1013904223u;
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    v ^= v >> vec3u(16u);
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    return v;
+}
+
+fn hash(seed: u32, stream: u32, index: u32) -> u32 {
+    return pcg3d(vec3u(seed, stream, index)).x;
+}
+
+fn hash_f32(seed: u32, stream: u32, index: u32) -> f32 {
+    return f32(hash(seed, stream, index) >> 8u) / 16777216.0;
+}
+
+fn hash_vec2(seed: u32, stream: u32, index: u32) -> vec2f {
+    let v = pcg3d(vec3u(seed, stream, index));
+    return vec2f(vec2u(v.x, v.y) >> vec2u(8u)) / 16777216.0;
+}
@@ -0,0 +1,156 @@
+use std::io::{self, BufRead, Write};
+
+use glam::{Vec3, Vec4};
+use itertools::Itertools;
+
+/// A linear RGBA image in row-major order, starting from the top row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec4>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, fill: Vec4) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![fill; width as usize * height as usize],
+        }
+    }
+
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Vec4>) -> Self {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Vec4] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Vec4] {
+        &mut self.pixels
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Vec4 {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Vec4) {
+        let idx = self.index(x, y);
+        self.pixels[idx] = color;
+    }
+
+    /// Copies `src` into the image with its top left corner at `x`, `y`.
+    pub fn copy_from(&mut self, x: u32, y: u32, src: &Image) {
+        assert!(x + src.width <= self.width && y + src.height <= self.height);
+        for (src_y, src_row) in src.pixels.chunks(src.width as usize).enumerate() {
+            let start = self.index(x, y + src_y as u32);
+            self.pixels[start..start + src_row.len()].copy_from_slice(src_row);
+        }
+    }
+
+    /// Writes the image as a binary PPM, dropping the alpha channel and quantizing to 8 bits.
+    pub fn write_ppm(&self, mut writer: impl Write) -> io::Result<()> {
+        Self::write_ppm_header(&mut writer, self.width, self.height)?;
+        self.write_ppm_rows(writer)
+    }
+
+    /// Writes the header of a binary PPM, for images written a few rows at a time with
+    /// `write_ppm_rows`.
+    pub fn write_ppm_header(mut writer: impl Write, width: u32, height: u32) -> io::Result<()> {
+        write!(writer, "P6\n{width} {height}\n255\n")
+    }
+
+    /// Writes the pixels of the image as PPM rows, without a header.
+    pub fn write_ppm_rows(&self, mut writer: impl Write) -> io::Result<()> {
+        let bytes = self
+            .pixels
+            .iter()
+            .flat_map(|pixel| pixel.truncate().to_array())
+            .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect_vec();
+        writer.write_all(&bytes)
+    }
+
+    /// Writes the image as a binary PAM with an alpha channel, quantized to 8 bits. The pixels
+    /// are premultiplied, as rendered, while PAM stores straight alpha, so colors are divided by
+    /// their alpha first.
+    pub fn write_pam(&self, mut writer: impl Write) -> io::Result<()> {
+        Self::write_pam_header(&mut writer, self.width, self.height)?;
+        self.write_pam_rows(writer)
+    }
+
+    /// Writes the header of a binary PAM, for images written a few rows at a time with
+    /// `write_pam_rows`.
+    pub fn write_pam_header(mut writer: impl Write, width: u32, height: u32) -> io::Result<()> {
+        write!(
+            writer,
+            "P7\nWIDTH {width}\nHEIGHT {height}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n"
+        )
+    }
+
+    /// Writes the pixels of the image as PAM rows, without a header, see `write_pam`.
+    pub fn write_pam_rows(&self, mut writer: impl Write) -> io::Result<()> {
+        let bytes = self
+            .pixels
+            .iter()
+            .flat_map(|&pixel| {
+                let rgb = if pixel.w > 0.0 {
+                    pixel.truncate() / pixel.w
+                } else {
+                    Vec3::ZERO
+                };
+                rgb.extend(pixel.w).to_array()
+            })
+            .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect_vec();
+        writer.write_all(&bytes)
+    }
+
+    /// Reads a binary PPM with 8-bit channels as written by `write_ppm`, with an opaque alpha.
+    pub fn read_ppm(mut reader: impl BufRead) -> io::Result<Self> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut header = vec![];
+        while header.iter().filter(|&&byte| byte == b'\n').count() < 3 {
+            let n_read = reader.read_until(b'\n', &mut header)?;
+            if n_read == 0 {
+                return Err(invalid("truncated PPM header"));
+            }
+        }
+        let header = String::from_utf8(header).map_err(|_| invalid("non-UTF-8 PPM header"))?;
+        let Some(("P6", width, height, "255")) = header.split_ascii_whitespace().collect_tuple()
+        else {
+            return Err(invalid("unsupported PPM header"));
+        };
+        let width = width.parse().map_err(|_| invalid("invalid PPM width"))?;
+        let height = height.parse().map_err(|_| invalid("invalid PPM height"))?;
+
+        let mut bytes = vec![0; width as usize * height as usize * 3];
+        reader.read_exact(&mut bytes)?;
+        let pixels = bytes
+            .chunks(3)
+            .map(|rgb| Vec4::new(rgb[0] as f32, rgb[1] as f32, rgb[2] as f32, 255.0) / 255.0)
+            .collect_vec();
+        Ok(Self::from_pixels(width, height, pixels))
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height);
+        y as usize * self.width as usize + x as usize
+    }
+}
@@ -1,42 +1,71 @@
-use std::time::Duration;
-
-use color_eyre::Result;
-use glam::vec4;
+use std::{env, path::PathBuf, time::Duration};
 
 use app::App;
-use dance::DanceSubAppBuilder;
+use color_eyre::Result;
+use dance::{
+    DanceSubAppBuilder, clock::ClockHandle, scene::Scene, sequence::Sequence,
+    transformations::TransformationGenerator,
+};
 use log::LogSubApp;
+use shader::ShaderDir;
 
 pub mod app;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cli;
 pub mod dance;
 pub mod data;
+pub mod hash;
+pub mod image;
 pub mod log;
 pub mod random;
+pub mod shader;
 pub mod time;
 
-pub fn run() -> Result<()> {
+/// Opens the window on `scene`, on `sequence`, or on a random dance without either, animated by
+/// `clock` from its current time rather than from the scene's. Without a GPU adapter, the dance is
+/// simulated on the CPU instead, see `FallbackApp`.
+pub fn run(scene: Option<Scene>, sequence: Option<Sequence>, clock: ClockHandle) -> Result<()> {
     env_logger::init();
-    App::new(
-        Duration::from_millis(10),
-        winit::window::WindowAttributes::default()
-            .with_inner_size(winit::dpi::PhysicalSize::new(1080, 1080)),
-    )
-    .add_sub_app(LogSubApp)
-    .add_sub_app(DanceSubAppBuilder {
-        n_points: 2_000_000,
-        transformation_colors: vec![
-            vec4(0.9, 0.9, 0.6, 1.0),
-            vec4(0.6, 0.9, 0.9, 1.0),
-            vec4(0.9, 0.6, 0.9, 1.0),
-            vec4(0.9, 0.6, 0.4, 1.0),
-            vec4(0.4, 0.6, 0.9, 1.0),
-        ],
-    })
-    .run()
+    let mut dance = DanceSubAppBuilder {
+        n_points: Scene::DEFAULT_N_POINTS,
+        transformation_colors: TransformationGenerator::DEFAULT_COLORS.to_vec(),
+        transformation_seed: None,
+        transformation_period: None,
+        clock,
+        sequence,
+        contractivity_bound: None,
+        render_options: Default::default(),
+        snippets: Default::default(),
+        background: Default::default(),
+        // load the shaders from the source tree and reload them on change while developing
+        shader_dir: env::var_os("PARTICLE_DANCE_HOT_RELOAD")
+            .map(|_| PathBuf::from(ShaderDir::SOURCE_DIR)),
+    };
+    if let Some(scene) = scene {
+        dance.n_points = scene.n_points;
+        dance.transformation_colors = scene.colors;
+        dance.transformation_seed = Some(scene.seed);
+        dance.transformation_period = scene.period;
+        dance.snippets = scene.snippets;
+        dance.render_options = scene.render_options;
+        dance.background = scene.background;
+    }
+    let window_attributes = winit::window::WindowAttributes::default()
+        .with_inner_size(winit::dpi::PhysicalSize::new(1080, 1080));
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(error) = futures::executor::block_on(app::Gpu::probe_adapter()) {
+        ::log::warn!("simulating on the CPU, no GPU adapter: {error}");
+        return dance::fallback::FallbackApp::new(window_attributes, dance).run();
+    }
+    App::new(Duration::from_millis(10), window_attributes)
+        .add_sub_app(LogSubApp)
+        .add_sub_app(dance)
+        .run()
 }
 
+/// Runs a random dance in `canvas`, animated by `clock`.
 #[cfg(target_arch = "wasm32")]
-pub fn run_web(canvas: web_sys::HtmlCanvasElement) {
+pub fn run_web(canvas: web_sys::HtmlCanvasElement, clock: ClockHandle) {
     use winit::platform::web::WindowAttributesExtWebSys;
 
     env_logger::init();
@@ -48,14 +77,17 @@ pub fn run_web(canvas: web_sys::HtmlCanvasElement) {
     )
     .add_sub_app(LogSubApp)
     .add_sub_app(DanceSubAppBuilder {
-        n_points: 2_000_000,
-        transformation_colors: vec![
-            vec4(0.9, 0.9, 0.6, 1.0),
-            vec4(0.6, 0.9, 0.9, 1.0),
-            vec4(0.9, 0.6, 0.9, 1.0),
-            vec4(0.9, 0.6, 0.4, 1.0),
-            vec4(0.4, 0.6, 0.9, 1.0),
-        ],
+        n_points: Scene::DEFAULT_N_POINTS,
+        transformation_colors: TransformationGenerator::DEFAULT_COLORS.to_vec(),
+        transformation_seed: None,
+        transformation_period: None,
+        clock,
+        sequence: None,
+        contractivity_bound: None,
+        render_options: Default::default(),
+        snippets: Default::default(),
+        background: Default::default(),
+        shader_dir: None,
     })
     .run();
 }
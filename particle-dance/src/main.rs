@@ -1,5 +1,16 @@
+#[cfg(not(target_arch = "wasm32"))]
+use clap::Parser;
 use color_eyre::eyre::Result;
+#[cfg(not(target_arch = "wasm32"))]
+use particle_dance::cli::Cli;
 
+#[cfg(not(target_arch = "wasm32"))]
 fn main() -> Result<()> {
-    particle_dance::run()
+    Cli::parse().run()
+}
+
+/// The web has no command line, so this opens a random dance.
+#[cfg(target_arch = "wasm32")]
+fn main() -> Result<()> {
+    particle_dance::run(None, None, Default::default())
 }
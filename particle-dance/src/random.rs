@@ -1,34 +1,74 @@
-use std::{ops::BitXor, sync::OnceLock};
+use std::{
+    collections::hash_map::RandomState,
+    f32,
+    hash::{BuildHasher, Hasher},
+    ops::{Range, RangeInclusive},
+    sync::{
+        OnceLock,
+        atomic::{AtomicU32, Ordering},
+    },
+};
 
-use glam::{Vec2, vec2};
+use glam::{Vec2, Vec3, Vec4, vec2, vec3, vec4};
 
 use crate::time::Instant;
 
+/// A xoshiro128++ generator <https://prng.di.unimi.it/>.
 #[derive(Debug, Clone)]
 pub struct Rng {
-    seed: u32,
+    state: [u32; 4],
 }
 
 pub trait Random {
     fn random(rng: &mut Rng) -> Self;
 }
 
-const K: u32 = 0x517c_c1b7; // (2^32 - 1) / pi
-const L: u32 = 0x5e2d_58d8; // (2^32 - 1) / e
+pub trait RandomRange {
+    type Output;
+    fn random(self, rng: &mut Rng) -> Self::Output;
+}
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
 
 impl Rng {
     pub fn new() -> Self {
         static EPOCH: OnceLock<Instant> = OnceLock::new();
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
         let epoch = *EPOCH.get_or_init(Instant::now);
-        Self::with_seed((Instant::now() - epoch).as_nanos() as u32)
+
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128((Instant::now() - epoch).as_nanos());
+        hasher.write_u32(COUNTER.fetch_add(1, Ordering::Relaxed));
+        Self::with_seed_u64(hasher.finish())
     }
 
     pub fn with_seed(seed: u32) -> Self {
-        Self { seed }
+        Self::with_seed_u64(seed as u64)
     }
 
+    fn with_seed_u64(seed: u64) -> Self {
+        // expand the seed with SplitMix64, as recommended by the xoshiro authors
+        let mut seed = seed;
+        let mut next = || {
+            seed = seed.wrapping_add(GOLDEN_GAMMA);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+        let (a, b) = (next(), next());
+        Self {
+            state: [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32],
+        }
+    }
+
+    /// Derives an independent stream from the current state and `rhs`.
     pub fn hash(&mut self, rhs: u32) -> &mut Self {
-        self.seed = self.seed.rotate_left(5).bitxor(rhs).wrapping_mul(K);
+        let [a, b, c, d] = self.state.map(u64::from);
+        let key = (a | b << 32)
+            ^ (c | d << 32).rotate_left(29)
+            ^ (rhs as u64 + 1).wrapping_mul(GOLDEN_GAMMA);
+        *self = Self::with_seed_u64(key);
         self
     }
 
@@ -36,9 +76,45 @@ impl Rng {
         T::random(self)
     }
 
+    pub fn random_range<R: RandomRange>(&mut self, range: R) -> R::Output {
+        range.random(self)
+    }
+
     pub fn random_u32(&mut self) -> u32 {
-        // based on FxHash <https://nnethercote.github.io/2021/12/08/a-brutally-effective-hash-function-in-rust.html>
-        self.hash(L).seed
+        let [s0, s1, s2, s3] = &mut self.state;
+        let result = s0.wrapping_add(*s3).rotate_left(7).wrapping_add(*s0);
+
+        let t = *s1 << 9;
+        *s2 ^= *s0;
+        *s3 ^= *s1;
+        *s1 ^= *s2;
+        *s0 ^= *s3;
+        *s2 ^= t;
+        *s3 = s3.rotate_left(11);
+
+        result
+    }
+
+    /// A sample of the standard normal distribution.
+    pub fn random_gaussian(&mut self) -> f32 {
+        self.random_gaussian_pair().x
+    }
+
+    /// Two independent samples of the standard normal distribution.
+    pub fn random_gaussian_pair(&mut self) -> Vec2 {
+        // Box-Muller transform, `1 - u` is in `(0, 1]` so that the logarithm stays finite
+        let radius = (-2.0 * (1.0 - self.random::<f32>()).ln()).sqrt();
+        self.random_unit_circle() * radius
+    }
+
+    /// A uniformly distributed point inside the unit disk.
+    pub fn random_unit_disk(&mut self) -> Vec2 {
+        self.random_unit_circle() * self.random::<f32>().sqrt()
+    }
+
+    /// A uniformly distributed point on the unit circle.
+    pub fn random_unit_circle(&mut self) -> Vec2 {
+        Vec2::from_angle(self.random::<f32>() * f32::consts::TAU)
     }
 }
 
@@ -54,9 +130,16 @@ impl Random for u32 {
     }
 }
 
+impl Random for bool {
+    fn random(rng: &mut Rng) -> Self {
+        rng.random_u32() >> 31 != 0
+    }
+}
+
 impl Random for f32 {
+    /// Uniformly distributed in `[0, 1)` with the full 24 bits of precision.
     fn random(rng: &mut Rng) -> f32 {
-        rng.random_u32() as u16 as f32 / u16::MAX as f32
+        (rng.random_u32() >> 8) as f32 / (1 << 24) as f32
     }
 }
 
@@ -65,3 +148,60 @@ impl Random for Vec2 {
         vec2(rng.random(), rng.random())
     }
 }
+
+impl Random for Vec3 {
+    fn random(rng: &mut Rng) -> Self {
+        vec3(rng.random(), rng.random(), rng.random())
+    }
+}
+
+impl Random for Vec4 {
+    fn random(rng: &mut Rng) -> Self {
+        vec4(rng.random(), rng.random(), rng.random(), rng.random())
+    }
+}
+
+impl<T: Random, const N: usize> Random for [T; N] {
+    fn random(rng: &mut Rng) -> Self {
+        std::array::from_fn(|_| rng.random())
+    }
+}
+
+impl RandomRange for Range<f32> {
+    type Output = f32;
+    fn random(self, rng: &mut Rng) -> f32 {
+        assert!(self.start < self.end, "empty range");
+        (self.start + (self.end - self.start) * rng.random::<f32>()).min(self.end.next_down())
+    }
+}
+
+impl RandomRange for Range<u32> {
+    type Output = u32;
+    fn random(self, rng: &mut Rng) -> u32 {
+        assert!(self.start < self.end, "empty range");
+        self.start + random_below(rng, self.end - self.start)
+    }
+}
+
+impl RandomRange for RangeInclusive<u32> {
+    type Output = u32;
+    fn random(self, rng: &mut Rng) -> u32 {
+        let (start, end) = self.into_inner();
+        assert!(start <= end, "empty range");
+        match (end - start).checked_add(1) {
+            Some(len) => start + random_below(rng, len),
+            None => rng.random_u32(),
+        }
+    }
+}
+
+/// An unbiased integer in `0..bound`, using Lemire's multiply-and-reject method.
+fn random_below(rng: &mut Rng, bound: u32) -> u32 {
+    let threshold = bound.wrapping_neg() % bound;
+    loop {
+        let product = rng.random_u32() as u64 * bound as u64;
+        if product as u32 >= threshold {
+            return (product >> 32) as u32;
+        }
+    }
+}
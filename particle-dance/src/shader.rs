@@ -0,0 +1,253 @@
+//! Assembles WGSL shaders from named modules, so that shared functions and the struct definitions
+//! generated from Rust types are written down once.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use itertools::Itertools;
+
+use crate::{
+    app::Gpu,
+    data::{WgslLayout, WgslStruct},
+    hash,
+};
+
+/// Resolves `#import name` lines by splicing in the module `name`, once per shader no matter how
+/// often it is imported.
+#[derive(Debug, Clone, Default)]
+pub struct Composer {
+    modules: HashMap<String, String>,
+    structs: Vec<WgslStruct>,
+}
+
+impl Composer {
+    /// A composer with the `hash` module.
+    pub fn new() -> Self {
+        Self::default().with_module("hash", hash::WGSL)
+    }
+
+    pub fn with_module(mut self, name: &str, source: &str) -> Self {
+        self.modules.insert(name.to_owned(), source.to_owned());
+        self
+    }
+
+    /// Adds a module defining `T` and one for every struct it is made of, each named after its
+    /// struct.
+    pub fn with_struct<T: WgslLayout>(mut self) -> Self {
+        let structs = T::wgsl_structs();
+        for definition in &structs {
+            let mut source = String::new();
+            for dependency in &structs {
+                let refers_to = |ty: &str| {
+                    ty == dependency.name || ty.starts_with(&format!("array<{},", dependency.name))
+                };
+                if definition.fields.iter().any(|field| refers_to(&field.ty)) {
+                    source += &format!("#import {}\n", dependency.name);
+                }
+            }
+            source += &definition.definition();
+            self = self.with_module(&definition.name, &source);
+
+            if !self.structs.contains(definition) {
+                self.structs.push(definition.clone());
+            }
+        }
+        self
+    }
+
+    /// Every struct added with `with_struct`.
+    pub fn structs(&self) -> &[WgslStruct] {
+        &self.structs
+    }
+
+    /// Resolves the imports of `source`, named `name` in errors.
+    pub fn compose(&self, name: &str, source: &str) -> Result<String> {
+        let mut composed = String::new();
+        self.compose_into(
+            name,
+            source,
+            &mut HashSet::new(),
+            &mut vec![name.to_owned()],
+            &mut composed,
+        )?;
+        Ok(composed)
+    }
+
+    fn compose_into(
+        &self,
+        name: &str,
+        source: &str,
+        imported: &mut HashSet<String>,
+        stack: &mut Vec<String>,
+        composed: &mut String,
+    ) -> Result<()> {
+        for (line_idx, line) in source.lines().enumerate() {
+            let Some(import) = line.trim().strip_prefix("#import ") else {
+                *composed += line;
+                *composed += "\n";
+                continue;
+            };
+
+            let import = import.trim();
+            if stack.iter().any(|module| module == import) {
+                bail!("{name}:{}: circular import of `{import}`", line_idx + 1);
+            }
+            if !imported.insert(import.to_owned()) {
+                continue;
+            }
+            let module = self
+                .modules
+                .get(import)
+                .ok_or_else(|| eyre!("{name}:{}: unknown module `{import}`", line_idx + 1))?;
+
+            stack.push(import.to_owned());
+            self.compose_into(import, module, imported, stack, composed)?;
+            stack.pop();
+        }
+        Ok(())
+    }
+
+    /// Composes `source` and validates the result, with readable errors pointing into it.
+    pub fn compose_validated(&self, name: &str, source: &str) -> Result<String> {
+        let composed = self.compose(name, source)?;
+        validate(name, &composed)?;
+        Ok(composed)
+    }
+}
+
+/// Parses and validates a complete shader the way `wgpu` would.
+pub fn validate(name: &str, source: &str) -> Result<naga::Module> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|error| eyre!("{}", error.emit_to_string_with_path(source, name)))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|error| eyre!("{}", error.emit_to_string_with_path(source, name)))?;
+    Ok(module)
+}
+
+/// Checks that WGSL lays out the definitions of `structs` like Rust does, field by field.
+pub fn check_layouts(structs: &[WgslStruct]) -> Result<()> {
+    let source = structs
+        .iter()
+        .map(WgslStruct::definition)
+        .collect::<String>();
+    let module = validate("struct definitions", &source)?;
+
+    for definition in structs {
+        let Some(naga::TypeInner::Struct { members, span }) = module
+            .types
+            .iter()
+            .find(|(_, ty)| ty.name.as_deref() == Some(&definition.name))
+            .map(|(_, ty)| &ty.inner)
+        else {
+            bail!(
+                "struct `{}` is missing from its definition",
+                definition.name
+            );
+        };
+
+        let fields = definition
+            .fields
+            .iter()
+            .filter(|field| !field.name.starts_with('_'))
+            .collect_vec();
+        if fields.len() != members.len() {
+            bail!(
+                "`{}` has {} fields in Rust, but {} in WGSL",
+                definition.name,
+                fields.len(),
+                members.len()
+            );
+        }
+        for (field, member) in fields.into_iter().zip(members) {
+            if member.name.as_deref() != Some(field.name) || member.offset as usize != field.offset
+            {
+                bail!(
+                    "`{}.{}` is at offset {} in Rust, but WGSL puts `{}` at {}",
+                    definition.name,
+                    field.name,
+                    field.offset,
+                    member.name.as_deref().unwrap_or("_"),
+                    member.offset
+                );
+            }
+        }
+        if *span as usize != definition.size {
+            bail!(
+                "`{}` is {} bytes in Rust, but {span} bytes in WGSL",
+                definition.name,
+                definition.size
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Runs `f`, turning the validation errors `wgpu` reports for the objects it creates into an
+/// error instead of a panic. In the browser errors are only reported asynchronously, so they
+/// cannot be caught here and `f`'s sources should be checked with `validate` first.
+pub fn catch_validation_errors<T>(gpu: &Gpu, f: impl FnOnce() -> T) -> Result<T> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        gpu.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let value = f();
+        match futures::executor::block_on(gpu.device.pop_error_scope()) {
+            Some(error) => Err(eyre!("{error}")),
+            None => Ok(value),
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = gpu;
+        Ok(f())
+    }
+}
+
+/// Shader files read from a directory at runtime instead of the copies embedded in the binary, so
+/// that they can be edited while the app runs.
+#[derive(Debug, Clone)]
+pub struct ShaderDir {
+    dir: PathBuf,
+    modified: HashMap<String, Option<SystemTime>>,
+}
+
+impl ShaderDir {
+    /// The crate's own source directory, for development builds.
+    pub const SOURCE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            modified: HashMap::new(),
+        }
+    }
+
+    /// Reads the file at `path` relative to the directory.
+    pub fn read(&self, path: &str) -> Result<String> {
+        let path = self.dir.join(path);
+        fs::read_to_string(&path).wrap_err_with(|| format!("failed to read {}", path.display()))
+    }
+
+    /// Whether any of `paths` was modified since the previous call. The first call only records
+    /// the modification times.
+    pub fn poll_modified(&mut self, paths: &[&str]) -> bool {
+        let mut any_modified = false;
+        for &path in paths {
+            let modified = fs::metadata(self.dir.join(path))
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            if let Some(previous) = self.modified.insert(path.to_owned(), modified) {
+                any_modified |= previous != modified;
+            }
+        }
+        any_modified
+    }
+}
@@ -0,0 +1,180 @@
+//! Checks the attractor statistics against attractors whose dimensions are known.
+
+use clap::Parser;
+use glam::{Vec2, Vec4, vec2};
+use particle_dance::{
+    cli::{Cli, Command},
+    dance::{
+        Dance, Point, Transformation,
+        analysis::{self, AttractorStatistics},
+        cpu::CpuSimulator,
+        density::DensityRender,
+        offscreen::OffscreenTarget,
+        seeding::PointDistribution,
+    },
+    random::Rng,
+};
+
+mod common;
+
+use common::{assert_near, gpu, sierpinski};
+
+/// Maps that each halve the plane towards one of `centers`.
+fn halving(centers: &[Vec2]) -> Vec<Transformation> {
+    centers
+        .iter()
+        .map(|&center| Transformation {
+            center,
+            scale: 0.5,
+            angle: 0.0,
+            color: Vec4::ONE,
+        })
+        .collect()
+}
+
+/// Points settled on the attractor of `transformations`.
+fn settled_points(transformations: &[Transformation], n_points: usize) -> Vec<Point> {
+    let points = PointDistribution::Square.sample(n_points, &mut Rng::with_seed(0x5eed));
+    let mut simulator = CpuSimulator::new(points, transformations);
+    for _ in 0..30 {
+        simulator.step();
+    }
+    simulator.points().to_vec()
+}
+
+#[test]
+fn sierpinski_triangle_has_its_dimension() {
+    let transformations = sierpinski();
+    let statistics = AttractorStatistics::from_points(
+        &settled_points(&transformations, 200_000),
+        &transformations,
+    );
+    let dimension = 3.0_f32.ln() / 2.0_f32.ln();
+    assert_near(statistics.box_counting_dimension, dimension, 0.1);
+    // every part of the triangle is visited equally often
+    assert_near(statistics.correlation_dimension, dimension, 0.15);
+    assert_near(statistics.lyapunov_exponent, 0.5_f32.ln(), 1e-4);
+    assert!(statistics.coverage > 0.1 && statistics.coverage < 0.6);
+}
+
+#[test]
+fn filled_square_and_segment_have_integer_dimensions() {
+    // sampled directly, so that the measure is exactly uniform
+    let square = PointDistribution::Square.sample(200_000, &mut Rng::with_seed(0x5eed));
+    let transformations = sierpinski();
+    let statistics = AttractorStatistics::from_points(&square, &transformations);
+    assert_near(statistics.box_counting_dimension, 2.0, 0.02);
+    assert_near(statistics.correlation_dimension, 2.0, 0.02);
+    assert!(statistics.coverage > 0.99, "{}", statistics.coverage);
+    assert!(statistics.entropy > 0.99, "{}", statistics.entropy);
+
+    let segment = square
+        .iter()
+        .map(|point| Point {
+            pos: vec2(point.pos.x, 0.0),
+        })
+        .collect::<Vec<_>>();
+    let statistics = AttractorStatistics::from_points(&segment, &transformations);
+    assert_near(statistics.box_counting_dimension, 1.0, 0.02);
+    assert_near(statistics.correlation_dimension, 1.0, 0.02);
+    assert!(statistics.coverage < 0.1, "{}", statistics.coverage);
+}
+
+#[test]
+fn simulated_filled_square_has_two_dimensions() {
+    let transformations = halving(&[
+        vec2(-1.0, -1.0),
+        vec2(1.0, -1.0),
+        vec2(-1.0, 1.0),
+        vec2(1.0, 1.0),
+    ]);
+    let statistics = AttractorStatistics::from_points(
+        &settled_points(&transformations, 200_000),
+        &transformations,
+    );
+    assert_near(statistics.box_counting_dimension, 2.0, 0.05);
+    assert!(statistics.coverage > 0.95, "{}", statistics.coverage);
+}
+
+#[test]
+fn a_single_spot_has_no_dimension() {
+    let transformations = halving(&[Vec2::ZERO]);
+    let statistics = AttractorStatistics::from_points(
+        &settled_points(&transformations, 10_000),
+        &transformations,
+    );
+    assert_eq!(statistics.box_counting_dimension, 0.0);
+    assert_eq!(statistics.entropy, 0.0);
+}
+
+#[test]
+fn lyapunov_exponent_averages_the_stretching() {
+    let transformations = [0.25, 0.5, 2.0].map(|scale| Transformation {
+        center: Vec2::ZERO,
+        scale,
+        angle: 1.0,
+        color: Vec4::ONE,
+    });
+    let expected = (0.25_f32.ln() + 0.5_f32.ln() + 2.0_f32.ln()) / 3.0;
+    let exponent = analysis::lyapunov_exponent(&transformations, 100_000, &mut Rng::with_seed(1));
+    assert_near(exponent, expected, 0.02);
+}
+
+#[test]
+fn density_histogram_has_the_dimension_of_the_points() {
+    let gpu = gpu();
+    let transformations = sierpinski();
+    let points = PointDistribution::Square.sample(20_000, &mut Rng::with_seed(0x5eed));
+    let mut dance = Dance::new(&points, &transformations, OffscreenTarget::FORMAT, &gpu);
+    let field = dance
+        .render_density_field(
+            DensityRender {
+                warmup_steps: 20,
+                steps: 100,
+                ..DensityRender::new(256, 256)
+            },
+            &gpu,
+        )
+        .unwrap();
+    let statistics = AttractorStatistics::from_density(&field, &transformations);
+    assert_near(
+        statistics.box_counting_dimension,
+        3.0_f32.ln() / 2.0_f32.ln(),
+        0.1,
+    );
+    assert!(statistics.coverage > 0.0 && statistics.coverage < 0.5);
+}
+
+#[test]
+fn cli_analyzes_on_the_cpu() {
+    let cli = Cli::try_parse_from([
+        "particle-dance",
+        "analyze",
+        "--cpu",
+        "--points",
+        "20000",
+        "--seed",
+        "7",
+    ])
+    .unwrap();
+    let Some(Command::Analyze(args)) = cli.command else {
+        panic!("{cli:?}");
+    };
+    assert_eq!(args.transformations.seed, 7);
+    let statistics = args.analyze().unwrap();
+    assert!(statistics.box_counting_dimension > 0.0 && statistics.box_counting_dimension <= 2.1);
+    assert!(statistics.lyapunov_exponent < 0.0);
+
+    let density_on_cpu = Cli::try_parse_from([
+        "particle-dance",
+        "analyze",
+        "--cpu",
+        "--density-steps",
+        "10",
+    ])
+    .unwrap();
+    let Some(Command::Analyze(args)) = density_on_cpu.command else {
+        panic!("{density_on_cpu:?}");
+    };
+    assert!(args.analyze().is_err());
+}
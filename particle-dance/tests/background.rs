@@ -0,0 +1,263 @@
+//! Checks backgrounds and transparent output on a headless fallback adapter.
+
+use glam::{Vec2, Vec4, vec2, vec4};
+use particle_dance::{
+    app::Gpu,
+    dance::{
+        Dance, Point, Transformation,
+        background::Background,
+        offscreen::OffscreenTarget,
+        post::PostProcess,
+        render::{BlendMode, RenderOptions, Splat},
+        still::Still,
+    },
+    image::Image,
+};
+
+mod common;
+
+use common::{STEP, assert_close, gpu};
+
+/// A dance with white points at `positions`.
+fn dance(positions: &[Vec2], gpu: &Gpu) -> Dance {
+    let points = positions
+        .iter()
+        .map(|&pos| Point { pos })
+        .collect::<Vec<_>>();
+    // centered away from the points, which the default color snippet divides by the distance to
+    let transformations = [Transformation {
+        center: vec2(5.0, 5.0),
+        scale: 1.0,
+        angle: 0.0,
+        color: Vec4::ONE,
+    }];
+    Dance::new(&points, &transformations, OffscreenTarget::FORMAT, gpu)
+}
+
+/// A dance whose only point is out of view, leaving just the background.
+fn empty_dance(gpu: &Gpu) -> Dance {
+    dance(&[vec2(100.0, 100.0)], gpu)
+}
+
+fn render(dance: &mut Dance, width: u32, height: u32, gpu: &Gpu) -> Image {
+    let mut target = OffscreenTarget::new(width, height, gpu);
+    dance.render(target.texture(), gpu).unwrap();
+    target.read(gpu).unwrap()
+}
+
+#[test]
+fn default_background_is_opaque_black() {
+    let gpu = gpu();
+    let mut dance = empty_dance(&gpu);
+    assert_eq!(*dance.background(), Background::Solid(Vec4::W));
+    let image = render(&mut dance, 8, 8, &gpu);
+    assert!(image.pixels().iter().all(|&pixel| pixel == Vec4::W));
+}
+
+#[test]
+fn solid_background_fills_with_and_without_hdr() {
+    let gpu = gpu();
+    let mut dance = empty_dance(&gpu);
+    let color = vec4(0.2, 0.4, 0.6, 1.0);
+    dance.set_background(Background::Solid(color), &gpu);
+    for hdr in [true, false] {
+        dance.set_render_options(
+            RenderOptions {
+                hdr,
+                ..Default::default()
+            },
+            &gpu,
+        );
+        let image = render(&mut dance, 8, 8, &gpu);
+        for &pixel in image.pixels() {
+            assert_close(pixel, color);
+        }
+    }
+}
+
+#[test]
+fn linear_gradient_runs_from_start_to_end() {
+    let gpu = gpu();
+    let mut dance = empty_dance(&gpu);
+    dance.set_background(
+        Background::Linear {
+            start: vec2(-1.0, 0.0),
+            end: vec2(1.0, 0.0),
+            start_color: Vec4::W,
+            end_color: Vec4::ONE,
+        },
+        &gpu,
+    );
+    let image = render(&mut dance, 64, 16, &gpu);
+    for y in 0..16 {
+        assert_eq!(image.get(0, y), image.get(0, 0));
+        let row = (0..64).map(|x| image.get(x, y).x).collect::<Vec<_>>();
+        assert!(row.is_sorted(), "{row:?}");
+        // sampled at the pixel centers, half a pixel in from either end
+        assert!(row[0] < 0.01 && row[63] > 0.99, "{row:?}");
+        assert!((row[32] - 0.5).abs() < 2.0 * STEP, "{row:?}");
+    }
+}
+
+#[test]
+fn radial_gradient_reaches_the_edge_color() {
+    let gpu = gpu();
+    let mut dance = empty_dance(&gpu);
+    let edge_color = vec4(0.0, 0.0, 1.0, 1.0);
+    dance.set_background(
+        Background::Radial {
+            center: Vec2::ZERO,
+            radius: 0.5,
+            center_color: Vec4::ONE,
+            edge_color,
+        },
+        &gpu,
+    );
+    let image = render(&mut dance, 32, 32, &gpu);
+    assert!(image.get(16, 16).x > 0.9);
+    for (x, y) in [(0, 0), (31, 0), (0, 31), (31, 31), (4, 16), (16, 4)] {
+        assert_eq!(image.get(x, y), edge_color);
+    }
+}
+
+#[test]
+fn image_background_is_stretched_over_the_image() {
+    let gpu = gpu();
+    let mut dance = empty_dance(&gpu);
+    let quadrants = Image::from_pixels(
+        2,
+        2,
+        vec![
+            vec4(1.0, 0.0, 0.0, 1.0),
+            vec4(0.0, 1.0, 0.0, 1.0),
+            vec4(0.0, 0.0, 1.0, 1.0),
+            Vec4::ONE,
+        ],
+    );
+    dance.set_background(Background::Image(quadrants.clone()), &gpu);
+    let image = render(&mut dance, 32, 32, &gpu);
+    for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        assert_close(image.get(x * 31, y * 31), quadrants.get(x, y));
+    }
+}
+
+#[test]
+fn transparent_output_is_premultiplied() {
+    let gpu = gpu();
+    let positions = (0..200)
+        .map(|i| Vec2::from_angle(i as f32) * 0.5)
+        .collect::<Vec<_>>();
+    let mut dance = dance(&positions, &gpu);
+    dance.set_background(Background::TRANSPARENT, &gpu);
+    for blend_mode in [BlendMode::Replace, BlendMode::Additive, BlendMode::Alpha] {
+        for hdr in [true, false] {
+            dance.set_render_options(
+                RenderOptions {
+                    blend_mode,
+                    intensity: 0.5,
+                    hdr,
+                    ..Default::default()
+                },
+                &gpu,
+            );
+            let image = render(&mut dance, 32, 32, &gpu);
+            assert_eq!(image.get(16, 16), Vec4::ZERO);
+            assert!(image.pixels().iter().any(|pixel| pixel.w > 0.0));
+            for pixel in image.pixels() {
+                assert!(pixel.truncate().max_element() <= pixel.w, "{pixel}");
+            }
+        }
+    }
+
+    // a translucent background shows through as premultiplied too
+    dance.set_background(Background::Solid(vec4(1.0, 0.0, 0.0, 0.5)), &gpu);
+    let image = render(&mut dance, 32, 32, &gpu);
+    assert_close(image.get(16, 16), vec4(0.5, 0.0, 0.0, 0.5));
+}
+
+#[test]
+fn translucent_points_are_graded_straight() {
+    let gpu = gpu();
+    let mut dance = dance(&[Vec2::ZERO], &gpu);
+    dance.set_background(Background::TRANSPARENT, &gpu);
+    let mut center = |gamma| {
+        dance.set_render_options(
+            RenderOptions {
+                intensity: 0.5,
+                splat: Splat::Disc { radius: 64.0 },
+                post_process: PostProcess {
+                    gamma,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            &gpu,
+        );
+        render(&mut dance, 8, 8, &gpu).get(4, 4)
+    };
+
+    let linear = center(1.0);
+    assert!((linear.w - 0.5).abs() <= STEP, "{linear}");
+    let straight = linear.truncate() / linear.w;
+    // the alpha is left as it is, and the color graded as if the points were opaque
+    let expected = (straight.powf(1.0 / 2.2) * 0.5).extend(0.5);
+    assert_close(center(2.2), expected);
+}
+
+#[test]
+fn pam_has_straight_alpha() {
+    let image = Image::from_pixels(2, 1, vec![vec4(0.5, 0.25, 0.0, 0.5), Vec4::ZERO]);
+    let mut pam = vec![];
+    image.write_pam(&mut pam).unwrap();
+    let header = "P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+    assert_eq!(&pam[..header.len()], header.as_bytes());
+    assert_eq!(&pam[header.len()..], [255, 128, 0, 128, 0, 0, 0, 0]);
+}
+
+#[test]
+fn tiled_stills_continue_the_gradient() {
+    let gpu = gpu();
+    let mut dance = empty_dance(&gpu);
+    dance.set_background(
+        Background::Linear {
+            start: vec2(-1.0, 1.0),
+            end: vec2(1.0, -1.0),
+            start_color: Vec4::W,
+            end_color: Vec4::ONE,
+        },
+        &gpu,
+    );
+    let still = Still {
+        supersampling: 2,
+        ..Still::new(40, 24)
+    };
+    let whole = dance.render_still(still, &gpu).unwrap();
+    let tiled = dance
+        .render_still(
+            Still {
+                tile_size: 7,
+                ..still
+            },
+            &gpu,
+        )
+        .unwrap();
+    for (&a, &b) in whole.pixels().iter().zip(tiled.pixels()) {
+        assert_close(a, b);
+    }
+    assert!(whole.get(0, 0).x < 0.1 && whole.get(39, 23).x > 0.9);
+
+    let mut pam = vec![];
+    dance
+        .write_still_pam(
+            Still {
+                tile_size: 7,
+                ..still
+            },
+            &mut pam,
+            &gpu,
+        )
+        .unwrap();
+    let mut expected = vec![];
+    tiled.write_pam(&mut expected).unwrap();
+    assert_eq!(pam, expected);
+}
@@ -0,0 +1,123 @@
+//! Checks the animation clock of the window and the options that set it up.
+
+use clap::Parser;
+use particle_dance::{
+    cli::Cli,
+    dance::{
+        clock::{AnimationClock, ClockHandle},
+        scene::Scene,
+        snippets::Snippets,
+        transformations::TransformationGenerator,
+    },
+};
+
+mod common;
+
+use common::assert_near;
+
+#[test]
+fn clock_advances_by_speed() {
+    let mut clock = AnimationClock::new(2.0);
+    assert_eq!(clock.speed(), AnimationClock::DEFAULT_SPEED);
+    assert!(clock.tick(0.5));
+    assert_near(clock.time(), 2.05, 1e-5);
+
+    clock.set_speed(2.0);
+    assert!(clock.tick(0.25));
+    assert_near(clock.time(), 2.55, 1e-5);
+
+    clock.reverse();
+    assert_eq!(clock.speed(), -2.0);
+    assert!(clock.tick(0.5));
+    assert_near(clock.time(), 1.55, 1e-5);
+}
+
+#[test]
+fn paused_clock_holds_still_and_skips_the_simulation() {
+    let mut clock = AnimationClock::new(1.0);
+    clock.toggle_paused();
+    assert!(clock.is_paused());
+    for _ in 0..10 {
+        assert!(!clock.tick(0.1));
+    }
+    assert_eq!(clock.time(), 1.0);
+
+    clock.seek(3.0);
+    assert!(!clock.tick(0.1));
+    assert_eq!(clock.time(), 3.0);
+
+    clock.toggle_paused();
+    assert!(clock.tick(1.0));
+    assert_near(clock.time(), 3.1, 1e-5);
+}
+
+#[test]
+fn steps_move_a_frame_each_in_either_direction() {
+    let mut clock = AnimationClock::new(0.0);
+    clock.set_speed(-1.5);
+    clock.step(2);
+    assert!(clock.is_paused());
+    let frame = 1.5 * AnimationClock::STEP_DURATION;
+    // the wall time of the frame does not matter, and the steps go forwards even in reverse
+    assert!(clock.tick(10.0));
+    assert_near(clock.time(), frame, 1e-5);
+    assert!(clock.tick(0.0));
+    assert_near(clock.time(), 2.0 * frame, 1e-5);
+    assert!(!clock.tick(0.1));
+    assert_near(clock.time(), 2.0 * frame, 1e-5);
+
+    clock.step(-3);
+    for _ in 0..3 {
+        assert!(clock.tick(0.1));
+    }
+    assert!(!clock.tick(0.1));
+    assert_near(clock.time(), -frame, 1e-5);
+
+    // resuming drops the steps not taken yet
+    clock.step(5);
+    clock.set_paused(false);
+    assert!(clock.tick(0.0));
+    assert_near(clock.time(), -frame, 1e-5);
+}
+
+#[test]
+fn handle_shares_the_clock() {
+    let handle = ClockHandle::new(AnimationClock::new(0.5));
+    let controller = handle.clone();
+    controller.lock().seek(4.0);
+    controller.lock().set_paused(true);
+    assert_eq!(handle.lock().time(), 4.0);
+    assert!(handle.lock().is_paused());
+}
+
+#[test]
+fn cli_sets_up_the_window_clock() {
+    let cli = Cli::try_parse_from(["particle-dance", "--speed", "-0.5", "--paused"]).unwrap();
+    assert!(cli.command.is_none());
+    let clock = cli.window.clock(None).unwrap();
+    assert_eq!(clock.speed(), -0.5);
+    assert!(clock.is_paused());
+    assert_eq!(clock.time(), 0.0);
+
+    let scene = Scene {
+        seed: 1,
+        colors: TransformationGenerator::default_colors(2),
+        time: 7.0,
+        period: None,
+        n_points: 1000,
+        snippets: Snippets::default(),
+        render_options: Default::default(),
+        background: Default::default(),
+    };
+    let cli = Cli::try_parse_from(["particle-dance"]).unwrap();
+    let clock = cli.window.clock(Some(&scene)).unwrap();
+    assert_eq!(clock.time(), 7.0);
+    assert_eq!(clock.speed(), AnimationClock::DEFAULT_SPEED);
+    assert!(!clock.is_paused());
+
+    let cli = Cli::try_parse_from(["particle-dance", "--time", "-2"]).unwrap();
+    assert_eq!(cli.window.clock(Some(&scene)).unwrap().time(), -2.0);
+
+    let cli = Cli::try_parse_from(["particle-dance", "--speed", "inf"]).unwrap();
+    assert!(cli.window.clock(None).is_err());
+}
@@ -0,0 +1,55 @@
+//! Helpers shared by the integration tests, each of which uses only some of them.
+#![allow(dead_code)]
+
+use std::fmt::Display;
+
+use glam::{vec2, vec4};
+use particle_dance::{app::Gpu, dance::Transformation};
+
+/// One step of an 8-bit channel.
+pub const STEP: f32 = 1.0 / 255.0;
+
+/// A headless GPU on the fallback adapter, usually a software rasterizer that renders alike on
+/// every machine. Panics without one, as the GPU tests cannot tell anything then.
+pub fn gpu() -> Gpu {
+    match futures::executor::block_on(Gpu::headless(true)) {
+        Ok(gpu) => gpu,
+        Err(error) => panic!("GPU tests need a fallback adapter, such as llvmpipe: {error}"),
+    }
+}
+
+pub fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "{actual} is not within {tolerance} of {expected}"
+    );
+}
+
+/// Asserts that two colors differ by no more than rounding to 8 bits would, on every channel.
+pub fn assert_close<V, const N: usize>(actual: V, expected: V)
+where
+    V: Copy + Display + Into<[f32; N]>,
+{
+    let close = actual
+        .into()
+        .into_iter()
+        .zip(expected.into())
+        .all(|(actual, expected)| (actual - expected).abs() <= 1.5 * STEP);
+    assert!(close, "{actual} is not {expected}");
+}
+
+/// The three halving maps of the Sierpinski triangle, colored red, green and blue.
+pub fn sierpinski() -> Vec<Transformation> {
+    [
+        (vec2(0.0, 0.8), vec4(1.0, 0.2, 0.2, 1.0)),
+        (vec2(-0.7, -0.6), vec4(0.2, 1.0, 0.2, 1.0)),
+        (vec2(0.7, -0.6), vec4(0.2, 0.2, 1.0, 1.0)),
+    ]
+    .map(|(center, color)| Transformation {
+        center,
+        scale: 0.5,
+        angle: 0.0,
+        color,
+    })
+    .to_vec()
+}
@@ -0,0 +1,157 @@
+//! Round trips through `Buffer` on a headless fallback adapter.
+
+use std::{thread, time::Duration};
+
+use glam::{Vec2, vec2};
+use itertools::Itertools;
+use particle_dance::{
+    dance::{Point, Transformation},
+    data::{Buffer, GrowableBuffer, WgpuMat3x3, assert_storage_layout, assert_uniform_layout},
+};
+use wgpu as g;
+
+mod common;
+
+use common::gpu;
+
+const USAGE: g::BufferUsages = g::BufferUsages::STORAGE
+    .union(g::BufferUsages::COPY_SRC)
+    .union(g::BufferUsages::COPY_DST);
+
+fn data(len: usize) -> Vec<Vec2> {
+    (0..len).map(|i| vec2(i as f32, -(i as f32))).collect_vec()
+}
+
+#[test]
+fn read_returns_initial_data() {
+    let gpu = gpu();
+    let buffer = Buffer::from_data(&data(100), None, USAGE, &gpu);
+    assert_eq!(buffer.len(), 100);
+    assert_eq!(buffer.read_to_vec(&gpu).unwrap(), data(100));
+    assert_eq!(
+        futures::executor::block_on(buffer.read_to_vec_async(&gpu)).unwrap(),
+        data(100)
+    );
+}
+
+#[test]
+fn readbacks_are_polled_until_mapped() {
+    let gpu = gpu();
+    let buffer = Buffer::from_data(&data(100), None, USAGE, &gpu);
+    let mut readback = buffer.range(10..20).read_later(&gpu);
+    let read = loop {
+        if let Some(read) = readback.try_take(&gpu).unwrap() {
+            break read;
+        }
+        thread::sleep(Duration::from_millis(1));
+    };
+    assert_eq!(read, data(100)[10..20]);
+
+    let empty = buffer.range(5..5).read_later(&gpu);
+    assert!(
+        futures::executor::block_on(empty.wait(&gpu))
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn ranges_are_in_elements() {
+    let gpu = gpu();
+    let buffer = Buffer::from_data(&data(100), None, USAGE, &gpu);
+
+    let range = buffer.range(10..25);
+    assert_eq!((range.offset(), range.len()), (10, 15));
+    assert_eq!(range.byte_offset(), 10 * 8);
+    assert_eq!(range.byte_size(), 15 * 8);
+    assert_eq!(range.read_to_vec(&gpu).unwrap(), data(100)[10..25]);
+
+    assert_eq!(
+        buffer.range(90..).read_to_vec(&gpu).unwrap(),
+        data(100)[90..]
+    );
+    assert_eq!(
+        buffer.range(..=2).read_to_vec(&gpu).unwrap(),
+        data(100)[..=2]
+    );
+    assert!(buffer.range(50..50).read_to_vec(&gpu).unwrap().is_empty());
+}
+
+#[test]
+fn write_overwrites_only_its_range() {
+    let gpu = gpu();
+    let buffer = Buffer::from_data(&data(100), None, USAGE, &gpu);
+    buffer.write(20, &[Vec2::ONE; 5], &gpu);
+
+    let mut expected = data(100);
+    expected[20..25].fill(Vec2::ONE);
+    assert_eq!(buffer.read_to_vec(&gpu).unwrap(), expected);
+}
+
+#[test]
+fn copy_from_copies_a_range() {
+    let gpu = gpu();
+    let src = Buffer::from_data(&data(100), None, USAGE, &gpu);
+    let dst = Buffer::from_data(&[Vec2::ZERO; 50], None, USAGE, &gpu);
+    dst.copy_from(10, src.range(60..80), &gpu);
+
+    let mut expected = vec![Vec2::ZERO; 50];
+    expected[10..30].copy_from_slice(&data(100)[60..80]);
+    assert_eq!(dst.read_to_vec(&gpu).unwrap(), expected);
+}
+
+#[test]
+fn unaligned_lengths_are_read_whole() {
+    let gpu = gpu();
+    let bytes = (0..=255).collect_vec();
+    let buffer = Buffer::<u8>::from_data(&bytes, None, USAGE, &gpu);
+    // an aligned offset with a length that is not a multiple of 4
+    assert_eq!(buffer.range(8..15).read_to_vec(&gpu).unwrap(), bytes[8..15]);
+}
+
+// the layouts the shaders rely on
+const _: () = assert_storage_layout::<Point>();
+const _: () = assert_storage_layout::<Transformation>();
+const _: () = assert_uniform_layout::<Transformation>();
+const _: () = assert_uniform_layout::<WgpuMat3x3>();
+
+#[test]
+fn growable_buffer_doubles_capacity() {
+    let gpu = gpu();
+    let mut buffer = GrowableBuffer::from_data(&data(10), None, g::BufferUsages::STORAGE, &gpu);
+    assert_eq!((buffer.len(), buffer.capacity()), (10, 10));
+
+    // shrinking and growing back within the capacity keeps the buffer
+    assert!(!buffer.write(&data(3), &gpu));
+    assert_eq!((buffer.len(), buffer.capacity()), (3, 10));
+    assert!(!buffer.write(&data(10), &gpu));
+
+    assert!(buffer.write(&data(11), &gpu));
+    assert_eq!((buffer.len(), buffer.capacity()), (11, 32));
+    assert_eq!(buffer.range(..).read_to_vec(&gpu).unwrap(), data(11));
+
+    assert!(buffer.write(&data(100), &gpu));
+    assert_eq!(buffer.capacity(), 128);
+}
+
+#[test]
+fn growable_buffer_keeps_contents_when_extended() {
+    let gpu = gpu();
+    let mut buffer = GrowableBuffer::from_data(&data(4), None, g::BufferUsages::STORAGE, &gpu);
+    assert!(buffer.extend(&data(10)[4..], &gpu));
+    assert_eq!(buffer.range(..).read_to_vec(&gpu).unwrap(), data(10));
+
+    assert!(!buffer.resize(6, &gpu));
+    assert!(buffer.resize(20, &gpu));
+    assert_eq!(buffer.range(..6).read_to_vec(&gpu).unwrap(), data(6));
+    assert_eq!(buffer.len(), 20);
+}
+
+#[test]
+fn growable_buffer_ranges_stop_at_len() {
+    let gpu = gpu();
+    let mut buffer = GrowableBuffer::from_data(&data(10), None, g::BufferUsages::STORAGE, &gpu);
+    assert!(!buffer.write(&data(5), &gpu));
+    assert_eq!(buffer.range(..).len(), 5);
+    assert_eq!(buffer.range(2..).read_to_vec(&gpu).unwrap(), data(5)[2..]);
+}
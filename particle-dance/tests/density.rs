@@ -0,0 +1,231 @@
+//! Checks tiled density renders on a headless fallback adapter.
+
+use glam::{Vec2, Vec4, vec2, vec4};
+use itertools::Itertools;
+use particle_dance::{
+    app::Gpu,
+    dance::{
+        Dance, Point, Transformation, density::DensityRender, histogram::Bin,
+        offscreen::OffscreenTarget, seeding::PointDistribution,
+    },
+    image::Image,
+    random::Rng,
+};
+
+mod common;
+
+use common::{STEP, gpu, sierpinski};
+
+fn sierpinski_dance(gpu: &Gpu) -> Dance {
+    let points = PointDistribution::Square.sample(2000, &mut Rng::with_seed(0x5eed));
+    Dance::new(&points, &sierpinski(), OffscreenTarget::FORMAT, gpu)
+}
+
+/// The fraction of pixels that differ by more than a quantization step.
+fn differing_fraction(a: &Image, b: &Image) -> f32 {
+    assert_eq!((a.width(), a.height()), (b.width(), b.height()));
+    let n_differing = a
+        .pixels()
+        .iter()
+        .zip(b.pixels())
+        .filter(|(a, b)| (**a - **b).abs().max_element() > STEP)
+        .count();
+    n_differing as f32 / a.pixels().len() as f32
+}
+
+#[test]
+fn tiles_are_normalized_together() {
+    let gpu = gpu();
+    let mut dance = sierpinski_dance(&gpu);
+    let render = DensityRender {
+        warmup_steps: 10,
+        steps: 20,
+        ..DensityRender::new(48, 40)
+    };
+
+    let whole = dance.render_density(render, &gpu).unwrap();
+    let tiled = dance
+        .render_density(
+            DensityRender {
+                tile_size: 11,
+                ..render
+            },
+            &gpu,
+        )
+        .unwrap();
+    // a point right on a pixel edge may land on either side of it depending on the tile
+    let fraction = differing_fraction(&whole, &tiled);
+    assert!(fraction < 0.01, "{fraction}");
+
+    // only the most visited pixel of the whole image is at full brightness, not one per tile
+    let n_brightest = |image: &Image| {
+        image
+            .pixels()
+            .iter()
+            .filter(|pixel| pixel.truncate().max_element() >= 1.0 - STEP)
+            .count()
+    };
+    assert!(n_brightest(&tiled) <= n_brightest(&whole) + 1);
+}
+
+#[test]
+fn a_still_point_is_counted_every_step() {
+    let gpu = gpu();
+    let color = vec4(0.2, 0.4, 0.8, 1.0);
+    let mut dance = still_point_dance(Vec2::ZERO, color, &gpu);
+    let image = dance
+        .render_density(DensityRender::new(9, 9), &gpu)
+        .unwrap();
+    for y in 0..9 {
+        for x in 0..9 {
+            let expected = if (x, y) == (4, 4) { color } else { Vec4::W };
+            assert!(
+                image.get(x, y).abs_diff_eq(expected, STEP),
+                "({x}, {y}): {}",
+                image.get(x, y)
+            );
+        }
+    }
+}
+
+#[test]
+fn points_and_frame_are_restored() {
+    let gpu = gpu();
+    let mut dance = sierpinski_dance(&gpu);
+    dance.step(&gpu);
+    let points = dance.read_points(&gpu).unwrap();
+    let mut target = OffscreenTarget::new(16, 16, &gpu);
+    dance.render(target.texture(), &gpu).unwrap();
+    let frame = target.read(&gpu).unwrap();
+
+    let render = DensityRender {
+        tile_size: 5,
+        ..DensityRender::new(12, 12)
+    };
+    dance.render_density(render, &gpu).unwrap();
+    let restored = dance.read_points(&gpu).unwrap();
+    assert!(restored.iter().zip(&points).all(|(a, b)| a.pos == b.pos));
+    dance.render(target.texture(), &gpu).unwrap();
+    assert_eq!(target.read(&gpu).unwrap(), frame);
+}
+
+#[test]
+fn ppm_is_written_a_row_of_tiles_at_a_time() {
+    let gpu = gpu();
+    let mut dance = sierpinski_dance(&gpu);
+    let render = DensityRender {
+        steps: 10,
+        tile_size: 7,
+        ..DensityRender::new(15, 10)
+    };
+
+    let mut streamed = vec![];
+    dance
+        .write_density_ppm(render, &mut streamed, &gpu)
+        .unwrap();
+    let mut whole = vec![];
+    dance
+        .render_density(render, &gpu)
+        .unwrap()
+        .write_ppm(&mut whole)
+        .unwrap();
+    assert_eq!(streamed, whole);
+}
+
+/// A dance whose single point stays at `pos`, in `color`.
+fn still_point_dance(pos: Vec2, color: Vec4, gpu: &Gpu) -> Dance {
+    // the identity, centered away from the point, which the default color snippet divides by the
+    // distance to
+    let transformations = [Transformation {
+        center: vec2(5.0, 5.0),
+        scale: 1.0,
+        angle: 0.0,
+        color,
+    }];
+    Dance::new(
+        &[Point { pos }],
+        &transformations,
+        OffscreenTarget::FORMAT,
+        gpu,
+    )
+}
+
+#[test]
+fn raw_field_keeps_counts_and_color_sums() {
+    let gpu = gpu();
+    let color = vec4(0.2, 0.4, 0.8, 1.0);
+    let mut dance = still_point_dance(vec2(0.5, 0.5), color, &gpu);
+    let render = DensityRender {
+        steps: 30,
+        ..DensityRender::new(9, 9)
+    };
+    let field = dance.render_density_field(render, &gpu).unwrap();
+    assert_eq!((field.width(), field.height()), (9, 9));
+    for y in 0..9 {
+        for x in 0..9 {
+            let bin = field.get(x, y);
+            if (x, y) == (6, 2) {
+                assert_eq!(bin.count, 30);
+                assert!(bin.color.abs_diff_eq(color.truncate() * 30.0, 30.0 * STEP));
+            } else {
+                assert_eq!(bin, Bin::default(), "({x}, {y})");
+            }
+        }
+    }
+
+    let mut pfm = vec![];
+    field.write_counts_pfm(&mut pfm).unwrap();
+    let header = b"Pf\n9 9\n-1.0\n";
+    assert_eq!(&pfm[..header.len()], header);
+    let counts = pfm[header.len()..]
+        .chunks(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(counts.len(), 81);
+    // PFM rows go from the bottom up
+    assert_eq!(counts[(8 - 2) * 9 + 6], 30.0);
+    assert_eq!(counts.iter().sum::<f32>(), 30.0);
+
+    let mut pfm = vec![];
+    field.write_colors_pfm(&mut pfm).unwrap();
+    assert!(pfm.starts_with(b"PF\n9 9\n-1.0\n"));
+    assert_eq!(pfm.len(), header.len() + 81 * 3 * 4);
+}
+
+#[test]
+fn npy_is_written_a_row_of_tiles_at_a_time() {
+    let gpu = gpu();
+    let mut dance = sierpinski_dance(&gpu);
+    let render = DensityRender {
+        warmup_steps: 10,
+        steps: 10,
+        tile_size: 8,
+        ..DensityRender::new(20, 12)
+    };
+    let field = dance.render_density_field(render, &gpu).unwrap();
+    assert!(field.bins().iter().any(|bin| bin.count > 0));
+
+    let mut npy = vec![];
+    dance.write_density_npy(render, &mut npy, &gpu).unwrap();
+    let mut expected = vec![];
+    field.write_npy(&mut expected).unwrap();
+    assert_eq!(npy, expected);
+
+    assert!(npy.starts_with(b"\x93NUMPY\x01\x00"));
+    let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+    assert_eq!((10 + header_len) % 64, 0);
+    let header = std::str::from_utf8(&npy[10..10 + header_len]).unwrap();
+    assert!(header.contains("'shape': (12, 20, 4)"), "{header}");
+    assert!(header.ends_with('\n'));
+    assert_eq!(npy.len(), 10 + header_len + 12 * 20 * 4 * 4);
+
+    // the last channel of the first bin with points is its count
+    let (index, bin) = field
+        .bins()
+        .iter()
+        .find_position(|bin| bin.count > 0)
+        .unwrap();
+    let offset = 10 + header_len + (index * 4 + 3) * 4;
+    let count = f32::from_le_bytes(npy[offset..offset + 4].try_into().unwrap());
+    assert_eq!(count, bin.count as f32);
+}
@@ -0,0 +1,176 @@
+//! Cross-checks `hash.wgsl` against `hash.rs` on a headless fallback adapter, and checks that the
+//! hashes are uniformly distributed.
+
+use std::iter;
+
+use itertools::Itertools;
+use particle_dance::{
+    app::Gpu,
+    data::Buffer,
+    hash::{self, hash, hash_f32, hash_vec2},
+};
+use wgpu as g;
+
+mod common;
+
+use common::gpu;
+
+const KERNEL: &str = "
+@group(0) @binding(0) var<storage> inputs: array<vec4u>;
+@group(0) @binding(1) var<storage, read_write> outputs: array<vec4u>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    if id.x >= arrayLength(&inputs) {
+        return;
+    }
+    let input = inputs[id.x];
+    let v = hash_vec2(input.x, input.y, input.z);
+    outputs[id.x] = vec4u(
+        hash(input.x, input.y, input.z),
+        bitcast<u32>(hash_f32(input.x, input.y, input.z)),
+        bitcast<vec2u>(v),
+    );
+}
+";
+
+/// The Rust counterpart of `KERNEL`.
+fn expected(input: [u32; 4]) -> [u32; 4] {
+    let [seed, stream, index, _] = input;
+    let v = hash_vec2(seed, stream, index);
+    [
+        hash(seed, stream, index),
+        hash_f32(seed, stream, index).to_bits(),
+        v.x.to_bits(),
+        v.y.to_bits(),
+    ]
+}
+
+fn run_kernel(inputs: &[[u32; 4]], gpu: &Gpu) -> Vec<[u32; 4]> {
+    let shader = gpu.device.create_shader_module(g::ShaderModuleDescriptor {
+        label: Some("hash test shader"),
+        source: g::ShaderSource::Wgsl(format!("{}{KERNEL}", hash::WGSL).into()),
+    });
+    let pipeline = gpu
+        .device
+        .create_compute_pipeline(&g::ComputePipelineDescriptor {
+            label: Some("hash test pipeline"),
+            layout: None,
+            module: &shader,
+            entry_point: None,
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+    let input_buffer = Buffer::from_data(
+        inputs,
+        Some("hash test inputs"),
+        g::BufferUsages::STORAGE,
+        gpu,
+    );
+    let output_buffer = Buffer::<[u32; 4]>::new(
+        inputs.len(),
+        Some("hash test outputs"),
+        g::BufferUsages::STORAGE | g::BufferUsages::COPY_SRC,
+        gpu,
+    );
+
+    let bind_group = gpu.device.create_bind_group(&g::BindGroupDescriptor {
+        label: Some("hash test bind group"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            g::BindGroupEntry {
+                binding: 0,
+                resource: input_buffer.as_entire_binding(),
+            },
+            g::BindGroupEntry {
+                binding: 1,
+                resource: output_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder = gpu
+        .device
+        .create_command_encoder(&g::CommandEncoderDescriptor {
+            label: Some("hash test command encoder"),
+        });
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&Default::default());
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups((inputs.len() as u32).div_ceil(64), 1, 1);
+    }
+    gpu.queue.submit(iter::once(encoder.finish()));
+
+    output_buffer.read_to_vec(gpu).unwrap()
+}
+
+#[test]
+fn wgsl_matches_rust() {
+    let gpu = gpu();
+
+    let edge_values = [0, 1, 2, 0x7fff_ffff, 0x8000_0000, u32::MAX - 1, u32::MAX];
+    let mut inputs = edge_values
+        .into_iter()
+        .cartesian_product(edge_values)
+        .cartesian_product(edge_values)
+        .map(|((seed, stream), index)| [seed, stream, index, 0])
+        .collect_vec();
+    // a spread of ordinary values as well
+    inputs.extend((0..10_000).map(|i| [i / 100 * 7919, i % 4, i * 104_729, 0]));
+
+    let outputs = run_kernel(&inputs, &gpu);
+    for (input, output) in inputs.into_iter().zip(outputs) {
+        assert_eq!(output, expected(input), "input {input:?}");
+    }
+}
+
+#[test]
+fn hash_f32_is_in_unit_interval() {
+    let samples = (0..1_000_000).map(|i| hash_f32(0x5eed, 0, i)).collect_vec();
+    assert!(samples.iter().all(|x| (0.0..1.0).contains(x)));
+
+    let mean = samples.iter().map(|&x| x as f64).sum::<f64>() / samples.len() as f64;
+    assert!((mean - 0.5).abs() < 0.001, "mean = {mean}");
+}
+
+#[test]
+fn neighbouring_counters_are_uniform() {
+    // consecutive indices, streams and seeds should each spread evenly over the buckets
+    let n_samples = 256 * 1024;
+    for (name, f) in [
+        ("index", &(|i| hash(0x5eed, 0, i)) as &dyn Fn(u32) -> u32),
+        ("stream", &|i| hash(0x5eed, i, 0)),
+        ("seed", &|i| hash(i, 0, 0)),
+    ] {
+        let mut counts = [0usize; 256];
+        for i in 0..n_samples {
+            counts[(f(i) >> 24) as usize] += 1;
+        }
+        let expected = n_samples as f64 / counts.len() as f64;
+        let chi_squared = counts
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum::<f64>();
+        // the 0.1% critical value of the chi-squared distribution with 255 degrees of freedom
+        assert!(chi_squared < 330.5, "{name}: chi-squared = {chi_squared}");
+    }
+}
+
+#[test]
+fn hash_vec2_components_are_independent() {
+    let mut counts = [0usize; 64];
+    let n_samples = 640_000;
+    for i in 0..n_samples {
+        let v = hash_vec2(0x5eed, 0, i);
+        counts[(v.x * 8.0) as usize * 8 + (v.y * 8.0) as usize] += 1;
+    }
+    let expected = n_samples as f64 / counts.len() as f64;
+    let chi_squared = counts
+        .iter()
+        .map(|&count| (count as f64 - expected).powi(2) / expected)
+        .sum::<f64>();
+    // the 0.1% critical value of the chi-squared distribution with 63 degrees of freedom
+    assert!(chi_squared < 103.4, "chi-squared = {chi_squared}");
+}
@@ -0,0 +1,255 @@
+//! Checks the post-processing of the HDR target on a headless fallback adapter.
+
+use glam::{Vec2, Vec3, Vec4, vec2, vec4};
+use particle_dance::{
+    app::Gpu,
+    dance::{
+        Dance, Point, Transformation,
+        bloom::Bloom,
+        offscreen::OffscreenTarget,
+        post::{PostProcess, ToneMapping},
+        render::{BlendMode, RenderOptions, Splat},
+    },
+    image::Image,
+};
+
+mod common;
+
+use common::{assert_close, gpu};
+
+const SIZE: u32 = 17;
+
+/// Fills the whole target with `color` at HDR `intensity`, using a single splat larger than it.
+fn render_flat(color: Vec4, intensity: f32, post_process: PostProcess, gpu: &Gpu) -> Image {
+    // centered away from the point, which the default color snippet divides by the distance to
+    let transformations = [Transformation {
+        center: vec2(5.0, 5.0),
+        scale: 1.0,
+        angle: 0.0,
+        color,
+    }];
+    let mut dance = Dance::new(
+        &[Point { pos: Vec2::ZERO }],
+        &transformations,
+        OffscreenTarget::FORMAT,
+        gpu,
+    );
+    dance.set_render_options(
+        RenderOptions {
+            blend_mode: BlendMode::Additive,
+            splat: Splat::Disc {
+                radius: SIZE as f32 * 2.0,
+            },
+            intensity,
+            hdr: true,
+            post_process,
+        },
+        gpu,
+    );
+    let mut target = OffscreenTarget::new(SIZE, SIZE, gpu);
+    dance.render(target.texture(), gpu).unwrap();
+    target.read(gpu).unwrap()
+}
+
+fn center(image: &Image) -> Vec3 {
+    image.get(SIZE / 2, SIZE / 2).truncate()
+}
+
+#[test]
+fn exposure_and_gamma() {
+    let gpu = gpu();
+    let gray = vec4(0.25, 0.25, 0.25, 1.0);
+    let exposed = PostProcess {
+        exposure: 1.0,
+        ..Default::default()
+    };
+    assert_close(
+        center(&render_flat(gray, 1.0, exposed, &gpu)),
+        Vec3::splat(0.5),
+    );
+
+    let gamma = PostProcess {
+        gamma: 2.0,
+        ..Default::default()
+    };
+    assert_close(
+        center(&render_flat(gray, 1.0, gamma, &gpu)),
+        Vec3::splat(0.5),
+    );
+}
+
+#[test]
+fn tone_mapping_compresses_highlights() {
+    let gpu = gpu();
+    let white = Vec4::ONE;
+    let reinhard = PostProcess {
+        tone_mapping: ToneMapping::Reinhard,
+        ..Default::default()
+    };
+    assert_close(
+        center(&render_flat(white, 1.0, reinhard, &gpu)),
+        Vec3::splat(0.5),
+    );
+    assert_close(
+        center(&render_flat(white, 3.0, reinhard, &gpu)),
+        Vec3::splat(0.75),
+    );
+
+    for tone_mapping in [ToneMapping::Aces, ToneMapping::AgX] {
+        let post_process = PostProcess {
+            tone_mapping,
+            ..Default::default()
+        };
+        // brighter inputs stay distinguishable instead of clipping at 1
+        let levels = [0.25, 1.0, 4.0]
+            .map(|intensity| center(&render_flat(white, intensity, post_process, &gpu)).x);
+        assert!(
+            levels[0] < levels[1] && levels[1] < levels[2] && levels[2] < 1.0,
+            "{tone_mapping:?}: {levels:?}"
+        );
+    }
+}
+
+#[test]
+fn agx_desaturates_bright_colors() {
+    let gpu = gpu();
+    let red = vec4(1.0, 0.0, 0.0, 1.0);
+    let agx = PostProcess {
+        tone_mapping: ToneMapping::AgX,
+        ..Default::default()
+    };
+    let dim = center(&render_flat(red, 0.2, agx, &gpu));
+    let bright = center(&render_flat(red, 50.0, agx, &gpu));
+    assert!(bright.x > dim.x);
+    assert!(bright.y > dim.y + 0.1, "{dim} {bright}");
+}
+
+#[test]
+fn saturation_and_vibrance() {
+    let gpu = gpu();
+    let color = vec4(0.8, 0.4, 0.2, 1.0);
+    let grayscale = PostProcess {
+        saturation: 0.0,
+        ..Default::default()
+    };
+    let gray = center(&render_flat(color, 1.0, grayscale, &gpu));
+    let luminance = Vec3::new(0.2126, 0.7152, 0.0722).dot(color.truncate());
+    assert_close(gray, Vec3::splat(luminance));
+
+    // vibrance spreads the channels of a dull color further than those of a saturated one
+    let vibrant = PostProcess {
+        vibrance: 0.5,
+        ..Default::default()
+    };
+    let spread = |c: Vec3| c.max_element() - c.min_element();
+    let dull = vec4(0.5, 0.4, 0.4, 1.0);
+    let saturated = vec4(0.5, 0.0, 0.0, 1.0);
+    let dull_gain = spread(center(&render_flat(dull, 1.0, vibrant, &gpu))) / 0.1;
+    let saturated_gain = spread(center(&render_flat(saturated, 1.0, vibrant, &gpu))) / 0.5;
+    assert!(dull_gain > saturated_gain, "{dull_gain} {saturated_gain}");
+}
+
+#[test]
+fn vignette_darkens_the_corners() {
+    let gpu = gpu();
+    let vignette = PostProcess {
+        vignette: 1.0,
+        ..Default::default()
+    };
+    let image = render_flat(Vec4::ONE, 1.0, vignette, &gpu);
+    assert_close(center(&image), Vec3::ONE);
+    let corner = image.get(0, 0).truncate();
+    assert!(corner.x < 0.2, "{corner}");
+    assert_close(image.get(0, SIZE - 1).truncate(), corner);
+    // half a pixel from the top edge
+    let d = 0.5 - 0.5 / SIZE as f32;
+    assert_close(
+        image.get(SIZE / 2, 0).truncate(),
+        Vec3::splat(1.0 - 2.0 * d * d),
+    );
+}
+
+/// Renders a single white pixel at HDR `intensity` in the center of a `size` by `size` target.
+fn render_dot(size: u32, intensity: f32, post_process: PostProcess, gpu: &Gpu) -> Image {
+    let transformations = [Transformation {
+        center: vec2(5.0, 5.0),
+        scale: 1.0,
+        angle: 0.0,
+        color: Vec4::ONE,
+    }];
+    // the center of the pixel right of and below the middle of the target
+    let pos = Vec2::splat(1.0 / size as f32) * vec2(1.0, -1.0);
+    let mut dance = Dance::new(
+        &[Point { pos }],
+        &transformations,
+        OffscreenTarget::FORMAT,
+        gpu,
+    );
+    dance.set_render_options(
+        RenderOptions {
+            intensity,
+            post_process,
+            ..Default::default()
+        },
+        gpu,
+    );
+    let mut target = OffscreenTarget::new(size, size, gpu);
+    dance.render(target.texture(), gpu).unwrap();
+    target.read(gpu).unwrap()
+}
+
+fn with_bloom(bloom: Bloom) -> PostProcess {
+    PostProcess {
+        bloom: Some(bloom),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn bloom_spreads_bright_pixels() {
+    let gpu = gpu();
+    let size = 64;
+    let plain = render_dot(size, 50.0, PostProcess::default(), &gpu);
+    let bloomed = render_dot(size, 50.0, with_bloom(Bloom::default()), &gpu);
+    let (x, y) = (size / 2, size / 2);
+    assert_eq!(plain.get(x, y), Vec4::ONE);
+    assert_eq!(bloomed.get(x, y), Vec4::ONE);
+    for distance in [2, 4, 8] {
+        assert_eq!(plain.get(x + distance, y).x, 0.0);
+        let glow = bloomed.get(x + distance, y);
+        assert!(glow.x > 0.0, "nothing at {distance} pixels");
+        // the glow is as symmetric as the pixel grid allows
+        assert!((glow.x - bloomed.get(x - distance, y).x).abs() < 0.1);
+    }
+    assert!(bloomed.get(x + 2, y).x > bloomed.get(x + 8, y).x);
+}
+
+#[test]
+fn bloom_radius_sets_its_reach() {
+    let gpu = gpu();
+    let size = 128;
+    let glow_at = |radius, distance| {
+        let bloom = Bloom {
+            radius,
+            strength: 1.0,
+            ..Default::default()
+        };
+        render_dot(size, 500.0, with_bloom(bloom), &gpu).get(size / 2 + distance, size / 2)
+    };
+    let far = 24;
+    assert!(glow_at(4.0, far).x < glow_at(64.0, far).x);
+}
+
+#[test]
+fn bloom_ignores_pixels_below_its_threshold() {
+    let gpu = gpu();
+    let plain = render_flat(vec4(0.4, 0.3, 0.2, 1.0), 1.0, PostProcess::default(), &gpu);
+    let bloom = Bloom {
+        strength: 1.0,
+        threshold: 1.0,
+        knee: 0.1,
+        ..Default::default()
+    };
+    let bloomed = render_flat(vec4(0.4, 0.3, 0.2, 1.0), 1.0, with_bloom(bloom), &gpu);
+    assert_eq!(plain, bloomed);
+}
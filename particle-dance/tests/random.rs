@@ -0,0 +1,258 @@
+//! Statistical checks of `Rng`. Every test uses a fixed seed, so the thresholds, set at a
+//! significance level of about 0.1%, cannot make them flaky.
+
+use std::{collections::HashSet, f32, iter};
+
+use glam::{Vec2, Vec3, Vec4};
+use itertools::Itertools;
+use particle_dance::random::Rng;
+
+const N_SAMPLES: usize = 1_000_000;
+const SEED: u32 = 0x5eed;
+
+/// Pearson's chi-squared statistic of bucket counts against a uniform distribution.
+fn chi_squared(counts: &[usize]) -> f64 {
+    let n_samples = counts.iter().sum::<usize>() as f64;
+    let expected = n_samples / counts.len() as f64;
+    counts
+        .iter()
+        .map(|&count| (count as f64 - expected).powi(2) / expected)
+        .sum()
+}
+
+/// Critical value of the chi-squared distribution at the 0.1% level, from the Wilson-Hilferty
+/// approximation.
+fn chi_squared_critical(degrees_of_freedom: usize) -> f64 {
+    const Z: f64 = 3.09;
+    let k = degrees_of_freedom as f64;
+    let h = 2.0 / (9.0 * k);
+    k * (1.0 - h + Z * h.sqrt()).powi(3)
+}
+
+fn assert_uniform_buckets(name: &str, counts: &[usize]) {
+    let statistic = chi_squared(counts);
+    let critical = chi_squared_critical(counts.len() - 1);
+    assert!(
+        statistic < critical,
+        "{name}: chi-squared statistic {statistic} exceeds {critical}"
+    );
+}
+
+fn mean_variance(samples: impl Iterator<Item = f32>) -> (f64, f64) {
+    let samples = samples.map(f64::from).collect_vec();
+    let n_samples = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n_samples;
+    let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n_samples;
+    (mean, variance)
+}
+
+#[test]
+fn f32_is_uniform_in_unit_interval() {
+    let mut rng = Rng::with_seed(SEED);
+    let samples = iter::repeat_with(|| rng.random::<f32>())
+        .take(N_SAMPLES)
+        .collect_vec();
+
+    assert!(samples.iter().all(|x| (0.0..1.0).contains(x)));
+
+    let (mean, variance) = mean_variance(samples.iter().copied());
+    assert!((mean - 0.5).abs() < 0.001, "mean = {mean}");
+    assert!(
+        (variance - 1.0 / 12.0).abs() < 0.0005,
+        "variance = {variance}"
+    );
+
+    let mut counts = [0; 256];
+    for x in samples {
+        counts[(x * 256.0) as usize] += 1;
+    }
+    assert_uniform_buckets("f32", &counts);
+}
+
+#[test]
+fn f32_has_full_precision() {
+    let mut rng = Rng::with_seed(SEED);
+    let n_samples = 100_000;
+    let distinct = iter::repeat_with(|| rng.random::<f32>().to_bits())
+        .take(n_samples)
+        .collect::<HashSet<_>>();
+    // with 2^24 equally likely values, about 300 collisions are expected
+    assert!(
+        distinct.len() > n_samples - 500,
+        "{} distinct",
+        distinct.len()
+    );
+
+    // the low bits of the mantissa are used too
+    let mut rng = Rng::with_seed(SEED);
+    let mut counts = [0; 16];
+    for _ in 0..N_SAMPLES {
+        counts[(rng.random::<f32>() * (1 << 24) as f32) as usize % 16] += 1;
+    }
+    assert_uniform_buckets("f32 low bits", &counts);
+}
+
+#[test]
+fn u32_bits_are_uniform() {
+    let mut rng = Rng::with_seed(SEED);
+    let mut bit_counts = [0; 32];
+    for _ in 0..N_SAMPLES {
+        let x = rng.random_u32();
+        for (bit, count) in bit_counts.iter_mut().enumerate() {
+            *count += (x >> bit & 1) as usize;
+        }
+    }
+    for (bit, count) in bit_counts.into_iter().enumerate() {
+        assert_uniform_buckets(&format!("bit {bit}"), &[count, N_SAMPLES - count]);
+    }
+}
+
+#[test]
+fn consecutive_seeds_are_independent() {
+    let mut counts = [0; 256];
+    for seed in 0..N_SAMPLES as u32 {
+        counts[(Rng::with_seed(seed).random_u32() >> 24) as usize] += 1;
+    }
+    assert_uniform_buckets("first output of consecutive seeds", &counts);
+}
+
+#[test]
+fn hashed_streams_are_uncorrelated() {
+    let rng = Rng::with_seed(SEED);
+    let n_streams = 100_000;
+    let samples = (0..n_streams + 1)
+        .map(|i| rng.clone().hash(i).random::<f32>() as f64 - 0.5)
+        .collect_vec();
+    let correlation = samples
+        .iter()
+        .tuple_windows()
+        .map(|(a, b)| a * b)
+        .sum::<f64>()
+        / n_streams as f64
+        * 12.0;
+    assert!(correlation.abs() < 0.01, "correlation = {correlation}");
+
+    let mut counts = [0; 64];
+    for (i, j) in (0..N_SAMPLES as u32 / 64).cartesian_product(0..64) {
+        let x = rng.clone().hash(i).hash(j).random::<f32>();
+        counts[(x * 64.0) as usize] += 1;
+    }
+    assert_uniform_buckets("hashed streams", &counts);
+}
+
+#[test]
+fn ranges_are_uniform() {
+    let mut rng = Rng::with_seed(SEED);
+
+    let mut counts = [0; 10];
+    for _ in 0..N_SAMPLES {
+        counts[(rng.random_range(5..15) - 5) as usize] += 1;
+    }
+    assert_uniform_buckets("u32 range", &counts);
+
+    let mut counts = [0; 7];
+    for _ in 0..N_SAMPLES {
+        counts[(rng.random_range(3..=9) - 3) as usize] += 1;
+    }
+    assert_uniform_buckets("u32 inclusive range", &counts);
+
+    let mut counts = [0; 100];
+    for _ in 0..N_SAMPLES {
+        let x = rng.random_range(-2.0..3.0);
+        assert!((-2.0..3.0).contains(&x));
+        counts[((x + 2.0) * 20.0) as usize] += 1;
+    }
+    assert_uniform_buckets("f32 range", &counts);
+}
+
+#[test]
+fn gaussian_has_standard_moments() {
+    let mut rng = Rng::with_seed(SEED);
+    let samples = iter::repeat_with(|| rng.random_gaussian())
+        .take(N_SAMPLES)
+        .collect_vec();
+
+    assert!(samples.iter().all(|x| x.is_finite()));
+
+    let (mean, variance) = mean_variance(samples.iter().copied());
+    assert!(mean.abs() < 0.004, "mean = {mean}");
+    assert!((variance - 1.0).abs() < 0.005, "variance = {variance}");
+
+    let within_one_sigma =
+        samples.iter().filter(|x| x.abs() < 1.0).count() as f64 / N_SAMPLES as f64;
+    assert!(
+        (within_one_sigma - 0.682_689).abs() < 0.002,
+        "{within_one_sigma} within one standard deviation"
+    );
+}
+
+#[test]
+fn unit_disk_is_uniform() {
+    let mut rng = Rng::with_seed(SEED);
+    let mut radius_counts = [0; 16];
+    let mut angle_counts = [0; 16];
+    for _ in 0..N_SAMPLES {
+        let point = rng.random_unit_disk();
+        let radius_sq = point.length_squared();
+        assert!(radius_sq <= 1.0);
+        // area-uniform, so the squared radius is uniform
+        radius_counts[((radius_sq * 16.0) as usize).min(15)] += 1;
+        angle_counts[angle_bucket(point, 16)] += 1;
+    }
+    assert_uniform_buckets("disk radius", &radius_counts);
+    assert_uniform_buckets("disk angle", &angle_counts);
+}
+
+#[test]
+fn unit_circle_is_uniform() {
+    let mut rng = Rng::with_seed(SEED);
+    let mut counts = [0; 64];
+    for _ in 0..N_SAMPLES {
+        let point = rng.random_unit_circle();
+        assert!((point.length() - 1.0).abs() < 1e-6);
+        counts[angle_bucket(point, 64)] += 1;
+    }
+    assert_uniform_buckets("circle angle", &counts);
+}
+
+fn angle_bucket(point: Vec2, n_buckets: usize) -> usize {
+    let turns = point.to_angle() / f32::consts::TAU + 0.5;
+    ((turns * n_buckets as f32) as usize).min(n_buckets - 1)
+}
+
+#[test]
+fn bool_is_fair() {
+    let mut rng = Rng::with_seed(SEED);
+    let n_true = iter::repeat_with(|| rng.random::<bool>())
+        .take(N_SAMPLES)
+        .filter(|&b| b)
+        .count();
+    assert_uniform_buckets("bool", &[n_true, N_SAMPLES - n_true]);
+}
+
+#[test]
+fn composite_components_are_independent() {
+    let mut rng = Rng::with_seed(SEED);
+    let mut counts = [0; 64];
+    for _ in 0..N_SAMPLES / 4 {
+        let v = rng.random::<Vec3>();
+        let bucket = |x: f32| (x * 4.0) as usize;
+        counts[bucket(v.x) * 16 + bucket(v.y) * 4 + bucket(v.z)] += 1;
+    }
+    assert_uniform_buckets("Vec3 joint distribution", &counts);
+
+    let mut counts = [0; 16];
+    for _ in 0..N_SAMPLES / 4 {
+        let v = rng.random::<Vec4>();
+        let bucket = |x: f32| (x * 2.0) as usize;
+        counts[bucket(v.x) * 8 + bucket(v.y) * 4 + bucket(v.z) * 2 + bucket(v.w)] += 1;
+    }
+    assert_uniform_buckets("Vec4 joint distribution", &counts);
+
+    let mut counts = [0; 27];
+    for _ in 0..N_SAMPLES / 4 {
+        let [a, b, c] = rng.random::<[f32; 3]>().map(|x| (x * 3.0) as usize);
+        counts[a * 9 + b * 3 + c] += 1;
+    }
+    assert_uniform_buckets("array joint distribution", &counts);
+}
@@ -0,0 +1,435 @@
+//! Renders fixed scenes on a headless fallback adapter and compares them against golden images
+//! in `tests/golden`. Run with `UPDATE_GOLDEN=1` to rewrite the golden images after an intended
+//! change to the shaders.
+
+use std::{
+    env, f32,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+};
+
+use glam::{Vec2, Vec4, vec2, vec4};
+use itertools::Itertools;
+use particle_dance::{
+    app::Gpu,
+    dance::{
+        Dance, Point, Transformation,
+        cpu::CpuSimulator,
+        offscreen::OffscreenTarget,
+        render::{BlendMode, RenderOptions, Splat},
+        seeding::PointDistribution,
+        transformations::TransformationGenerator,
+    },
+    image::Image,
+    random::Rng,
+};
+
+mod common;
+
+use common::{gpu, sierpinski};
+
+const SIZE: u32 = 96;
+const N_POINTS: usize = 100_000;
+const N_FRAMES: usize = 30;
+const SEED: u32 = 0x5eed;
+
+/// Mean and max distance between the block averages of two images.
+const GOLDEN_TOLERANCE: (f32, f32) = (0.005, 0.08);
+const CPU_TOLERANCE: (f32, f32) = (0.002, 0.1);
+
+fn initial_points(n_points: usize) -> Vec<Point> {
+    PointDistribution::Square.sample(n_points, &mut Rng::with_seed(SEED))
+}
+
+fn spiral() -> Vec<Transformation> {
+    vec![
+        Transformation {
+            center: vec2(0.1, 0.0),
+            scale: 0.85,
+            angle: 0.4,
+            color: vec4(0.9, 0.6, 0.4, 1.0),
+        },
+        Transformation {
+            center: vec2(0.6, 0.4),
+            scale: 0.3,
+            angle: -1.2,
+            color: vec4(0.4, 0.6, 0.9, 1.0),
+        },
+    ]
+}
+
+fn generated() -> Vec<Transformation> {
+    let colors = vec![
+        vec4(0.9, 0.9, 0.6, 1.0),
+        vec4(0.6, 0.9, 0.9, 1.0),
+        vec4(0.9, 0.6, 0.9, 1.0),
+        vec4(0.9, 0.6, 0.4, 1.0),
+        vec4(0.4, 0.6, 0.9, 1.0),
+    ];
+    TransformationGenerator::with_seed(colors, SEED).generate(1.5)
+}
+
+fn run_gpu(points: &[Point], transformations: &[Transformation], gpu: &Gpu) -> (Image, u32) {
+    let mut dance = Dance::new(points, transformations, OffscreenTarget::FORMAT, gpu);
+    let mut target = OffscreenTarget::new(SIZE, SIZE, gpu);
+    for _ in 0..N_FRAMES {
+        dance.step(gpu);
+    }
+    dance.render(target.texture(), gpu).unwrap();
+    let image = target.read(gpu).unwrap();
+    let n_respawns = dance.take_respawn_count(gpu).unwrap();
+    (image, n_respawns)
+}
+
+fn run_cpu(points: &[Point], transformations: &[Transformation]) -> Image {
+    let mut simulator = CpuSimulator::new(points.to_vec(), transformations);
+    for _ in 0..N_FRAMES {
+        simulator.step();
+    }
+    simulator.render(SIZE, SIZE)
+}
+
+/// Compares images the way a viewer would, on 4x4 block averages rather than single pixels, so
+/// that a point landing one pixel over does not count as a difference.
+fn perceptual_distance(a: &Image, b: &Image) -> (f32, f32) {
+    assert_eq!((a.width(), a.height()), (b.width(), b.height()));
+    const BLOCK: u32 = 4;
+
+    let block_average = |image: &Image, bx: u32, by: u32| {
+        (0..BLOCK)
+            .cartesian_product(0..BLOCK)
+            .map(|(dx, dy)| image.get(bx * BLOCK + dx, by * BLOCK + dy))
+            .sum::<Vec4>()
+            / (BLOCK * BLOCK) as f32
+    };
+
+    let distances = (0..a.width() / BLOCK)
+        .cartesian_product(0..a.height() / BLOCK)
+        .map(|(bx, by)| {
+            let difference = block_average(a, bx, by) - block_average(b, bx, by);
+            difference.truncate().abs().max_element()
+        })
+        .collect_vec();
+    let mean = distances.iter().sum::<f32>() / distances.len() as f32;
+    let max = distances.iter().copied().fold(0.0, f32::max);
+    (mean, max)
+}
+
+fn assert_close(name: &str, actual: &Image, expected: &Image, tolerance: (f32, f32)) {
+    let (mean, max) = perceptual_distance(actual, expected);
+    assert!(
+        mean <= tolerance.0 && max <= tolerance.1,
+        "{name}: distance (mean = {mean}, max = {max}) exceeds tolerance {tolerance:?}"
+    );
+}
+
+fn check_golden(name: &str, image: &Image) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.ppm"));
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        image
+            .write_ppm(BufWriter::new(File::create(&path).unwrap()))
+            .unwrap();
+        return;
+    }
+
+    let golden = Image::read_ppm(BufReader::new(File::open(&path).unwrap_or_else(|error| {
+        panic!("failed to open golden image {path:?}, run with UPDATE_GOLDEN=1: {error}")
+    })))
+    .unwrap();
+
+    // round-trip through the golden format so that quantization is not counted as a difference
+    let mut quantized = vec![];
+    image.write_ppm(&mut quantized).unwrap();
+    let image = Image::read_ppm(quantized.as_slice()).unwrap();
+
+    assert_close(name, &image, &golden, GOLDEN_TOLERANCE);
+}
+
+#[test]
+fn sierpinski_matches_golden() {
+    let gpu = gpu();
+    let (image, n_respawns) = run_gpu(&initial_points(N_POINTS), &sierpinski(), &gpu);
+    assert_eq!(n_respawns, 0);
+    check_golden("sierpinski", &image);
+}
+
+#[test]
+fn spiral_matches_golden() {
+    let gpu = gpu();
+    let (image, n_respawns) = run_gpu(&initial_points(N_POINTS), &spiral(), &gpu);
+    assert_eq!(n_respawns, 0);
+    check_golden("spiral", &image);
+}
+
+#[test]
+fn generated_matches_golden() {
+    let gpu = gpu();
+    let (image, n_respawns) = run_gpu(&initial_points(N_POINTS), &generated(), &gpu);
+    assert_eq!(n_respawns, 0);
+    check_golden("generated", &image);
+}
+
+#[test]
+fn gpu_matches_cpu() {
+    let gpu = gpu();
+    for (name, transformations) in [
+        ("sierpinski", sierpinski()),
+        ("spiral", spiral()),
+        ("generated", generated()),
+    ] {
+        let points = initial_points(N_POINTS);
+        let (gpu_image, _) = run_gpu(&points, &transformations, &gpu);
+        let cpu_image = run_cpu(&points, &transformations);
+        assert_close(name, &gpu_image, &cpu_image, CPU_TOLERANCE);
+    }
+}
+
+#[test]
+fn gpu_points_match_cpu() {
+    let gpu = gpu();
+    // both pick the same transformation for every point, so only rounding differs, and the maps
+    // are contractive so it does not accumulate
+    let points = initial_points(N_POINTS);
+    let mut dance = Dance::new(&points, &sierpinski(), OffscreenTarget::FORMAT, &gpu);
+    let mut simulator = CpuSimulator::new(points, &sierpinski());
+    for _ in 0..N_FRAMES {
+        dance.step(&gpu);
+        simulator.step();
+    }
+
+    let gpu_points = dance.read_points(&gpu).unwrap();
+    assert_eq!(gpu_points.len(), N_POINTS);
+    for (gpu_point, cpu_point) in gpu_points.iter().zip(simulator.points()) {
+        assert!(
+            gpu_point.pos.distance(cpu_point.pos) < 1e-5,
+            "GPU point {:?} differs from CPU point {:?}",
+            gpu_point.pos,
+            cpu_point.pos
+        );
+    }
+}
+
+#[test]
+fn expansive_points_are_respawned() {
+    let gpu = gpu();
+    let transformations = vec![Transformation {
+        center: Vec2::ZERO,
+        scale: 3.0,
+        angle: 0.0,
+        color: Vec4::ONE,
+    }];
+    let (image, n_respawns) = run_gpu(&initial_points(N_POINTS), &transformations, &gpu);
+    assert!(n_respawns > 0);
+    // respawned points land back in view instead of vanishing
+    assert!(
+        image
+            .pixels()
+            .iter()
+            .any(|pixel| pixel.truncate() != Vec4::ZERO.truncate())
+    );
+}
+
+#[test]
+fn every_point_is_simulated() {
+    let gpu = gpu();
+    // a point count that does not fill the last workgroup, with every point escaping every frame
+    let transformations = vec![Transformation {
+        center: Vec2::ZERO,
+        scale: 1e30,
+        angle: 0.0,
+        color: Vec4::ONE,
+    }];
+    let mut dance = Dance::new(
+        &initial_points(1000),
+        &transformations,
+        OffscreenTarget::FORMAT,
+        &gpu,
+    );
+    dance.step(&gpu);
+    dance.step(&gpu);
+    assert_eq!(dance.take_respawn_count(&gpu).unwrap(), 2000);
+
+    // a count read later covers the steps before it, and those after go to the next one
+    dance.step(&gpu);
+    let mut readback = dance.take_respawn_count_later(&gpu);
+    dance.step(&gpu);
+    let count = loop {
+        if let Some(count) = readback.try_take(&gpu).unwrap() {
+            break count;
+        }
+    };
+    assert_eq!(count, [1000]);
+    assert_eq!(dance.take_respawn_count(&gpu).unwrap(), 1000);
+}
+
+#[test]
+fn respawns_match_cpu_across_dispatches() {
+    let gpu = gpu();
+    // more points than one dispatch simulates, all of them escaping every frame, so every
+    // position comes from the respawn hash of the point's index in the whole buffer
+    const POINTS_PER_DISPATCH: usize = 64 * 65535;
+    let transformations = vec![Transformation {
+        center: Vec2::ZERO,
+        scale: 1e30,
+        angle: 0.0,
+        color: Vec4::ONE,
+    }];
+    let points = initial_points(POINTS_PER_DISPATCH + 1000);
+    let mut dance = Dance::new(&points, &transformations, OffscreenTarget::FORMAT, &gpu);
+    let mut simulator = CpuSimulator::new(points, &transformations);
+    for _ in 0..2 {
+        dance.step(&gpu);
+        simulator.step();
+    }
+
+    assert_eq!(
+        dance.take_respawn_count(&gpu).unwrap(),
+        simulator.take_respawn_count()
+    );
+    let gpu_points = dance.read_points(&gpu).unwrap();
+    assert_eq!(gpu_points.len(), simulator.points().len());
+    assert!(
+        gpu_points
+            .iter()
+            .zip(simulator.points())
+            .all(|(gpu_point, cpu_point)| gpu_point.pos == cpu_point.pos)
+    );
+    // the second dispatch does not repeat the first
+    assert_ne!(gpu_points[0].pos, gpu_points[POINTS_PER_DISPATCH].pos);
+}
+
+/// Renders `n_points` points stacked on the center of pixel (8, 8) of a 17x17 target, with an
+/// identity transformation of the given color.
+fn render_stacked(n_points: usize, color: Vec4, options: RenderOptions, gpu: &Gpu) -> Image {
+    let size = 17;
+    let center = Point {
+        pos: Vec2::splat((8.5 / size as f32) * 2.0 - 1.0) * vec2(1.0, -1.0),
+    };
+    // centered away from the points, which the default color snippet divides by the distance to
+    let transformations = [Transformation {
+        center: Vec2::ONE,
+        scale: 1.0,
+        angle: 0.0,
+        color,
+    }];
+    let mut dance = Dance::new(
+        &vec![center; n_points],
+        &transformations,
+        OffscreenTarget::FORMAT,
+        gpu,
+    );
+    dance.set_render_options(options, gpu);
+    let mut target = OffscreenTarget::new(size, size, gpu);
+    dance.render(target.texture(), gpu).unwrap();
+    target.read(gpu).unwrap()
+}
+
+#[test]
+fn blend_modes_combine_overlapping_points() {
+    let gpu = gpu();
+    let color = vec4(0.0, 1.0, 0.5, 1.0);
+    let step = 1.0 / 255.0;
+    for (blend_mode, hdr, expected, tolerance) in [
+        (BlendMode::Replace, false, 0.1, step),
+        (BlendMode::Max, false, 0.1, step),
+        // every point is rounded to the 8-bit target before the next one is added
+        (BlendMode::Additive, false, 0.5, 5.0 * step),
+        (BlendMode::Additive, true, 0.5, step),
+        // each point covers a tenth of what is below it
+        (BlendMode::Alpha, true, 1.0 - 0.9f32.powi(5), step),
+    ] {
+        let options = RenderOptions {
+            blend_mode,
+            intensity: 0.1,
+            hdr,
+            ..Default::default()
+        };
+        let pixel = render_stacked(5, color, options, &gpu).get(8, 8);
+        assert!(
+            (pixel.y - expected).abs() <= tolerance,
+            "{blend_mode:?}, hdr = {hdr}: {pixel}"
+        );
+        assert!((pixel.z - expected * 0.5).abs() <= tolerance);
+    }
+}
+
+#[test]
+fn hdr_target_is_clamped_when_resolved() {
+    let gpu = gpu();
+    // 20 points of 0.1 add up past 1, while 3 add up without rounding in between
+    let color = vec4(1.0, 1.0, 1.0, 1.0);
+    let options = RenderOptions {
+        blend_mode: BlendMode::Additive,
+        intensity: 0.1,
+        hdr: true,
+        ..Default::default()
+    };
+    let pixel = render_stacked(20, color, options, &gpu).get(8, 8);
+    assert_eq!(pixel, Vec4::ONE);
+    let pixel = render_stacked(3, color, options, &gpu).get(8, 8);
+    assert!((pixel.x - 0.3).abs() <= 1.0 / 255.0, "{pixel}");
+}
+
+#[test]
+fn splats_cover_their_radius() {
+    let gpu = gpu();
+    let color = Vec4::ONE;
+    let radius = 6.0;
+    for splat in [Splat::Disc { radius }, Splat::Gaussian { radius }] {
+        let options = RenderOptions {
+            splat,
+            hdr: true,
+            ..Default::default()
+        };
+        let image = render_stacked(1, color, options, &gpu);
+        let n_covered = image.pixels().iter().filter(|pixel| pixel.x > 0.0).count() as f32;
+        let area = f32::consts::PI * radius * radius;
+        assert!(
+            (n_covered - area).abs() < area * 0.15,
+            "{splat:?}: {n_covered}"
+        );
+        assert!(image.get(8, 8).x > 0.95);
+        assert_eq!(image.get(8, 15), vec4(0.0, 0.0, 0.0, 1.0));
+
+        // a disc is flat, a Gaussian falls off towards its edge
+        let profile = (8..14).map(|x| image.get(x, 8).x).collect_vec();
+        match splat {
+            Splat::Disc { .. } => assert!(profile.iter().all(|&x| x == 1.0), "{profile:?}"),
+            _ => assert!(
+                profile.windows(2).all(|pair| pair[1] < pair[0]),
+                "{profile:?}"
+            ),
+        }
+    }
+}
+
+#[test]
+fn hdr_target_matches_direct_rendering() {
+    let gpu = gpu();
+    let points = initial_points(N_POINTS);
+    // the default post-processing leaves the image as it is
+    let (hdr, _) = run_gpu(&points, &generated(), &gpu);
+
+    let mut dance = Dance::new(&points, &generated(), OffscreenTarget::FORMAT, &gpu);
+    dance.set_render_options(
+        RenderOptions {
+            hdr: false,
+            ..Default::default()
+        },
+        &gpu,
+    );
+    let mut target = OffscreenTarget::new(SIZE, SIZE, &gpu);
+    for _ in 0..N_FRAMES {
+        dance.step(&gpu);
+    }
+    dance.render(target.texture(), &gpu).unwrap();
+    // float16 rounding may move a unorm value by one step at most
+    let direct = target.read(&gpu).unwrap();
+    for (a, b) in direct.pixels().iter().zip(hdr.pixels()) {
+        assert!(a.abs_diff_eq(*b, 1.5 / 255.0), "{a} != {b}");
+    }
+}
@@ -0,0 +1,230 @@
+//! Checks the seed search, its scores and the scene files it writes.
+
+use std::{env, fs, process};
+
+use clap::Parser;
+use glam::{Vec2, Vec4, vec4};
+use particle_dance::{
+    cli::{Cli, Command},
+    dance::{
+        analysis::AttractorStatistics,
+        background::Background,
+        bloom::Bloom,
+        post::PostProcess,
+        render::{BlendMode, RenderOptions},
+        scene::Scene,
+        search::{Score, SeedSearch},
+        snippets::Snippets,
+        transformations::TransformationGenerator,
+    },
+    image::Image,
+};
+
+mod common;
+
+use common::gpu;
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = env::temp_dir().join(format!("particle-dance-{name}-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn statistics() -> AttractorStatistics {
+    AttractorStatistics {
+        box_counting_dimension: 1.6,
+        correlation_dimension: 1.5,
+        lyapunov_exponent: -0.5,
+        coverage: 0.25,
+        entropy: 0.5,
+    }
+}
+
+fn small_search() -> SeedSearch {
+    SeedSearch {
+        top: 3,
+        n_points: 5_000,
+        steps: 20,
+        thumbnail_size: 32,
+        ..SeedSearch::new(10..16)
+    }
+}
+
+#[test]
+fn scene_round_trips_through_json() {
+    let dir = temp_dir("scene");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("scene.json");
+    let scene = Scene {
+        seed: 42,
+        colors: TransformationGenerator::default_colors(3),
+        time: 1.5,
+        period: Some(4),
+        n_points: 1000,
+        snippets: Snippets {
+            color: Some(
+                "fn color(point: vec2f, transformation_idx: u32) -> vec4f { return vec4f(1.0); }"
+                    .into(),
+            ),
+            variation: None,
+        },
+        render_options: RenderOptions {
+            blend_mode: BlendMode::Additive,
+            intensity: 2.0,
+            post_process: PostProcess {
+                bloom: Some(Bloom::default()),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        background: Background::Radial {
+            center: Vec2::ZERO,
+            radius: 1.5,
+            center_color: vec4(0.1, 0.0, 0.2, 1.0),
+            edge_color: Vec4::W,
+        },
+    };
+    scene.write(&path).unwrap();
+    assert_eq!(Scene::read(&path).unwrap(), scene);
+    assert_eq!(scene.transformations().len(), 3);
+    let generated = TransformationGenerator::with_seed(scene.colors.clone(), 42)
+        .looping(4)
+        .generate(1.5);
+    for (a, b) in scene.transformations().iter().zip(&generated) {
+        assert_eq!((a.center, a.scale, a.angle), (b.center, b.scale, b.angle));
+    }
+
+    // scenes written before the snippets, render options and background read with the defaults
+    fs::write(
+        &path,
+        r#"{"seed": 1, "colors": [[1, 1, 1, 1]], "n_points": 10}"#,
+    )
+    .unwrap();
+    let old = Scene::read(&path).unwrap();
+    assert_eq!(old.snippets, Snippets::default());
+    assert_eq!(old.render_options, RenderOptions::default());
+    assert_eq!(old.background, Background::default());
+
+    fs::write(&path, r#"{"seed": 1, "colors": [], "n_points": 10}"#).unwrap();
+    assert!(Scene::read(&path).is_err());
+    fs::write(
+        &path,
+        r#"{"seed": 1, "colors": [[1, 1, 1, 1]], "period": 0, "n_points": 10}"#,
+    )
+    .unwrap();
+    assert!(Scene::read(&path).is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn black_and_escaping_attractors_score_zero() {
+    let black = Image::new(16, 16, Vec4::W);
+    assert_eq!(Score::new(&black, &statistics()).total, 0.0);
+
+    let mut image = Image::new(16, 16, Vec4::W);
+    for x in 0..16 {
+        image.set(x, x, vec4(1.0, 0.2, 0.1, 1.0));
+    }
+    let escaping = AttractorStatistics {
+        lyapunov_exponent: 0.1,
+        ..statistics()
+    };
+    assert_eq!(Score::new(&image, &escaping).total, 0.0);
+    assert!(Score::new(&image, &statistics()).total > 0.0);
+}
+
+#[test]
+fn score_prefers_partial_coverage_over_a_filled_frame() {
+    let filled = Image::new(16, 16, Vec4::ONE);
+    let mut quarter = Image::new(16, 16, Vec4::W);
+    for y in 0..8 {
+        for x in 0..8 {
+            quarter.set(x, y, Vec4::ONE);
+        }
+    }
+    let filled = Score::new(&filled, &statistics());
+    let quarter = Score::new(&quarter, &statistics());
+    assert_eq!(filled.coverage, 0.0);
+    assert_eq!(quarter.coverage, 1.0);
+    assert_eq!(filled.edge_density, 0.0);
+    assert!(quarter.edge_density > 0.0);
+    assert!(quarter.total > filled.total);
+}
+
+#[test]
+fn search_on_the_cpu_keeps_the_best_first() {
+    let search = small_search();
+    let mut n_scored = 0;
+    let best = search.run(None, |_| n_scored += 1).unwrap();
+    assert_eq!(n_scored, 6);
+    assert_eq!(best.len(), 3);
+    assert!(best.is_sorted_by(|a, b| a.score.total >= b.score.total));
+    for candidate in &best {
+        assert!(search.seeds.contains(&candidate.scene.seed));
+        assert_eq!(candidate.thumbnail.width(), 32);
+        assert_eq!(candidate.scene.n_points, Scene::DEFAULT_N_POINTS);
+    }
+}
+
+#[test]
+fn search_on_the_gpu_scores_like_the_cpu() {
+    let gpu = gpu();
+    let search = small_search();
+    let on_gpu = search.run(Some(&gpu), |_| {}).unwrap();
+    assert_eq!(on_gpu.len(), 3);
+    assert!(on_gpu.is_sorted_by(|a, b| a.score.total >= b.score.total));
+    assert!(on_gpu.iter().all(|candidate| candidate.score.total >= 0.0));
+    // the simulations agree, so the attractors are measured alike
+    let on_cpu = search.run(None, |_| {}).unwrap();
+    for candidate in &on_gpu {
+        let cpu = on_cpu
+            .iter()
+            .find(|other| other.scene.seed == candidate.scene.seed);
+        if let Some(cpu) = cpu {
+            assert_eq!(cpu.statistics, candidate.statistics);
+        }
+    }
+}
+
+#[test]
+fn cli_search_writes_scenes_and_thumbnails() {
+    let dir = temp_dir("search");
+    let out = dir.to_str().unwrap();
+    let cli = Cli::try_parse_from([
+        "particle-dance",
+        "search",
+        "--cpu",
+        "--first-seed",
+        "3",
+        "--n-seeds",
+        "4",
+        "--top",
+        "2",
+        "--points",
+        "5000",
+        "--thumbnail-size",
+        "16",
+        "--out",
+        out,
+    ])
+    .unwrap();
+    let Some(Command::Search(args)) = cli.command else {
+        panic!("{cli:?}");
+    };
+    let best = args.search().unwrap();
+    assert_eq!(best.len(), 2);
+    for candidate in &best {
+        let seed = candidate.scene.seed;
+        let scene = Scene::read(&dir.join(format!("seed-{seed}.json"))).unwrap();
+        assert_eq!(scene, candidate.scene);
+        assert!(dir.join(format!("seed-{seed}.ppm")).is_file());
+    }
+    fs::remove_dir_all(dir).unwrap();
+
+    let with_scene = Cli::try_parse_from(["particle-dance", "--scene", "seed-3.json"]).unwrap();
+    assert!(with_scene.command.is_none());
+    assert_eq!(
+        with_scene.window.scene.unwrap().to_str(),
+        Some("seed-3.json")
+    );
+}
@@ -0,0 +1,58 @@
+//! Checks the point distributions the dance can be reseeded from.
+
+use glam::{Vec4, vec2, vec4};
+use particle_dance::{
+    dance::{
+        Point,
+        seeding::{ImageMask, PointDistribution},
+    },
+    image::Image,
+    random::Rng,
+};
+
+#[test]
+fn density_mask_reseeds_where_the_points_are() {
+    // a cluster in the top left quadrant, and a point outside the square that is ignored
+    let points = [
+        vec2(-0.6, 0.6),
+        vec2(-0.8, 0.9),
+        vec2(-0.7, 0.3),
+        vec2(3.0, 0.0),
+    ]
+    .map(|pos| Point { pos });
+    let mask = ImageMask::from_points(&points, 4, 4);
+    assert_eq!((mask.width(), mask.height()), (4, 4));
+
+    let reseeded = PointDistribution::Mask(mask).sample(10_000, &mut Rng::with_seed(1));
+    assert_eq!(reseeded.len(), 10_000);
+    assert!(
+        reseeded
+            .iter()
+            .all(|point| (-1.0..=-0.5).contains(&point.pos.x) && (0.0..=1.0).contains(&point.pos.y))
+    );
+    // two of the three points share a cell
+    let in_shared_cell = reseeded.iter().filter(|point| point.pos.y >= 0.5).count();
+    assert!((6_000..7_300).contains(&in_shared_cell), "{in_shared_cell}");
+}
+
+#[test]
+fn image_mask_reseeds_by_luminance() {
+    // a white top row, a gray bottom left pixel and a transparent one that weighs nothing
+    let image = Image::from_pixels(
+        2,
+        2,
+        vec![Vec4::ONE, Vec4::ONE, vec4(0.5, 0.5, 0.5, 1.0), Vec4::ZERO],
+    );
+    let mask = ImageMask::from_image(&image);
+    assert_eq!((mask.width(), mask.height()), (2, 2));
+
+    let reseeded = PointDistribution::Mask(mask).sample(10_000, &mut Rng::with_seed(2));
+    assert!(
+        reseeded
+            .iter()
+            .all(|point| point.pos.x <= 0.0 || point.pos.y >= 0.0)
+    );
+    // the top row is twice as bright as the gray pixel, and twice as large
+    let in_top_row = reseeded.iter().filter(|point| point.pos.y >= 0.0).count();
+    assert!((7_700..8_300).contains(&in_top_row), "{in_top_row}");
+}
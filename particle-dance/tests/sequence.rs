@@ -0,0 +1,354 @@
+//! Checks how sequences pick cues and crossfade between them, and the files they are read from.
+
+use std::{env, f32::consts::PI, fs, process};
+
+use clap::Parser;
+use glam::{Vec2, Vec4, vec2, vec4};
+use particle_dance::{
+    cli::Cli,
+    dance::{
+        Point, Transformation,
+        bloom::Bloom,
+        cpu::CpuSimulator,
+        post::PostProcess,
+        render::{BlendMode, Camera, RenderOptions},
+        seeding::PointDistribution,
+        sequence::{self, Cue, CueTransformations, Curve, Sequence, Transition},
+    },
+    random::Rng,
+};
+
+mod common;
+
+use common::{assert_near, sierpinski};
+
+fn fixed(scale: f32, n: usize) -> CueTransformations {
+    CueTransformations::Fixed(
+        (0..n)
+            .map(|i| Transformation {
+                center: vec2(i as f32, 0.0),
+                scale,
+                angle: 0.0,
+                color: Vec4::ONE,
+            })
+            .collect(),
+    )
+}
+
+fn cue(transformations: CueTransformations, duration: f32, transition: f32) -> Cue {
+    Cue {
+        transformations,
+        camera: None,
+        render_options: None,
+        duration,
+        transition: Transition {
+            duration: transition,
+            curve: Curve::Linear,
+        },
+    }
+}
+
+/// Two cues of fixed maps, the second fading in over its first two seconds.
+fn two_cues(looping: bool) -> Sequence {
+    Sequence {
+        cues: vec![cue(fixed(0.2, 2), 4.0, 1.0), cue(fixed(0.6, 3), 6.0, 2.0)],
+        looping,
+    }
+}
+
+#[test]
+fn curves_run_from_0_to_1() {
+    for curve in [
+        Curve::Linear,
+        Curve::EaseIn,
+        Curve::EaseOut,
+        Curve::EaseInOut,
+    ] {
+        assert_eq!(curve.apply(0.0), 0.0);
+        assert_eq!(curve.apply(1.0), 1.0);
+        assert_eq!(curve.apply(2.0), 1.0);
+        let samples = (0..=10)
+            .map(|i| curve.apply(i as f32 / 10.0))
+            .collect::<Vec<_>>();
+        assert!(samples.is_sorted(), "{curve:?}");
+    }
+    assert!(Curve::EaseIn.apply(0.5) < 0.5);
+    assert!(Curve::EaseOut.apply(0.5) > 0.5);
+    assert_eq!(Curve::EaseInOut.apply(0.5), 0.5);
+}
+
+#[test]
+fn morph_pads_with_identity_maps_of_their_own_color() {
+    let from = [Transformation {
+        center: Vec2::ZERO,
+        scale: 0.5,
+        angle: 0.1,
+        color: Vec4::ONE,
+    }];
+    let to = [
+        Transformation {
+            center: Vec2::X,
+            scale: 0.3,
+            angle: 2.0 * PI - 0.1,
+            color: Vec4::W,
+        },
+        Transformation {
+            center: Vec2::Y,
+            scale: 0.4,
+            angle: 1.0,
+            color: vec4(1.0, 0.5, 0.0, 1.0),
+        },
+    ];
+
+    let start = sequence::morph(&from, &to, 0.0);
+    assert_eq!(start.len(), 2);
+    assert_eq!(start[0], from[0]);
+    assert_eq!(start[1].center, Vec2::Y);
+    assert_eq!(start[1].scale, 1.0);
+    assert_eq!(start[1].angle, 0.0);
+    assert_eq!(start[1].color, to[1].color);
+
+    let end = sequence::morph(&from, &to, 1.0);
+    assert_near(end[0].scale, 0.3, 1e-4);
+    assert_near(end[0].angle, -0.1, 1e-4);
+    assert_eq!(end[1].scale, 0.4);
+    assert_near(end[1].angle, 1.0, 1e-4);
+    assert_eq!(end[1].color, to[1].color);
+
+    let halfway = sequence::morph(&from, &to, 0.5);
+    assert_near(halfway[0].scale, 0.4, 1e-4);
+    assert_eq!(halfway[0].center, vec2(0.5, 0.0));
+    // from 0.1 to -0.1 the short way, through 0
+    assert_near(halfway[0].angle, 0.0, 1e-4);
+    assert_near(halfway[1].scale, 0.7, 1e-4);
+    assert_eq!(halfway[1].color, to[1].color);
+
+    let shrinking = sequence::morph(&to, &from, 1.0);
+    assert_eq!(shrinking.len(), 2);
+    assert_eq!(shrinking[1].scale, 1.0);
+    assert_eq!(shrinking[1].color, to[1].color);
+}
+
+/// The share of `points` in each cell of a 4 by 4 grid over the square from -1 to 1.
+fn mass(points: &[Point]) -> [f32; 16] {
+    let mut cells = [0.0; 16];
+    for point in points {
+        let cell = ((point.pos + 1.0) * 2.0)
+            .floor()
+            .clamp(Vec2::ZERO, Vec2::splat(3.0));
+        cells[cell.y as usize * 4 + cell.x as usize] += 1.0 / points.len() as f32;
+    }
+    cells
+}
+
+fn settled_mass(transformations: &[Transformation]) -> [f32; 16] {
+    let points = PointDistribution::Square.sample(20_000, &mut Rng::with_seed(0x5eed));
+    let mut simulator = CpuSimulator::new(points, transformations);
+    for _ in 0..60 {
+        simulator.step();
+    }
+    mass(simulator.points())
+}
+
+#[test]
+fn morph_keeps_the_mass_of_the_attractor_at_either_end() {
+    let from = sierpinski();
+    let mut to = sierpinski();
+    to.push(Transformation {
+        center: vec2(0.0, -0.2),
+        scale: 0.3,
+        angle: 1.0,
+        color: Vec4::ONE,
+    });
+
+    let expected = settled_mass(&from);
+    for morphed in [
+        sequence::morph(&from, &to, 0.0),
+        sequence::morph(&to, &from, 1.0),
+    ] {
+        assert_eq!(morphed.len(), 4);
+        let actual = settled_mass(&morphed);
+        for (actual, expected) in actual.into_iter().zip(expected) {
+            assert_near(actual, expected, 0.01);
+        }
+    }
+}
+
+#[test]
+fn sequence_holds_cues_and_crossfades_between_them() {
+    let sequence = two_cues(false);
+    assert_eq!(sequence.duration(), 10.0);
+    assert_eq!(sequence.cue_at(-1.0), (0, 0.0));
+    assert_eq!(sequence.cue_at(5.0), (1, 1.0));
+    assert_eq!(sequence.cue_at(12.0), (1, 8.0));
+
+    // the first cue has nothing to transition from
+    let state = sequence.at(0.5);
+    assert_eq!(state.transformations.len(), 2);
+    assert_eq!(state.transformations[0].scale, 0.2);
+
+    let state = sequence.at(5.0);
+    assert_eq!(state.transformations.len(), 3);
+    assert_near(state.transformations[0].scale, 0.4, 1e-4);
+    assert_near(state.transformations[2].scale, 0.8, 1e-4);
+
+    let state = sequence.at(7.0);
+    assert_eq!(state.transformations.len(), 3);
+    assert!(
+        state
+            .transformations
+            .iter()
+            .all(|transformation| transformation.scale == 0.6)
+    );
+
+    // held after the end
+    assert_eq!(sequence.at(100.0), sequence.at(9.0));
+}
+
+#[test]
+fn looping_sequence_transitions_from_the_last_cue_into_the_first() {
+    let sequence = two_cues(true);
+    assert_eq!(sequence.cue_at(10.5), (0, 0.5));
+    assert_eq!(sequence.cue_at(-0.5), (1, 5.5));
+    let state = sequence.at(10.5);
+    // halfway from three maps of 0.6 to two of 0.2
+    assert_eq!(state.transformations.len(), 3);
+    assert_near(state.transformations[0].scale, 0.4, 1e-4);
+    assert_near(state.transformations[2].scale, 0.8, 1e-4);
+    assert_eq!(state, sequence.at(0.5));
+}
+
+#[test]
+fn cues_inherit_and_crossfade_camera_and_render_options() {
+    let mut sequence = two_cues(false);
+    sequence.cues[0].camera = Some(Camera {
+        center: Vec2::ZERO,
+        zoom: 1.0,
+    });
+    sequence.cues[0].render_options = Some(RenderOptions {
+        blend_mode: BlendMode::Replace,
+        intensity: 1.0,
+        ..Default::default()
+    });
+    sequence.cues.push(cue(fixed(0.5, 1), 2.0, 2.0));
+    sequence.cues[1].camera = Some(Camera {
+        center: Vec2::X,
+        zoom: 4.0,
+    });
+    sequence.cues[1].render_options = Some(RenderOptions {
+        blend_mode: BlendMode::Additive,
+        intensity: 3.0,
+        post_process: PostProcess {
+            bloom: Some(Bloom {
+                strength: 1.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    let early = sequence.at(4.5);
+    assert_near(early.camera.zoom, 2.0_f32.powf(0.5), 1e-4);
+    assert_eq!(early.render_options.blend_mode, BlendMode::Replace);
+    assert_near(early.render_options.intensity, 1.5, 1e-4);
+    assert_near(
+        early.render_options.post_process.bloom.unwrap().strength,
+        0.25,
+        1e-4,
+    );
+
+    let late = sequence.at(5.5);
+    assert_eq!(late.camera.center, vec2(0.75, 0.0));
+    assert_near(late.camera.zoom, 2.0_f32.powf(1.5), 1e-4);
+    assert_eq!(late.render_options.blend_mode, BlendMode::Additive);
+
+    // the third cue keeps the camera and options of the second
+    let third = sequence.at(11.0);
+    assert_eq!(third.camera.zoom, 4.0);
+    assert_eq!(third.render_options.intensity, 3.0);
+    assert!(third.render_options.post_process.bloom.is_some());
+}
+
+#[test]
+fn sequences_are_read_from_json() {
+    let dir = env::temp_dir().join(format!("particle-dance-sequence-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("show.json");
+    fs::write(
+        &path,
+        r#"{
+            "looping": true,
+            "cues": [
+                {
+                    "transformations": {"generated": {"seed": 7, "colors": [[1, 1, 1, 1], [1, 0, 0, 1]]}},
+                    "duration": 20
+                },
+                {
+                    "transformations": {"fixed": [
+                        {"center": [0, 0], "scale": 0.5, "angle": 0, "color": [1, 1, 1, 1]}
+                    ]},
+                    "camera": {"zoom": 2},
+                    "render_options": {"blend_mode": "additive", "splat": {"gaussian": {"radius": 2}}},
+                    "duration": 10,
+                    "transition": {"duration": 5, "curve": "ease_in_out"}
+                }
+            ]
+        }"#,
+    )
+    .unwrap();
+    let sequence = Sequence::read(&path).unwrap();
+    assert!(sequence.looping);
+    assert_eq!(sequence.colors().len(), 2);
+    let cue = &sequence.cues[1];
+    assert_eq!(cue.transition.curve, Curve::EaseInOut);
+    assert_eq!(cue.camera.unwrap().center, Vec2::ZERO);
+    assert_eq!(cue.render_options.unwrap().intensity, 1.0);
+    let CueTransformations::Generated { speed, .. } = sequence.cues[0].transformations else {
+        panic!("{:?}", sequence.cues[0]);
+    };
+    assert_eq!(speed, 0.1);
+    // generated cues animate
+    assert_ne!(sequence.at(1.0), sequence.at(10.0));
+
+    let written = dir.join("written.json");
+    sequence.write(&written).unwrap();
+    let reread = Sequence::read(&written).unwrap();
+    for time in [0.0, 12.5, 22.0, 29.0] {
+        assert_eq!(reread.at(time), sequence.at(time));
+    }
+
+    for invalid in [
+        r#"{"cues": []}"#,
+        r#"{"cues": [{"transformations": {"fixed": []}, "duration": 1}]}"#,
+        r#"{"cues": [{"transformations": {"generated": {"seed": 1, "colors": [[1, 1, 1, 1]]}}, "duration": 0}]}"#,
+        r#"{"cues": [{"transformations": {"generated": {"seed": 1, "colors": [[1, 1, 1, 1]]}}, "duration": 1, "camera": {"zoom": 0}}]}"#,
+        r#"{"cues": [{"transformations": {"generated": {"seed": 1, "colors": [[1, 1, 1, 1]]}}, "duration": 1, "transition": {"duration": 2}}]}"#,
+    ] {
+        fs::write(&path, invalid).unwrap();
+        assert!(Sequence::read(&path).is_err(), "{invalid}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cli_plays_sequences_in_seconds() {
+    let cli = Cli::try_parse_from(["particle-dance", "--sequence", "show.json"]).unwrap();
+    let clock = cli.window.clock(None).unwrap();
+    assert_eq!(clock.speed(), Sequence::SPEED);
+
+    let cli =
+        Cli::try_parse_from(["particle-dance", "--sequence", "show.json", "--speed", "2"]).unwrap();
+    assert_eq!(cli.window.clock(None).unwrap().speed(), 2.0);
+
+    assert!(
+        Cli::try_parse_from([
+            "particle-dance",
+            "--sequence",
+            "show.json",
+            "--scene",
+            "scene.json"
+        ])
+        .is_err()
+    );
+}
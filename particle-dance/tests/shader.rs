@@ -0,0 +1,377 @@
+//! Checks shader composition and that the WGSL struct definitions generated from the Rust types
+//! agree with them.
+
+use std::{
+    env, fs,
+    path::Path,
+    process,
+    time::{Duration, SystemTime},
+};
+
+use glam::{Vec2, Vec4, vec4};
+use particle_dance::{
+    app::Gpu,
+    dance::{self, Dance, Point, Transformation, offscreen::OffscreenTarget, snippets::Snippets},
+    data::{WgslField, WgslStruct},
+    shader::{Composer, ShaderDir, check_layouts},
+};
+
+mod common;
+
+use common::gpu;
+
+#[test]
+fn generated_structs_match_rust_layouts() {
+    let composer = dance::shader_composer();
+    assert!(!composer.structs().is_empty());
+    check_layouts(composer.structs()).unwrap();
+}
+
+#[test]
+fn offset_mismatch_is_detected() {
+    // laid out like a `#[repr(C)]` struct with glam's 4-byte aligned `Vec2`, which WGSL aligns to
+    // 8 bytes instead
+    let mismatched = WgslStruct {
+        name: "Mismatched".to_owned(),
+        fields: vec![
+            WgslField {
+                name: "a",
+                ty: "f32".to_owned(),
+                offset: 0,
+                size: 4,
+            },
+            WgslField {
+                name: "b",
+                ty: "vec2f".to_owned(),
+                offset: 4,
+                size: 8,
+            },
+        ],
+        size: 12,
+    };
+    let error = check_layouts(&[mismatched]).unwrap_err().to_string();
+    assert!(error.contains("`Mismatched.b` is at offset 4"), "{error}");
+}
+
+#[test]
+fn size_mismatch_is_detected() {
+    // a `vec3f` is only 12 bytes, but WGSL pads the struct to its 16-byte alignment
+    let mismatched = WgslStruct {
+        name: "Mismatched".to_owned(),
+        fields: vec![WgslField {
+            name: "a",
+            ty: "vec3f".to_owned(),
+            offset: 0,
+            size: 12,
+        }],
+        size: 12,
+    };
+    let error = check_layouts(&[mismatched]).unwrap_err().to_string();
+    assert!(error.contains("12 bytes in Rust, but 16 bytes"), "{error}");
+}
+
+#[test]
+fn padding_fields_become_size_attributes() {
+    let padded = WgslStruct {
+        name: "Padded".to_owned(),
+        fields: vec![
+            WgslField {
+                name: "a",
+                ty: "u32".to_owned(),
+                offset: 0,
+                size: 4,
+            },
+            WgslField {
+                name: "_padding",
+                ty: "array<u32, 3>".to_owned(),
+                offset: 4,
+                size: 12,
+            },
+        ],
+        size: 16,
+    };
+    assert_eq!(
+        padded.definition(),
+        "struct Padded {\n    @size(16) a: u32,\n}\n"
+    );
+    check_layouts(&[padded]).unwrap();
+}
+
+#[test]
+fn built_in_shaders_compose() {
+    let composer = dance::shader_composer();
+    for (name, source) in [
+        ("sim.wgsl", include_str!("../src/dance/sim.wgsl")),
+        ("render.wgsl", include_str!("../src/dance/render.wgsl")),
+        ("post.wgsl", include_str!("../src/dance/post.wgsl")),
+        ("bloom.wgsl", include_str!("../src/dance/bloom.wgsl")),
+        ("trails.wgsl", include_str!("../src/dance/trails.wgsl")),
+        ("still.wgsl", include_str!("../src/dance/still.wgsl")),
+        (
+            "histogram.wgsl",
+            include_str!("../src/dance/histogram.wgsl"),
+        ),
+        (
+            "background.wgsl",
+            include_str!("../src/dance/background.wgsl"),
+        ),
+    ] {
+        if let Err(error) = composer.compose_validated(name, source) {
+            panic!("{error}");
+        }
+    }
+}
+
+#[test]
+fn modules_are_imported_once() {
+    let composer = Composer::default()
+        .with_module("a", "const A = 1;")
+        .with_module("b", "#import a\nconst B = A + 1;");
+    let composed = composer
+        .compose("main", "#import a\n#import b\n  #import a\nconst C = B;")
+        .unwrap();
+    assert_eq!(composed, "const A = 1;\nconst B = A + 1;\nconst C = B;\n");
+}
+
+#[test]
+fn bad_imports_are_reported() {
+    let composer = Composer::default()
+        .with_module("a", "#import b")
+        .with_module("b", "#import a");
+
+    let error = composer.compose("main", "\n#import c").unwrap_err();
+    assert_eq!(error.to_string(), "main:2: unknown module `c`");
+
+    let error = composer.compose("main", "#import a").unwrap_err();
+    assert_eq!(error.to_string(), "b:1: circular import of `a`");
+}
+
+const RED: Vec4 = vec4(1.0, 0.0, 0.0, 1.0);
+const GREEN: Vec4 = vec4(0.0, 1.0, 0.0, 1.0);
+const BLUE: Vec4 = vec4(0.0, 0.0, 1.0, 1.0);
+
+/// A dance with points on a circle and one identity transformation per color.
+fn ring_dance(colors: &[Vec4], gpu: &Gpu) -> Dance {
+    let points = (0..1000)
+        .map(|i| Point {
+            pos: Vec2::from_angle(i as f32) * 0.5,
+        })
+        .collect::<Vec<_>>();
+    let transformations = colors
+        .iter()
+        .map(|&color| Transformation {
+            center: Vec2::ZERO,
+            scale: 1.0,
+            angle: 0.0,
+            color,
+        })
+        .collect::<Vec<_>>();
+    Dance::new(&points, &transformations, OffscreenTarget::FORMAT, gpu)
+}
+
+/// Writes `contents` to `path`, with a modification time that differs from the previous one even
+/// on file systems with coarse timestamps.
+fn write_modified(path: &Path, contents: &str, modified: SystemTime) {
+    fs::write(path, contents).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+}
+
+#[test]
+fn shaders_reload_from_disk() {
+    let gpu = gpu();
+
+    let dir = env::temp_dir().join(format!("particle-dance-shaders-{}", process::id()));
+    fs::create_dir_all(dir.join("dance")).unwrap();
+    for path in Dance::SHADER_PATHS {
+        fs::copy(Path::new(ShaderDir::SOURCE_DIR).join(path), dir.join(path)).unwrap();
+    }
+    let color_path = dir.join("dance/color.wgsl");
+    let original = fs::read_to_string(&color_path).unwrap();
+
+    let mut dance = ring_dance(&[GREEN], &gpu);
+    dance
+        .set_shader_dir(Some(ShaderDir::new(&dir)), &gpu)
+        .unwrap();
+    assert!(!dance.poll_shaders_modified());
+
+    let mut target = OffscreenTarget::new(32, 32, &gpu);
+    let mut render = |dance: &mut Dance| {
+        dance.render(target.texture(), &gpu).unwrap();
+        target.read(&gpu).unwrap()
+    };
+    assert!(!render(&mut dance).pixels().contains(&RED));
+
+    let start = SystemTime::now();
+    write_modified(
+        &color_path,
+        &original.replace(
+            "return color / totalLength;",
+            "return vec4f(1.0, 0.0, 0.0, 1.0);",
+        ),
+        start + Duration::from_secs(1),
+    );
+    assert!(dance.poll_shaders_modified());
+    dance.reload_shaders(&gpu).unwrap();
+    assert!(render(&mut dance).pixels().contains(&RED));
+
+    // a broken shader is reported and the previous pipeline stays in use
+    write_modified(
+        &color_path,
+        &original.replace("fn color(", "fn color(,"),
+        start + Duration::from_secs(2),
+    );
+    assert!(dance.poll_shaders_modified());
+    let error = dance.reload_shaders(&gpu).unwrap_err();
+    assert!(format!("{error:#}").contains("render.wgsl"), "{error:#}");
+    let image = render(&mut dance);
+    assert!(image.pixels().contains(&RED));
+    assert!(!image.pixels().contains(&GREEN));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_reload_keeps_every_pipeline() {
+    let gpu = gpu();
+
+    let dir = env::temp_dir().join(format!("particle-dance-atomic-shaders-{}", process::id()));
+    fs::create_dir_all(dir.join("dance")).unwrap();
+    for path in Dance::SHADER_PATHS {
+        fs::copy(Path::new(ShaderDir::SOURCE_DIR).join(path), dir.join(path)).unwrap();
+    }
+    let mut dance = ring_dance(&[GREEN], &gpu);
+    dance
+        .set_shader_dir(Some(ShaderDir::new(&dir)), &gpu)
+        .unwrap();
+
+    // the simulation compiles, but the histogram, built after it, binds what its layout lacks
+    fs::write(
+        dir.join("dance/variation.wgsl"),
+        "fn variation(p: vec2f) -> vec2f { return p * 0.5 + 0.25; }",
+    )
+    .unwrap();
+    let histogram_path = dir.join("dance/histogram.wgsl");
+    let histogram = fs::read_to_string(&histogram_path).unwrap();
+    fs::write(
+        &histogram_path,
+        histogram.replace("@binding(3)", "@binding(7)"),
+    )
+    .unwrap();
+    assert!(dance.reload_shaders(&gpu).is_err());
+
+    let before = dance.read_points(&gpu).unwrap();
+    dance.step(&gpu);
+    for (before, after) in before.into_iter().zip(dance.read_points(&gpu).unwrap()) {
+        assert!(after.pos.abs_diff_eq(before.pos, 1e-6));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn color_snippet_sees_the_selected_transformation() {
+    let gpu = gpu();
+
+    let mut dance = ring_dance(&[RED, GREEN, BLUE], &gpu);
+    dance.step(&gpu);
+    dance
+        .set_snippets(
+            Snippets {
+                color: Some(
+                    "fn color(point: vec2f, transformation_idx: u32) -> vec4f {\n    \
+                     return transformations[transformation_idx].transformation.color;\n}"
+                        .to_owned(),
+                ),
+                variation: None,
+            },
+            &gpu,
+        )
+        .unwrap();
+
+    let mut target = OffscreenTarget::new(64, 64, &gpu);
+    dance.render(target.texture(), &gpu).unwrap();
+    let image = target.read(&gpu).unwrap();
+    // each point is drawn in the pure color of one of the transformations, never a blend
+    for color in [RED, GREEN, BLUE] {
+        assert!(image.pixels().contains(&color));
+    }
+    assert!(
+        image
+            .pixels()
+            .iter()
+            .all(|pixel| [Vec4::new(0.0, 0.0, 0.0, 1.0), RED, GREEN, BLUE].contains(pixel))
+    );
+}
+
+#[test]
+fn variation_snippet_moves_the_points() {
+    let gpu = gpu();
+
+    let mut dance = ring_dance(&[GREEN], &gpu);
+    dance
+        .set_snippets(
+            Snippets {
+                color: None,
+                variation: Some(
+                    "fn variation(p: vec2f) -> vec2f { return p * 0.5 + 0.25; }".to_owned(),
+                ),
+            },
+            &gpu,
+        )
+        .unwrap();
+    let before = dance.read_points(&gpu).unwrap();
+    dance.step(&gpu);
+    for (before, after) in before.into_iter().zip(dance.read_points(&gpu).unwrap()) {
+        assert!(after.pos.abs_diff_eq(before.pos * 0.5 + 0.25, 1e-6));
+    }
+}
+
+#[test]
+fn broken_snippet_keeps_the_previous_pipelines() {
+    let gpu = gpu();
+
+    let mut dance = ring_dance(&[GREEN], &gpu);
+    let red = Snippets {
+        color: Some(
+            "fn color(point: vec2f, idx: u32) -> vec4f { return vec4f(1.0, 0.0, 0.0, 1.0); }"
+                .to_owned(),
+        ),
+        variation: None,
+    };
+    dance.set_snippets(red.clone(), &gpu).unwrap();
+
+    let error = dance
+        .set_snippets(
+            Snippets {
+                color: Some("fn color(point: vec2f) -> vec4f { return point; }".to_owned()),
+                variation: Some("fn variation(p: vec2f) -> vec2f { return p }".to_owned()),
+            },
+            &gpu,
+        )
+        .unwrap_err();
+    assert!(format!("{error:#}").contains("sim.wgsl"), "{error:#}");
+
+    let error = dance
+        .set_snippets(
+            Snippets {
+                color: Some("fn color(point: vec2f) -> vec4f { return point; }".to_owned()),
+                variation: None,
+            },
+            &gpu,
+        )
+        .unwrap_err();
+    assert!(format!("{error:#}").contains("render.wgsl"), "{error:#}");
+
+    let mut target = OffscreenTarget::new(32, 32, &gpu);
+    dance.render(target.texture(), &gpu).unwrap();
+    assert!(target.read(&gpu).unwrap().pixels().contains(&RED));
+    // the snippets that still compile are kept for later reloads
+    dance.reload_shaders(&gpu).unwrap();
+    dance.render(target.texture(), &gpu).unwrap();
+    assert!(target.read(&gpu).unwrap().pixels().contains(&RED));
+}
@@ -0,0 +1,271 @@
+//! Checks supersampled and tiled stills on a headless fallback adapter.
+
+use glam::{Vec2, Vec4, vec2, vec4};
+use particle_dance::{
+    app::Gpu,
+    dance::{
+        Dance, Point, Transformation,
+        offscreen::OffscreenTarget,
+        post::PostProcess,
+        render::{BlendMode, RenderOptions, Splat},
+        still::{Filter, Still},
+    },
+    image::Image,
+};
+
+mod common;
+
+use common::{STEP, gpu};
+
+/// A dance of scattered white points.
+fn scattered_dance(gpu: &Gpu) -> Dance {
+    let points = (0..2000)
+        .map(|i| Point {
+            pos: Vec2::from_angle(i as f32 * 2.4) * (i as f32 / 2000.0).sqrt(),
+        })
+        .collect::<Vec<_>>();
+    // centered away from the points, which the default color snippet divides by the distance to
+    let transformations = [Transformation {
+        center: vec2(5.0, 5.0),
+        scale: 1.0,
+        angle: 0.0,
+        color: Vec4::ONE,
+    }];
+    Dance::new(&points, &transformations, OffscreenTarget::FORMAT, gpu)
+}
+
+fn max_difference(a: &Image, b: &Image) -> f32 {
+    assert_eq!((a.width(), a.height()), (b.width(), b.height()));
+    a.pixels()
+        .iter()
+        .zip(b.pixels())
+        .map(|(a, b)| (*a - *b).abs().max_element())
+        .fold(0.0, f32::max)
+}
+
+#[test]
+fn tiles_match_a_single_tile() {
+    let gpu = gpu();
+    let mut dance = scattered_dance(&gpu);
+    dance.set_render_options(
+        RenderOptions {
+            post_process: PostProcess {
+                vignette: 1.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        &gpu,
+    );
+
+    for filter in [Filter::Box, Filter::Gaussian, Filter::Mitchell] {
+        let still = Still {
+            supersampling: 3,
+            filter,
+            ..Still::new(40, 24)
+        };
+        let whole = dance.render_still(still, &gpu).unwrap();
+        let tiled = dance
+            .render_still(
+                Still {
+                    tile_size: 7,
+                    ..still
+                },
+                &gpu,
+            )
+            .unwrap();
+        let difference = max_difference(&whole, &tiled);
+        assert!(difference <= STEP, "{filter:?}: {difference}");
+    }
+}
+
+#[test]
+fn box_filter_averages_the_samples() {
+    let gpu = gpu();
+    let transformations = [Transformation {
+        center: vec2(5.0, 5.0),
+        scale: 1.0,
+        angle: 0.0,
+        color: vec4(0.2, 0.4, 0.6, 1.0),
+    }];
+    // a single point in the pixel at the center of a 5x5 image, covering one of its 4 samples
+    let mut dance = Dance::new(
+        &[Point {
+            pos: vec2(-0.1, 0.1),
+        }],
+        &transformations,
+        OffscreenTarget::FORMAT,
+        &gpu,
+    );
+    let still = Still {
+        supersampling: 2,
+        filter: Filter::Box,
+        ..Still::new(5, 5)
+    };
+    let image = dance.render_still(still, &gpu).unwrap();
+    // the pixel grows into a square of 2x2 samples, which the filter spreads over the pixels it
+    // straddles without losing any of its light
+    let total = image.pixels().iter().copied().sum::<Vec4>().truncate();
+    assert!(
+        total.abs_diff_eq(vec4(0.2, 0.4, 0.6, 0.0).truncate(), 4.0 * STEP),
+        "{total}"
+    );
+}
+
+fn mean_brightness(image: &Image) -> f32 {
+    let total = image.pixels().iter().map(|pixel| pixel.x).sum::<f32>();
+    total / image.pixels().len() as f32
+}
+
+#[test]
+fn pixel_splats_keep_their_brightness_when_supersampled() {
+    let gpu = gpu();
+    let mut dance = scattered_dance(&gpu);
+    let still = Still {
+        supersampling: 1,
+        filter: Filter::Box,
+        ..Still::new(256, 256)
+    };
+    let aliased = mean_brightness(&dance.render_still(still, &gpu).unwrap());
+    assert!(aliased > 0.0);
+    for supersampling in [2, 3] {
+        let smooth = mean_brightness(
+            &dance
+                .render_still(
+                    Still {
+                        supersampling,
+                        ..still
+                    },
+                    &gpu,
+                )
+                .unwrap(),
+        );
+        assert!(
+            (smooth / aliased - 1.0).abs() < 0.05,
+            "{supersampling}x: {smooth} against {aliased}"
+        );
+    }
+}
+
+#[test]
+fn splats_keep_their_brightness() {
+    let gpu = gpu();
+    let transformations = [Transformation {
+        center: vec2(5.0, 5.0),
+        scale: 1.0,
+        angle: 0.0,
+        color: vec4(0.25, 0.25, 0.25, 1.0),
+    }];
+    let mut dance = Dance::new(
+        &[Point { pos: Vec2::ZERO }; 2],
+        &transformations,
+        OffscreenTarget::FORMAT,
+        &gpu,
+    );
+    dance.set_render_options(
+        RenderOptions {
+            blend_mode: BlendMode::Additive,
+            splat: Splat::Disc { radius: 100.0 },
+            ..Default::default()
+        },
+        &gpu,
+    );
+    let image = dance.render_still(Still::new(9, 9), &gpu).unwrap();
+    for pixel in image.pixels() {
+        assert!(
+            pixel
+                .truncate()
+                .abs_diff_eq(Vec4::splat(0.5).truncate(), 1.5 * STEP),
+            "{pixel}"
+        );
+    }
+}
+
+#[test]
+fn supersampling_smooths_edges() {
+    let gpu = gpu();
+    let transformations = [Transformation {
+        center: vec2(5.0, 5.0),
+        scale: 1.0,
+        angle: 0.0,
+        color: Vec4::ONE,
+    }];
+    let mut dance = Dance::new(
+        &[Point { pos: Vec2::ZERO }],
+        &transformations,
+        OffscreenTarget::FORMAT,
+        &gpu,
+    );
+    dance.set_render_options(
+        RenderOptions {
+            splat: Splat::Disc { radius: 10.0 },
+            ..Default::default()
+        },
+        &gpu,
+    );
+    let is_partial = |pixel: &Vec4| pixel.x > 0.1 && pixel.x < 0.9;
+
+    let aliased = Still {
+        supersampling: 1,
+        filter: Filter::Box,
+        ..Still::new(32, 32)
+    };
+    let image = dance.render_still(aliased, &gpu).unwrap();
+    assert!(!image.pixels().iter().any(is_partial));
+
+    let smooth = Still {
+        supersampling: 4,
+        ..aliased
+    };
+    let image = dance.render_still(smooth, &gpu).unwrap();
+    assert!(
+        image
+            .pixels()
+            .iter()
+            .filter(|pixel| is_partial(pixel))
+            .count()
+            > 16
+    );
+}
+
+#[test]
+fn ppm_is_written_a_row_of_tiles_at_a_time() {
+    let gpu = gpu();
+    let mut dance = scattered_dance(&gpu);
+    let still = Still {
+        supersampling: 2,
+        tile_size: 5,
+        ..Still::new(13, 11)
+    };
+
+    let mut streamed = vec![];
+    dance.write_still_ppm(still, &mut streamed, &gpu).unwrap();
+    let mut whole = vec![];
+    dance
+        .render_still(still, &gpu)
+        .unwrap()
+        .write_ppm(&mut whole)
+        .unwrap();
+    assert_eq!(streamed, whole);
+}
+
+#[test]
+fn stills_leave_the_window_render_alone() {
+    let gpu = gpu();
+    let mut dance = scattered_dance(&gpu);
+    let mut target = OffscreenTarget::new(16, 16, &gpu);
+    dance.render(target.texture(), &gpu).unwrap();
+    let before = target.read(&gpu).unwrap();
+
+    dance
+        .render_still(
+            Still {
+                tile_size: 4,
+                ..Still::new(20, 20)
+            },
+            &gpu,
+        )
+        .unwrap();
+    dance.render(target.texture(), &gpu).unwrap();
+    assert_eq!(target.read(&gpu).unwrap(), before);
+}
@@ -0,0 +1,232 @@
+//! Checks that trails fade, average and start over on a headless fallback adapter.
+
+use glam::{Vec2, Vec3, Vec4, vec2, vec3, vec4};
+use particle_dance::{
+    app::Gpu,
+    dance::{
+        Dance, Point, Transformation,
+        offscreen::OffscreenTarget,
+        post::PostProcess,
+        render::{BlendMode, Camera, RenderOptions, Splat},
+        trails::{TrailMode, Trails},
+    },
+    image::Image,
+};
+
+mod common;
+
+use common::{assert_close, gpu};
+
+const SIZE: u32 = 17;
+const GRAY: Vec4 = vec4(0.5, 0.5, 0.5, 1.0);
+
+/// A dance whose single point fills the whole target with `color` while it is at the origin.
+fn flat_dance(color: Vec4, trails: Trails, gpu: &Gpu) -> Dance {
+    // centered away from the point, which the default color snippet divides by the distance to
+    let transformations = [Transformation {
+        center: vec2(5.0, 5.0),
+        scale: 1.0,
+        angle: 0.0,
+        color,
+    }];
+    let mut dance = Dance::new(
+        &[Point { pos: Vec2::ZERO }],
+        &transformations,
+        OffscreenTarget::FORMAT,
+        gpu,
+    );
+    dance.set_render_options(
+        RenderOptions {
+            blend_mode: BlendMode::Additive,
+            splat: Splat::Disc {
+                radius: SIZE as f32 * 2.0,
+            },
+            post_process: PostProcess {
+                trails: Some(trails),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        gpu,
+    );
+    dance
+}
+
+/// Moves the point far out of view, so that frames come out black.
+fn hide_point(dance: &mut Dance, gpu: &Gpu) {
+    dance.write_points(
+        &[Point {
+            pos: vec2(100.0, 100.0),
+        }],
+        gpu,
+    );
+}
+
+fn render(dance: &mut Dance, target: &mut OffscreenTarget, gpu: &Gpu) -> Vec3 {
+    dance.render(target.texture(), gpu).unwrap();
+    let image: Image = target.read(gpu).unwrap();
+    image.get(image.width() / 2, image.height() / 2).truncate()
+}
+
+#[test]
+fn fade_trails_decay_every_frame() {
+    let gpu = gpu();
+    let trails = Trails {
+        mode: TrailMode::Fade,
+        decay: 0.5,
+    };
+    let mut dance = flat_dance(GRAY, trails, &gpu);
+    let mut target = OffscreenTarget::new(SIZE, SIZE, &gpu);
+
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.5));
+    hide_point(&mut dance, &gpu);
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.25));
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.125));
+}
+
+#[test]
+fn fade_trails_add_up_where_points_linger() {
+    let gpu = gpu();
+    let trails = Trails {
+        mode: TrailMode::Fade,
+        decay: 0.5,
+    };
+    let mut dance = flat_dance(vec4(0.25, 0.25, 0.25, 1.0), trails, &gpu);
+    let mut target = OffscreenTarget::new(SIZE, SIZE, &gpu);
+
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.25));
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.375));
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.4375));
+}
+
+#[test]
+fn average_trails_keep_the_brightness_of_a_frame() {
+    let gpu = gpu();
+    let trails = Trails {
+        mode: TrailMode::Average,
+        decay: 0.25,
+    };
+    let mut dance = flat_dance(GRAY, trails, &gpu);
+    let mut target = OffscreenTarget::new(SIZE, SIZE, &gpu);
+
+    for _ in 0..3 {
+        assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.5));
+    }
+    hide_point(&mut dance, &gpu);
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.375));
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.28125));
+}
+
+#[test]
+fn moving_the_camera_resets_the_trails() {
+    let gpu = gpu();
+    let trails = Trails {
+        mode: TrailMode::Fade,
+        decay: 0.5,
+    };
+    let mut dance = flat_dance(GRAY, trails, &gpu);
+    let mut target = OffscreenTarget::new(SIZE, SIZE, &gpu);
+
+    render(&mut dance, &mut target, &gpu);
+    hide_point(&mut dance, &gpu);
+    // setting the same camera is not a move
+    dance.set_camera(Camera::default());
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.25));
+    dance.set_camera(Camera {
+        center: vec2(0.5, 0.0),
+        zoom: 2.0,
+    });
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::ZERO);
+}
+
+#[test]
+fn gradual_camera_moves_keep_the_trails() {
+    let gpu = gpu();
+    let trails = Trails {
+        mode: TrailMode::Fade,
+        decay: 0.5,
+    };
+    let mut dance = flat_dance(GRAY, trails, &gpu);
+    let mut target = OffscreenTarget::new(SIZE, SIZE, &gpu);
+
+    render(&mut dance, &mut target, &gpu);
+    hide_point(&mut dance, &gpu);
+    let camera = Camera {
+        center: vec2(0.01, 0.0),
+        zoom: 1.1,
+    };
+    dance.move_camera(camera);
+    assert_eq!(dance.camera(), camera);
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.25));
+}
+
+#[test]
+fn resetting_clears_overflowed_trails() {
+    let gpu = gpu();
+    let trails = Trails {
+        mode: TrailMode::Fade,
+        decay: 0.0,
+    };
+    let mut dance = flat_dance(GRAY, trails, &gpu);
+    let options = dance.render_options();
+    // brighter than half floats hold
+    dance.set_render_options(
+        RenderOptions {
+            intensity: 1e6,
+            ..options
+        },
+        &gpu,
+    );
+    let mut target = OffscreenTarget::new(SIZE, SIZE, &gpu);
+
+    for _ in 0..3 {
+        render(&mut dance, &mut target, &gpu);
+    }
+    dance.set_render_options(options, &gpu);
+    dance.reset_trails();
+    assert_close(render(&mut dance, &mut target, &gpu), Vec3::splat(0.5));
+}
+
+#[test]
+fn resizing_resets_the_trails() {
+    let gpu = gpu();
+    let trails = Trails {
+        mode: TrailMode::Fade,
+        decay: 0.5,
+    };
+    let mut dance = flat_dance(GRAY, trails, &gpu);
+
+    render(
+        &mut dance,
+        &mut OffscreenTarget::new(SIZE, SIZE, &gpu),
+        &gpu,
+    );
+    hide_point(&mut dance, &gpu);
+    let mut resized = OffscreenTarget::new(SIZE + 2, SIZE, &gpu);
+    assert_close(render(&mut dance, &mut resized, &gpu), Vec3::ZERO);
+}
+
+#[test]
+fn camera_moves_the_points() {
+    let gpu = gpu();
+    let transformations = [Transformation {
+        center: vec2(5.0, 5.0),
+        scale: 1.0,
+        angle: 0.0,
+        color: vec4(1.0, 0.0, 0.0, 1.0),
+    }];
+    let mut dance = Dance::new(
+        &[Point {
+            pos: vec2(0.5, 0.5),
+        }],
+        &transformations,
+        OffscreenTarget::FORMAT,
+        &gpu,
+    );
+    dance.set_camera(Camera {
+        center: vec2(0.5, 0.5),
+        zoom: 4.0,
+    });
+    let mut target = OffscreenTarget::new(SIZE, SIZE, &gpu);
+    assert_eq!(render(&mut dance, &mut target, &gpu), vec3(1.0, 0.0, 0.0));
+}
@@ -0,0 +1,94 @@
+//! Checks the keyframes of the transformation generator, looped and not.
+
+use particle_dance::dance::{Transformation, transformations::TransformationGenerator};
+
+const SEED: u32 = 0x5eed;
+
+fn generator() -> TransformationGenerator {
+    TransformationGenerator::with_seed(TransformationGenerator::default_colors(4), SEED)
+}
+
+fn max_difference(a: &[Transformation], b: &[Transformation]) -> f32 {
+    assert_eq!(a.len(), b.len());
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| {
+            (a.center - b.center)
+                .abs()
+                .max_element()
+                .max((a.scale - b.scale).abs())
+                .max((a.angle - b.angle).abs())
+        })
+        .fold(0.0, f32::max)
+}
+
+#[test]
+fn looping_generator_repeats_after_its_period() {
+    let looped = generator().looping(5);
+    assert_eq!(looped.period(), Some(5));
+    for t in [0.0, 0.3, 2.5, 4.9] {
+        let first = looped.generate(t);
+        for offset in [-5.0, 5.0, 10.0, 50.0] {
+            let difference = max_difference(&first, &looped.generate(t + offset));
+            assert!(difference < 1e-3, "{t} + {offset}: {difference}");
+        }
+    }
+
+    let unlooped = generator().generate(0.3);
+    assert!(max_difference(&unlooped, &generator().generate(5.3)) > 1e-2);
+}
+
+#[test]
+fn looping_generator_is_continuous_across_the_wrap() {
+    let generator = generator().looping(3);
+    let steps = 300;
+    let frames = (0..=steps)
+        .map(|i| generator.generate(i as f32 * 3.0 / steps as f32))
+        .collect::<Vec<_>>();
+    let largest_step = frames
+        .windows(2)
+        .map(|pair| max_difference(&pair[0], &pair[1]))
+        .fold(0.0, f32::max);
+    let wrap_step = max_difference(&frames[steps], &frames[0]);
+    assert!(wrap_step < 1e-3, "{wrap_step}");
+
+    // the wrap moves no more than the steps around it, rather than jumping
+    let around_wrap = max_difference(&frames[steps - 1], &generator.generate(3.0 / steps as f32));
+    assert!(
+        around_wrap <= 2.0 * largest_step + 1e-4,
+        "{around_wrap} {largest_step}"
+    );
+}
+
+#[test]
+fn generator_is_continuous_at_negative_keyframes() {
+    let generator = generator();
+    for keyframe in [-1.0, -2.0] {
+        let below = generator.generate(keyframe - 1e-3);
+        let above = generator.generate(keyframe + 1e-3);
+        let difference = max_difference(&below, &above);
+        assert!(difference < 1e-2, "{keyframe}: {difference}");
+    }
+}
+
+#[test]
+fn looping_generator_matches_away_from_the_wrap() {
+    // keyframes 0 to 7 only, whose neighbors need no wrapping
+    let looped = generator().looping(8);
+    for t in [1.0, 2.5, 5.9] {
+        assert_eq!(
+            max_difference(&looped.generate(t), &generator().generate(t)),
+            0.0,
+            "{t}"
+        );
+    }
+}
+
+#[test]
+fn single_keyframe_loop_is_constant() {
+    let generator = generator().looping(1);
+    let first = generator.generate(0.0);
+    for t in [0.25, 0.5, 0.75, 7.1] {
+        assert!(max_difference(&first, &generator.generate(t)) < 1e-4);
+    }
+}
@@ -1,7 +1,75 @@
+#![cfg(target_arch = "wasm32")]
+
+use particle_dance::dance::clock::ClockHandle;
 use wasm_bindgen::prelude::wasm_bindgen;
 use web_sys::HtmlCanvasElement;
 
 #[wasm_bindgen]
 pub fn run(canvas: HtmlCanvasElement) {
-    particle_dance::run_web(canvas)
+    particle_dance::run_web(canvas, ClockHandle::default())
+}
+
+/// A dance whose animation the page controls: it can be paused, stepped, sped up, reversed and
+/// seeked while it runs.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Player {
+    clock: ClockHandle,
+}
+
+#[wasm_bindgen]
+impl Player {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn run(&self, canvas: HtmlCanvasElement) {
+        particle_dance::run_web(canvas, self.clock.clone())
+    }
+
+    /// The generator time shown.
+    pub fn time(&self) -> f32 {
+        self.clock.lock().time()
+    }
+
+    pub fn seek(&self, time: f32) {
+        if time.is_finite() {
+            self.clock.lock().seek(time);
+        }
+    }
+
+    /// The generator time per second, negative when playing backwards.
+    pub fn speed(&self) -> f32 {
+        self.clock.lock().speed()
+    }
+
+    #[wasm_bindgen(js_name = setSpeed)]
+    pub fn set_speed(&self, speed: f32) {
+        if speed.is_finite() {
+            self.clock.lock().set_speed(speed);
+        }
+    }
+
+    pub fn reverse(&self) {
+        self.clock.lock().reverse();
+    }
+
+    #[wasm_bindgen(js_name = isPaused)]
+    pub fn is_paused(&self) -> bool {
+        self.clock.lock().is_paused()
+    }
+
+    pub fn pause(&self) {
+        self.clock.lock().set_paused(true);
+    }
+
+    pub fn resume(&self) {
+        self.clock.lock().set_paused(false);
+    }
+
+    /// Pauses and moves by `n_frames` frames, backwards for a negative count.
+    pub fn step(&self, n_frames: i32) {
+        self.clock.lock().step(n_frames);
+    }
 }